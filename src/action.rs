@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+
+use crate::network::Activation;
+use crate::randomizer::RandomProvider;
+
+/// Decision of the network passed to the world.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    /// Index of the chosen option.
+    Discrete(usize),
+    /// Vector of values, e.g. forces or probabilities.
+    Continuous(Vec<f64>),
+}
+
+impl Action {
+    /// Converts the action into plain values: a discrete action becomes
+    /// a single element with its index.
+    pub fn to_values(&self) -> Vec<f64> {
+        match self {
+            Action::Discrete(index) => vec![*index as f64],
+            Action::Continuous(values) => values.clone(),
+        }
+    }
+
+    pub fn as_discrete(&self) -> Option<usize> {
+        match self {
+            Action::Discrete(index) => Some(*index),
+            Action::Continuous(_) => None,
+        }
+    }
+}
+
+/// Turns the outputs of the last layer into the [`Action`](enum.Action.html).
+///
+/// The head is stored in the network layout, so a deployed specimen
+/// acts the same way as during the training.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub enum OutputHead {
+    /// Outputs are passed as they are.
+    #[default]
+    Raw,
+    /// Outputs are turned into probabilities. Higher temperature makes
+    /// the distribution more uniform.
+    Softmax { temperature: f64 },
+    /// Index of the highest output.
+    Argmax,
+    /// Index drawn at random from the softmax distribution of the outputs.
+    Sample { temperature: f64 },
+    /// Each output is squashed with tanh and scaled to `[low[i], high[i]]`.
+    ///
+    /// Requires [`Activation::Identity`](../network/enum.Activation.html), so that tanh
+    /// is applied to the weighted sums. Outputs that were already squashed,
+    /// e.g. by sigmoid, would never reach the bounds.
+    Bounded { low: Vec<f64>, high: Vec<f64> },
+}
+
+impl OutputHead {
+    /// Turns the outputs into the action. Only the sampling head
+    /// draws from the randomizer.
+    pub fn apply(&self, outputs: &[f64], randomizer: &mut dyn RandomProvider) -> Action {
+        match self {
+            OutputHead::Raw => Action::Continuous(outputs.to_vec()),
+            OutputHead::Softmax { temperature } => {
+                Action::Continuous(softmax(outputs, *temperature))
+            }
+            OutputHead::Argmax => Action::Discrete(argmax(outputs)),
+            OutputHead::Sample { temperature } => {
+                let probabilities = softmax(outputs, *temperature);
+                let mut remaining = randomizer.get_uniform();
+                Action::Discrete(
+                    probabilities
+                        .iter()
+                        .position(|probability| {
+                            remaining -= probability;
+                            remaining < 0.0
+                        })
+                        .unwrap_or(probabilities.len() - 1),
+                )
+            }
+            OutputHead::Bounded { low, high } => Action::Continuous(
+                outputs
+                    .iter()
+                    .zip(low.iter().zip(high.iter()))
+                    .map(|(output, (low, high))| low + (output.tanh() + 1.0) / 2.0 * (high - low))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Checks that the head can be applied to the specified number of outputs
+    /// produced with the activation of the network.
+    pub(crate) fn validate(
+        &self,
+        number_of_outputs: usize,
+        activation: Activation,
+    ) -> Result<(), String> {
+        match self {
+            OutputHead::Softmax { temperature } | OutputHead::Sample { temperature }
+                if *temperature <= 0.0 =>
+            {
+                Err("Temperature must be positive".to_string())
+            }
+            OutputHead::Bounded { low, high }
+                if low.len() != number_of_outputs || high.len() != number_of_outputs =>
+            {
+                Err("Bounds must match the number of outputs".to_string())
+            }
+            OutputHead::Bounded { .. } if activation != Activation::Identity => {
+                Err("Bounded head requires the identity activation".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Normalises the values into probabilities, `exp(x / temperature)` each.
+pub fn softmax(values: &[f64], temperature: f64) -> Vec<f64> {
+    let maximum = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let exponents: Vec<f64> = values
+        .iter()
+        .map(|value| ((value - maximum) / temperature).exp())
+        .collect();
+    let total: f64 = exponents.iter().sum();
+    exponents.iter().map(|value| value / total).collect()
+}
+
+/// Returns the index of the highest value (the first one in case of a tie).
+pub fn argmax(values: &[f64]) -> usize {
+    values
+        .iter()
+        .enumerate()
+        .fold((0, f64::NEG_INFINITY), |best, (index, value)| {
+            if *value > best.1 {
+                (index, *value)
+            } else {
+                best
+            }
+        })
+        .0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::action::{argmax, softmax, Action, OutputHead};
+    use crate::network::Activation;
+    use crate::randomizer::{DefaultRandomizer, RandomProvider};
+
+    #[test]
+    fn heads() {
+        let mut randomizer = DefaultRandomizer::new();
+        let outputs = [0.1, 0.7, 0.2];
+        assert_eq!(
+            OutputHead::Argmax.apply(&outputs, &mut randomizer),
+            Action::Discrete(1)
+        );
+        assert_eq!(argmax(&[1.0, 3.0, 3.0]), 1);
+
+        let probabilities = softmax(&outputs, 1.0);
+        assert!(relative_eq!(probabilities.iter().sum::<f64>(), 1.0));
+        assert!(probabilities[1] > probabilities[2] && probabilities[2] > probabilities[0]);
+        let sharp = softmax(&outputs, 0.01);
+        assert!(relative_eq!(sharp[1], 1.0, epsilon = 1e-9));
+
+        let bounded = OutputHead::Bounded {
+            low: vec![-2.0, 0.0],
+            high: vec![2.0, 10.0],
+        };
+        assert_eq!(
+            bounded.apply(&[0.0, 100.0], &mut randomizer),
+            Action::Continuous(vec![0.0, 10.0])
+        );
+        assert!(bounded.validate(2, Activation::Identity).is_ok());
+        assert!(bounded.validate(3, Activation::Identity).is_err());
+        assert!(bounded.validate(2, Activation::Sigmoid).is_err());
+    }
+
+    #[test]
+    fn sampling_follows_probabilities() {
+        struct Constant(f64);
+        impl RandomProvider for Constant {
+            fn get_number(&mut self) -> f64 {
+                self.0
+            }
+        }
+
+        let head = OutputHead::Sample { temperature: 1.0 };
+        let outputs = [0.0, 2.0f64.ln()];
+        // Uniform numbers 0.125 and 0.625 fall into the probabilities 1/3 and 2/3
+        assert_eq!(
+            head.apply(&outputs, &mut Constant(-1.0)),
+            Action::Discrete(0)
+        );
+        assert_eq!(
+            head.apply(&outputs, &mut Constant(1.0)),
+            Action::Discrete(1)
+        );
+
+        let mut randomizer = DefaultRandomizer::new();
+        let mut counts = [0; 2];
+        for _ in 0..3000 {
+            counts[head.apply(&outputs, &mut randomizer).as_discrete().unwrap()] += 1;
+        }
+        // Expected ratio is 1:2
+        assert!(relative_eq!(
+            counts[1] as f64 / 3000.0,
+            2.0 / 3.0,
+            epsilon = 0.05
+        ));
+    }
+}
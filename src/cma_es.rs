@@ -0,0 +1,532 @@
+use std::marker::PhantomData;
+
+use rand_distr::{Distribution, StandardNormal};
+
+use crate::network::{NetworkBuilder, NetworkLayout};
+use crate::randomizer::RandomProvider;
+use crate::simulating_world::SimulatingWorld;
+use crate::simulation::{evaluate, Finish};
+
+const DEFAULT_INITIAL_SIGMA: f64 = 0.5;
+const DEFAULT_MAX_RESTARTS: usize = 9;
+const RESTART_POPULATION_FACTOR: usize = 2;
+const TOLERANCE_X: f64 = 1e-11;
+const TOLERANCE_FITNESS: f64 = 1e-12;
+const MAXIMUM_CONDITION: f64 = 1e14;
+
+/// Symmetric matrix stored row by row.
+struct Matrix {
+    size: usize,
+    data: Vec<f64>,
+}
+
+impl Matrix {
+    fn identity(size: usize) -> Matrix {
+        let mut data = vec![0.0; size * size];
+        (0..size).for_each(|i| data[i * size + i] = 1.0);
+        Matrix { size, data }
+    }
+
+    fn at(&self, row: usize, column: usize) -> f64 {
+        self.data[row * self.size + column]
+    }
+
+    fn at_mut(&mut self, row: usize, column: usize) -> &mut f64 {
+        &mut self.data[row * self.size + column]
+    }
+
+    /// Calculates eigenvalues and eigenvectors (stored as columns)
+    /// using the cyclic Jacobi method.
+    fn eigen(&self) -> (Vec<f64>, Matrix) {
+        const MAX_SWEEPS: usize = 64;
+        let n = self.size;
+        let mut a = Matrix {
+            size: n,
+            data: self.data.clone(),
+        };
+        let mut v = Matrix::identity(n);
+
+        for _ in 0..MAX_SWEEPS {
+            let off_diagonal: f64 = (0..n)
+                .flat_map(|p| (p + 1..n).map(move |q| (p, q)))
+                .map(|(p, q)| a.at(p, q).powi(2))
+                .sum();
+            if off_diagonal < f64::EPSILON * f64::EPSILON {
+                break;
+            }
+
+            for p in 0..n {
+                for q in p + 1..n {
+                    let apq = a.at(p, q);
+                    if apq.abs() < f64::MIN_POSITIVE {
+                        continue;
+                    }
+                    let theta = (a.at(q, q) - a.at(p, p)) / (2.0 * apq);
+                    let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                    let c = 1.0 / (t * t + 1.0).sqrt();
+                    let s = t * c;
+
+                    *a.at_mut(p, p) -= t * apq;
+                    *a.at_mut(q, q) += t * apq;
+                    *a.at_mut(p, q) = 0.0;
+                    *a.at_mut(q, p) = 0.0;
+                    for k in (0..n).filter(|k| *k != p && *k != q) {
+                        let (akp, akq) = (a.at(k, p), a.at(k, q));
+                        *a.at_mut(k, p) = c * akp - s * akq;
+                        *a.at_mut(p, k) = c * akp - s * akq;
+                        *a.at_mut(k, q) = s * akp + c * akq;
+                        *a.at_mut(q, k) = s * akp + c * akq;
+                    }
+                    for k in 0..n {
+                        let (vkp, vkq) = (v.at(k, p), v.at(k, q));
+                        *v.at_mut(k, p) = c * vkp - s * vkq;
+                        *v.at_mut(k, q) = s * vkp + c * vkq;
+                    }
+                }
+            }
+        }
+
+        ((0..n).map(|i| a.at(i, i)).collect(), v)
+    }
+}
+
+/// Internal state of a single CMA-ES run (between two restarts).
+struct CmaState {
+    lambda: usize,
+    weights: Vec<f64>,
+    mu_eff: f64,
+    cc: f64,
+    cs: f64,
+    c1: f64,
+    cmu: f64,
+    damps: f64,
+    chi_n: f64,
+    mean: Vec<f64>,
+    sigma: f64,
+    pc: Vec<f64>,
+    ps: Vec<f64>,
+    covariance: Matrix,
+    eigenvectors: Matrix,
+    eigenvalues_sqrt: Vec<f64>,
+    generation: usize,
+    best_history: Vec<f64>,
+}
+
+impl CmaState {
+    fn new(mean: Vec<f64>, sigma: f64, lambda: usize) -> CmaState {
+        let n = mean.len();
+        let nf = n as f64;
+        let mu = lambda / 2;
+        let raw_weights: Vec<f64> = (0..mu)
+            .map(|i| ((lambda as f64 + 1.0) / 2.0).ln() - (i as f64 + 1.0).ln())
+            .collect();
+        let sum: f64 = raw_weights.iter().sum();
+        let weights: Vec<f64> = raw_weights.iter().map(|w| w / sum).collect();
+        let mu_eff = 1.0 / weights.iter().map(|w| w * w).sum::<f64>();
+
+        let cc = (4.0 + mu_eff / nf) / (nf + 4.0 + 2.0 * mu_eff / nf);
+        let cs = (mu_eff + 2.0) / (nf + mu_eff + 5.0);
+        let c1 = 2.0 / ((nf + 1.3).powi(2) + mu_eff);
+        let cmu =
+            (1.0 - c1).min(2.0 * (mu_eff - 2.0 + 1.0 / mu_eff) / ((nf + 2.0).powi(2) + mu_eff));
+        let damps = 1.0 + 2.0 * (((mu_eff - 1.0) / (nf + 1.0)).sqrt() - 1.0).max(0.0) + cs;
+        let chi_n = nf.sqrt() * (1.0 - 1.0 / (4.0 * nf) + 1.0 / (21.0 * nf * nf));
+
+        CmaState {
+            lambda,
+            weights,
+            mu_eff,
+            cc,
+            cs,
+            c1,
+            cmu,
+            damps,
+            chi_n,
+            mean,
+            sigma,
+            pc: vec![0.0; n],
+            ps: vec![0.0; n],
+            covariance: Matrix::identity(n),
+            eigenvectors: Matrix::identity(n),
+            eigenvalues_sqrt: vec![1.0; n],
+            generation: 0,
+            best_history: vec![],
+        }
+    }
+
+    fn dimension(&self) -> usize {
+        self.mean.len()
+    }
+
+    /// Returns the sampled steps `y = B * D * z`.
+    fn sample_step(&self, rng: &mut rand::rngs::ThreadRng) -> Vec<f64> {
+        let n = self.dimension();
+        let scaled: Vec<f64> = (0..n)
+            .map(|i| {
+                let z: f64 = StandardNormal.sample(rng);
+                self.eigenvalues_sqrt[i] * z
+            })
+            .collect();
+        (0..n)
+            .map(|row| {
+                (0..n)
+                    .map(|column| self.eigenvectors.at(row, column) * scaled[column])
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Calculates `C^(-1/2) * y`.
+    fn whiten(&self, y: &[f64]) -> Vec<f64> {
+        let n = self.dimension();
+        let projected: Vec<f64> = (0..n)
+            .map(|column| {
+                (0..n)
+                    .map(|row| self.eigenvectors.at(row, column) * y[row])
+                    .sum::<f64>()
+                    / self.eigenvalues_sqrt[column]
+            })
+            .collect();
+        (0..n)
+            .map(|row| {
+                (0..n)
+                    .map(|column| self.eigenvectors.at(row, column) * projected[column])
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Updates the distribution using steps sorted from the best to the worst.
+    fn update(&mut self, sorted_steps: &[Vec<f64>], best_fitness: f64) {
+        let n = self.dimension();
+        self.generation += 1;
+        self.best_history.push(best_fitness);
+
+        let y_w: Vec<f64> = (0..n)
+            .map(|i| {
+                self.weights
+                    .iter()
+                    .zip(sorted_steps.iter())
+                    .map(|(w, y)| w * y[i])
+                    .sum()
+            })
+            .collect();
+        let sigma = self.sigma;
+        self.mean
+            .iter_mut()
+            .zip(y_w.iter())
+            .for_each(|(m, y)| *m += sigma * y);
+
+        let whitened = self.whiten(&y_w);
+        let cs = self.cs;
+        let cs_factor = (cs * (2.0 - cs) * self.mu_eff).sqrt();
+        self.ps
+            .iter_mut()
+            .zip(whitened.iter())
+            .for_each(|(ps, w)| *ps = (1.0 - cs) * *ps + cs_factor * w);
+        let ps_norm = self.ps.iter().map(|x| x * x).sum::<f64>().sqrt();
+
+        let hsig =
+            ps_norm / (1.0 - (1.0 - self.cs).powi(2 * self.generation as i32)).sqrt() / self.chi_n
+                < 1.4 + 2.0 / (n as f64 + 1.0);
+        let hsig = if hsig { 1.0 } else { 0.0 };
+
+        let cc = self.cc;
+        let cc_factor = (cc * (2.0 - cc) * self.mu_eff).sqrt();
+        self.pc
+            .iter_mut()
+            .zip(y_w.iter())
+            .for_each(|(pc, y)| *pc = (1.0 - cc) * *pc + hsig * cc_factor * y);
+
+        let old_weight =
+            1.0 - self.c1 - self.cmu + (1.0 - hsig) * self.c1 * self.cc * (2.0 - self.cc);
+        for row in 0..n {
+            for column in 0..=row {
+                let rank_mu: f64 = self
+                    .weights
+                    .iter()
+                    .zip(sorted_steps.iter())
+                    .map(|(w, y)| w * y[row] * y[column])
+                    .sum();
+                let value = old_weight * self.covariance.at(row, column)
+                    + self.c1 * self.pc[row] * self.pc[column]
+                    + self.cmu * rank_mu;
+                *self.covariance.at_mut(row, column) = value;
+                *self.covariance.at_mut(column, row) = value;
+            }
+        }
+
+        self.sigma *= ((self.cs / self.damps) * (ps_norm / self.chi_n - 1.0)).exp();
+
+        let (eigenvalues, eigenvectors) = self.covariance.eigen();
+        self.eigenvalues_sqrt = eigenvalues
+            .iter()
+            .map(|e| e.max(f64::MIN_POSITIVE).sqrt())
+            .collect();
+        self.eigenvectors = eigenvectors;
+    }
+
+    /// Checks whether the run got stuck and should be restarted.
+    fn should_restart(&self, initial_sigma: f64) -> bool {
+        let max_deviation = self.eigenvalues_sqrt.iter().cloned().fold(0.0, f64::max);
+        let min_deviation = self
+            .eigenvalues_sqrt
+            .iter()
+            .cloned()
+            .fold(f64::INFINITY, f64::min);
+        if self.sigma * max_deviation < TOLERANCE_X * initial_sigma
+            || (max_deviation / min_deviation).powi(2) > MAXIMUM_CONDITION
+            || !self.sigma.is_finite()
+        {
+            return true;
+        }
+
+        let history_length =
+            10 + (30.0 * self.dimension() as f64 / self.lambda as f64).ceil() as usize;
+        if self.best_history.len() < history_length {
+            return false;
+        }
+        let recent = &self.best_history[self.best_history.len() - history_length..];
+        let max = recent.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let min = recent.iter().cloned().fold(f64::INFINITY, f64::min);
+        max - min < TOLERANCE_FITNESS
+    }
+}
+
+/// Trainer that uses the Covariance Matrix Adaptation Evolution Strategy.
+///
+/// All weights of the network are treated as a single search vector.
+/// Each candidate is evaluated by playing a single episode in the
+/// `SimulatingWorld`. When the search stagnates, it is restarted from
+/// a fresh random network with doubled population size (IPOP-CMA-ES).
+pub struct CmaEs<'a, T: SimulatingWorld> {
+    template: NetworkLayout,
+    neurons_per_layer: Vec<usize>,
+    randomizer: &'a mut dyn RandomProvider,
+    initial_sigma: f64,
+    max_restarts: usize,
+    state: CmaState,
+    best: Vec<crate::Specimen>,
+    restarts: usize,
+    counter: usize,
+    world: PhantomData<T>,
+}
+
+impl<'a, T: SimulatingWorld> CmaEs<'a, T> {
+    /// Creates new CMA-ES trainer.
+    ///
+    /// `population_size` defaults to `4 + 3 * ln(n)` where `n` is the number of
+    /// weights, `initial_sigma` is the initial step size used after each restart.
+    pub fn new(
+        neurons_per_layer: &[usize],
+        randomizer: &'a mut dyn RandomProvider,
+        population_size: Option<usize>,
+        initial_sigma: Option<f64>,
+    ) -> Result<CmaEs<'a, T>, String> {
+        let template = NetworkBuilder::new()
+            .with_neurons_per_layer(neurons_per_layer)
+            .with_randomizer(randomizer)
+            .build()
+            .layout;
+        let dimension = template.get_number_of_weights();
+        if dimension == 0 {
+            return Err("Network has no weights to optimize".to_string());
+        }
+
+        let lambda =
+            population_size.unwrap_or_else(|| 4 + (3.0 * (dimension as f64).ln()).floor() as usize);
+        if lambda < crate::MINIMUM_POPULATION_SIZE {
+            return Err(format!(
+                "Population too small, minimum size={}",
+                crate::MINIMUM_POPULATION_SIZE
+            ));
+        }
+
+        let initial_sigma = initial_sigma.unwrap_or(DEFAULT_INITIAL_SIGMA);
+        if initial_sigma <= 0.0 {
+            return Err("Initial step size must be positive".to_string());
+        }
+
+        Ok(CmaEs {
+            state: CmaState::new(template.get_weights(), initial_sigma, lambda),
+            template,
+            neurons_per_layer: neurons_per_layer.to_vec(),
+            randomizer,
+            initial_sigma,
+            max_restarts: DEFAULT_MAX_RESTARTS,
+            best: vec![],
+            restarts: 0,
+            counter: 0,
+            world: PhantomData,
+        })
+    }
+
+    /// Sets the maximum number of IPOP restarts. Once exhausted, the search
+    /// continues with the last distribution.
+    pub fn with_max_restarts(mut self, max_restarts: usize) -> Self {
+        self.max_restarts = max_restarts;
+        self
+    }
+
+    /// Runs the learning round.
+    ///
+    /// Returns two best specimen found so far, across all restarts.
+    pub fn run(&mut self, finish: Finish) -> Result<[crate::Specimen; 2], String> {
+        match finish {
+            Finish::Occurences(count) => {
+                for _ in 0..count {
+                    self.generation();
+                }
+                if self.best.len() < 2 {
+                    return Err(
+                        "Simulation finished, but no best specimen could be selected".to_string(),
+                    );
+                }
+                Ok([self.best[0].clone(), self.best[1].clone()])
+            }
+            _ => Err("Simulation end trigger not supported yet".to_string()),
+        }
+    }
+
+    /// Returns number of generations used in recent learning session.
+    pub fn get_number_of_iterations(&self) -> usize {
+        self.counter
+    }
+
+    /// Returns number of IPOP restarts performed so far.
+    pub fn get_number_of_restarts(&self) -> usize {
+        self.restarts
+    }
+
+    /// Returns the current population size.
+    pub fn get_population_size(&self) -> usize {
+        self.state.lambda
+    }
+
+    /// Returns the current step size.
+    pub fn get_sigma(&self) -> f64 {
+        self.state.sigma
+    }
+
+    fn remember(&mut self, specimen: crate::Specimen) {
+        self.best.push(specimen);
+        self.best.sort_by(|a, b| {
+            b.fitness
+                .partial_cmp(&a.fitness)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        self.best.truncate(2);
+    }
+
+    fn generation(&mut self) {
+        self.counter += 1;
+        let mut rng = rand::thread_rng();
+        let mut candidates: Vec<(Vec<f64>, crate::Specimen)> = (0..self.state.lambda)
+            .map(|_| {
+                let step = self.state.sample_step(&mut rng);
+                let weights: Vec<f64> = self
+                    .state
+                    .mean
+                    .iter()
+                    .zip(step.iter())
+                    .map(|(m, y)| m + self.state.sigma * y)
+                    .collect();
+                self.template.set_weights(&weights);
+                let fitness = evaluate::<T>(&self.template, self.randomizer).fitness;
+                (
+                    step,
+                    crate::Specimen {
+                        brain: self.template.clone(),
+                        fitness,
+                    },
+                )
+            })
+            .collect();
+        candidates.sort_by(|a, b| {
+            b.1.fitness
+                .partial_cmp(&a.1.fitness)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        candidates
+            .iter()
+            .take(2)
+            .for_each(|(_, specimen)| self.remember(specimen.clone()));
+
+        let best_fitness = candidates[0].1.fitness;
+        let sorted_steps: Vec<Vec<f64>> = candidates.into_iter().map(|(step, _)| step).collect();
+        self.state.update(&sorted_steps, best_fitness);
+
+        if self.restarts < self.max_restarts && self.state.should_restart(self.initial_sigma) {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.restarts += 1;
+        let fresh = NetworkBuilder::new()
+            .with_neurons_per_layer(&self.neurons_per_layer)
+            .with_randomizer(self.randomizer)
+            .build()
+            .layout;
+        self.state = CmaState::new(
+            fresh.get_weights(),
+            self.initial_sigma,
+            self.state.lambda * RESTART_POPULATION_FACTOR,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cma_es::{CmaEs, Matrix};
+    use crate::randomizer::DefaultRandomizer;
+    use crate::simulating_world::{assert_reaches_target, TargetWorld};
+    use crate::simulation::Finish;
+
+    #[test]
+    fn eigen_decomposition() {
+        let matrix = Matrix {
+            size: 3,
+            data: vec![4.0, 1.0, 2.0, 1.0, 3.0, 0.5, 2.0, 0.5, 5.0],
+        };
+        let (values, vectors) = matrix.eigen();
+        for (k, value) in values.iter().enumerate() {
+            for row in 0..3 {
+                let product: f64 = (0..3)
+                    .map(|column| matrix.at(row, column) * vectors.at(column, k))
+                    .sum();
+                assert!(relative_eq!(
+                    product,
+                    value * vectors.at(row, k),
+                    epsilon = 1e-9
+                ));
+            }
+        }
+        assert!(relative_eq!(
+            values.iter().sum::<f64>(),
+            12.0,
+            epsilon = 1e-9
+        ));
+    }
+
+    #[test]
+    fn converges_towards_target() {
+        let mut randomizer = DefaultRandomizer::new();
+        let mut trainer =
+            CmaEs::<TargetWorld>::new(&[2, 2, 1], &mut randomizer, None, None).unwrap();
+        let best = trainer.run(Finish::Occurences(60)).unwrap();
+        assert_eq!(trainer.get_number_of_iterations(), 60);
+        assert_reaches_target(&best, 1e-4);
+    }
+
+    #[test]
+    fn restart_doubles_population() {
+        let mut randomizer = DefaultRandomizer::new();
+        let mut trainer = CmaEs::<TargetWorld>::new(&[2, 1], &mut randomizer, Some(4), None)
+            .unwrap()
+            .with_max_restarts(1);
+        trainer.restart();
+        assert_eq!(trainer.get_number_of_restarts(), 1);
+        assert_eq!(trainer.get_population_size(), 8);
+    }
+}
@@ -0,0 +1,447 @@
+use std::fmt::Write;
+
+use crate::network::{Activation, DenseLayout};
+
+/// Language of the generated code.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Language {
+    /// Self-contained Rust module, exposing `INPUTS`, `OUTPUTS` and `forward`.
+    Rust,
+    /// C header and source, exposing `NAME_INPUTS`, `NAME_OUTPUTS` and `name_forward`.
+    /// Needs to be linked with the math library.
+    C,
+}
+
+/// Source code of the network, without dependencies on `easyneural`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GeneratedCode {
+    /// Header to be saved as `name.h`, generated for C only.
+    pub header: Option<String>,
+    pub source: String,
+}
+
+/// Weights of a fired layer. Disconnected inputs of pruned neurons have zero weights.
+struct LayerCode {
+    index: usize,
+    /// Source layers with the position of their first value among the inputs.
+    sources: Vec<(usize, usize)>,
+    number_of_inputs: usize,
+    weights: Vec<Vec<f64>>,
+}
+
+/// Generates the code firing the brain of the specimen, with the weights
+/// stored as constant arrays. Results match the ones of
+/// [`Exercise::get_output`](../training_ground/struct.Exercise.html#method.get_output),
+/// only the networks consisting of dense layers are supported.
+///
+/// The `name` needs to be a valid identifier, it prefixes the C symbols.
+pub fn generate(
+    specimen: &crate::Specimen,
+    language: Language,
+    name: &str,
+) -> Result<GeneratedCode, String> {
+    let valid_name = name
+        .chars()
+        .enumerate()
+        .all(|(i, c)| c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit()));
+    if name.is_empty() || !valid_name {
+        return Err(format!("'{}' is not a valid identifier", name));
+    }
+    let layout = &specimen.brain;
+    let DenseLayout { sizes, order } = layout.get_dense_layout("generated")?;
+    let layers: Vec<LayerCode> = order
+        .into_iter()
+        .skip(1)
+        .map(|layer_index| {
+            let mut offset = 0;
+            let sources: Vec<(usize, usize)> = layout
+                .get_layer_sources(layer_index)
+                .into_iter()
+                .map(|source| {
+                    offset += sizes[source] + 1;
+                    (source, offset - sizes[source] - 1)
+                })
+                .collect();
+            let weights = layout.layers[layer_index]
+                .iter()
+                .map(|neuron_id| &layout.neurons[*neuron_id])
+                .filter(|neuron| !neuron.bias)
+                .map(|neuron| {
+                    let mut row = vec![0.0; offset];
+                    neuron
+                        .inputs
+                        .iter()
+                        .enumerate()
+                        .for_each(|(j, weight)| row[neuron.get_input_position(j)] = *weight);
+                    row
+                })
+                .collect();
+            LayerCode {
+                index: layer_index,
+                sources,
+                number_of_inputs: offset,
+                weights,
+            }
+        })
+        .collect();
+
+    let last = sizes.len() - 1;
+    Ok(match language {
+        Language::Rust => GeneratedCode {
+            header: None,
+            source: generate_rust(&layers, &sizes, last, layout.activation),
+        },
+        Language::C => GeneratedCode {
+            header: Some(generate_c_header(&sizes, last, name)),
+            source: generate_c_source(&layers, &sizes, last, layout.activation, name),
+        },
+    })
+}
+
+fn generate_rust(
+    layers: &[LayerCode],
+    sizes: &[usize],
+    last: usize,
+    activation: Activation,
+) -> String {
+    let mut code = String::new();
+    writeln!(code, "//! Network generated by easyneural.\n").unwrap();
+    writeln!(code, "pub const INPUTS: usize = {};", sizes[0]).unwrap();
+    writeln!(code, "pub const OUTPUTS: usize = {};\n", sizes[last]).unwrap();
+    for layer in layers {
+        writeln!(
+            code,
+            "const WEIGHTS_{}: [[f64; {}]; {}] = [",
+            layer.index,
+            layer.number_of_inputs,
+            layer.weights.len()
+        )
+        .unwrap();
+        for row in &layer.weights {
+            writeln!(code, "    [{}],", format_row(row)).unwrap();
+        }
+        writeln!(code, "];\n").unwrap();
+    }
+    let body = match activation {
+        Activation::Sigmoid => "1.0 / (1.0 + (-x).exp())",
+        Activation::Tanh => "x.tanh()",
+        Activation::Relu => "x.max(0.0)",
+        Activation::Identity => "x",
+    };
+    writeln!(code, "fn activate(x: f64) -> f64 {{\n    {}\n}}\n", body).unwrap();
+
+    writeln!(
+        code,
+        "pub fn forward(inputs: &[f64; INPUTS]) -> [f64; OUTPUTS] {{"
+    )
+    .unwrap();
+    writeln!(code, "    let mut layer_0 = [1.0; INPUTS + 1];").unwrap();
+    writeln!(code, "    layer_0[..INPUTS].copy_from_slice(inputs);").unwrap();
+    for layer in layers {
+        let index = layer.index;
+        writeln!(
+            code,
+            "    let mut inputs_{} = [0.0; {}];",
+            index, layer.number_of_inputs
+        )
+        .unwrap();
+        for (source, offset) in &layer.sources {
+            writeln!(
+                code,
+                "    inputs_{}[{}..{}].copy_from_slice(&layer_{});",
+                index,
+                offset,
+                offset + sizes[*source] + 1,
+                source
+            )
+            .unwrap();
+        }
+        let size = if index == last {
+            sizes[index]
+        } else {
+            sizes[index] + 1
+        };
+        writeln!(code, "    let mut layer_{} = [1.0; {}];", index, size).unwrap();
+        writeln!(
+            code,
+            "    for (value, weights) in layer_{}.iter_mut().zip(WEIGHTS_{}.iter()) {{",
+            index, index
+        )
+        .unwrap();
+        writeln!(
+            code,
+            "        let total = weights\n            .iter()\n            .zip(inputs_{}.iter())\n            .fold(0.0, |total, (weight, input)| total + weight * input);",
+            index
+        )
+        .unwrap();
+        writeln!(code, "        *value = activate(total);\n    }}").unwrap();
+    }
+    writeln!(code, "    layer_{}\n}}", last).unwrap();
+    code
+}
+
+fn generate_c_header(sizes: &[usize], last: usize, name: &str) -> String {
+    let upper = name.to_uppercase();
+    let mut code = String::new();
+    writeln!(code, "/* Network generated by easyneural. */").unwrap();
+    writeln!(code, "#ifndef {}_H\n#define {}_H\n", upper, upper).unwrap();
+    writeln!(code, "#define {}_INPUTS {}", upper, sizes[0]).unwrap();
+    writeln!(code, "#define {}_OUTPUTS {}\n", upper, sizes[last]).unwrap();
+    writeln!(
+        code,
+        "void {}_forward(const double *inputs, double *outputs);\n",
+        name
+    )
+    .unwrap();
+    writeln!(code, "#endif").unwrap();
+    code
+}
+
+fn generate_c_source(
+    layers: &[LayerCode],
+    sizes: &[usize],
+    last: usize,
+    activation: Activation,
+    name: &str,
+) -> String {
+    let mut code = String::new();
+    writeln!(code, "/* Network generated by easyneural. */").unwrap();
+    writeln!(
+        code,
+        "#include <math.h>\n#include <string.h>\n#include \"{}.h\"\n",
+        name
+    )
+    .unwrap();
+    for layer in layers {
+        writeln!(
+            code,
+            "static const double weights_{}[{}][{}] = {{",
+            layer.index,
+            layer.weights.len(),
+            layer.number_of_inputs
+        )
+        .unwrap();
+        for row in &layer.weights {
+            writeln!(code, "    {{{}}},", format_row(row)).unwrap();
+        }
+        writeln!(code, "}};\n").unwrap();
+    }
+    let body = match activation {
+        Activation::Sigmoid => "1.0 / (1.0 + exp(-x))",
+        Activation::Tanh => "tanh(x)",
+        Activation::Relu => "x > 0.0 ? x : 0.0",
+        Activation::Identity => "x",
+    };
+    writeln!(
+        code,
+        "static double activate(double x)\n{{\n    return {};\n}}\n",
+        body
+    )
+    .unwrap();
+
+    writeln!(
+        code,
+        "void {}_forward(const double *inputs, double *outputs)\n{{",
+        name
+    )
+    .unwrap();
+    writeln!(code, "    double layer_0[{}];", sizes[0] + 1).unwrap();
+    for layer in layers.iter().filter(|layer| layer.index != last) {
+        writeln!(
+            code,
+            "    double layer_{}[{}];",
+            layer.index,
+            sizes[layer.index] + 1
+        )
+        .unwrap();
+    }
+    for layer in layers {
+        writeln!(
+            code,
+            "    double inputs_{}[{}];",
+            layer.index, layer.number_of_inputs
+        )
+        .unwrap();
+    }
+    writeln!(code, "    int i, j;\n").unwrap();
+    writeln!(
+        code,
+        "    memcpy(layer_0, inputs, {} * sizeof(double));",
+        sizes[0]
+    )
+    .unwrap();
+    writeln!(code, "    layer_0[{}] = 1.0;", sizes[0]).unwrap();
+    for layer in layers {
+        let index = layer.index;
+        for (source, offset) in &layer.sources {
+            writeln!(
+                code,
+                "    memcpy(inputs_{} + {}, layer_{}, {} * sizeof(double));",
+                index,
+                offset,
+                source,
+                sizes[*source] + 1
+            )
+            .unwrap();
+        }
+        let target = if index == last {
+            "outputs".to_string()
+        } else {
+            format!("layer_{}", index)
+        };
+        writeln!(code, "    for (i = 0; i < {}; i++) {{", sizes[index]).unwrap();
+        writeln!(code, "        double total = 0.0;").unwrap();
+        writeln!(
+            code,
+            "        for (j = 0; j < {}; j++) {{\n            total += weights_{}[i][j] * inputs_{}[j];\n        }}",
+            layer.number_of_inputs, index, index
+        )
+        .unwrap();
+        writeln!(code, "        {}[i] = activate(total);\n    }}", target).unwrap();
+        if index != last {
+            writeln!(code, "    {}[{}] = 1.0;", target, sizes[index]).unwrap();
+        }
+    }
+    writeln!(code, "}}").unwrap();
+    code
+}
+
+/// Formats the weights so that they are read back without any loss of precision.
+fn format_row(row: &[f64]) -> String {
+    row.iter()
+        .map(|weight| format!("{:?}", weight))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::process::Command;
+
+    use crate::codegen::{generate, Language};
+    use crate::network::{build_exporter_fixture, Activation};
+    use crate::randomizer::{DefaultRandomizer, RandomProvider};
+    use crate::training_ground::Exercise;
+
+    fn run(command: &mut Command) -> Vec<f64> {
+        let output = command.output().unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .split_whitespace()
+            .map(|value| value.parse().unwrap())
+            .collect()
+    }
+
+    fn compile_rust(directory: &Path, source: &str, inputs: &[Vec<f64>]) -> Vec<f64> {
+        std::fs::write(directory.join("network.rs"), source).unwrap();
+        let calls: String = inputs
+            .iter()
+            .map(|input| {
+                format!(
+                    "    for value in network::forward(&{:?}).iter() {{ println!(\"{{:?}}\", value); }}\n",
+                    input
+                )
+            })
+            .collect();
+        std::fs::write(
+            directory.join("main.rs"),
+            format!("mod network;\n\nfn main() {{\n{}}}\n", calls),
+        )
+        .unwrap();
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        let binary = directory.join("network_rust");
+        assert!(Command::new(rustc)
+            .arg(directory.join("main.rs"))
+            .arg("-o")
+            .arg(&binary)
+            .status()
+            .unwrap()
+            .success());
+        run(&mut Command::new(binary))
+    }
+
+    fn compile_c(directory: &Path, header: &str, source: &str, inputs: &[Vec<f64>]) -> Vec<f64> {
+        std::fs::write(directory.join("brain.h"), header).unwrap();
+        std::fs::write(directory.join("brain.c"), source).unwrap();
+        let calls: String = inputs
+            .iter()
+            .map(|input| {
+                let values: Vec<String> = input.iter().map(|x| format!("{:?}", x)).collect();
+                format!(
+                    "    {{\n        const double inputs[] = {{{}}};\n        brain_forward(inputs, outputs);\n        for (i = 0; i < BRAIN_OUTPUTS; i++) printf(\"%.17g\\n\", outputs[i]);\n    }}\n",
+                    values.join(", ")
+                )
+            })
+            .collect();
+        std::fs::write(
+            directory.join("main.c"),
+            format!(
+                "#include <stdio.h>\n#include \"brain.h\"\n\nint main(void)\n{{\n    double outputs[BRAIN_OUTPUTS];\n    int i;\n{}    return 0;\n}}\n",
+                calls
+            ),
+        )
+        .unwrap();
+        let binary = directory.join("network_c");
+        assert!(Command::new("cc")
+            .arg(directory.join("main.c"))
+            .arg(directory.join("brain.c"))
+            .arg("-lm")
+            .arg("-o")
+            .arg(&binary)
+            .status()
+            .unwrap()
+            .success());
+        run(&mut Command::new(binary))
+    }
+
+    #[test]
+    fn generated_code_matches_exercise() {
+        let directory =
+            std::env::temp_dir().join(format!("easyneural_codegen_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mut randomizer = DefaultRandomizer::new();
+        // The C part needs a C compiler, it is skipped on machines without one
+        let has_c_compiler = Command::new("cc").arg("--version").output().is_ok();
+
+        for activation in [Activation::Sigmoid, Activation::Tanh, Activation::Relu] {
+            let specimen = build_exporter_fixture(activation, &mut randomizer);
+
+            let inputs: Vec<Vec<f64>> = (0..5)
+                .map(|_| (0..3).map(|_| randomizer.get_number()).collect())
+                .collect();
+            let exercise = Exercise::new(&specimen);
+            let expected: Vec<f64> = inputs
+                .iter()
+                .flat_map(|input| exercise.get_output(input))
+                .collect();
+
+            let rust = generate(&specimen, Language::Rust, "brain").unwrap();
+            assert!(rust.header.is_none());
+            let outputs = compile_rust(&directory, &rust.source, &inputs);
+            assert_eq!(outputs.len(), expected.len());
+            outputs
+                .iter()
+                .zip(expected.iter())
+                .for_each(|(a, e)| assert!(relative_eq!(a, e, epsilon = 1e-12)));
+
+            if has_c_compiler {
+                let c = generate(&specimen, Language::C, "brain").unwrap();
+                let outputs = compile_c(&directory, &c.header.unwrap(), &c.source, &inputs);
+                assert_eq!(outputs.len(), expected.len());
+                outputs
+                    .iter()
+                    .zip(expected.iter())
+                    .for_each(|(a, e)| assert!(relative_eq!(a, e, epsilon = 1e-12)));
+            }
+
+            assert!(generate(&specimen, Language::C, "9lives").is_err());
+        }
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,455 @@
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::randomizer::{shuffle, RandomProvider};
+use crate::simulating_world::SimulatingWorld;
+use crate::simulation::SimulationStatus;
+use crate::specimen::SpecimenStatus;
+use crate::supervised::Sample;
+use crate::training_ground::Exercise;
+
+/// Method used to bring the values of each column to a common scale.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Normalization {
+    /// Maps the values to range `[0.0, 1.0]`.
+    MinMax,
+    /// Shifts the values to zero mean and unit standard deviation.
+    ZScore,
+}
+
+/// Per-column normalisation fitted on a dataset.
+///
+/// Each value is transformed as `(x - offset) / scale`. Keep the scaler
+/// together with the trained network, so that exactly the same preprocessing
+/// is applied at inference.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Scaler {
+    method: Normalization,
+    offsets: Vec<f64>,
+    scales: Vec<f64>,
+}
+
+impl Scaler {
+    /// Calculates the normalisation parameters of each column.
+    ///
+    /// All rows must have the same number of columns.
+    pub fn fit<'a>(
+        method: Normalization,
+        rows: impl Iterator<Item = &'a [f64]>,
+    ) -> Result<Scaler, String> {
+        let rows: Vec<&[f64]> = rows.collect();
+        check_widths(rows.iter().copied(), "Row")?;
+        Ok(Scaler::fit_rows(method, &rows))
+    }
+
+    fn fit_rows(method: Normalization, rows: &[&[f64]]) -> Scaler {
+        let columns = rows.first().map_or(0, |row| row.len());
+        let count = rows.len().max(1) as f64;
+        let (offsets, scales) = (0..columns)
+            .map(|column| {
+                let values = rows.iter().map(|row| row[column]);
+                let (offset, scale) = match method {
+                    Normalization::MinMax => {
+                        let min = values.clone().fold(f64::INFINITY, f64::min);
+                        let max = values.fold(f64::NEG_INFINITY, f64::max);
+                        (min, max - min)
+                    }
+                    Normalization::ZScore => {
+                        let mean = values.clone().sum::<f64>() / count;
+                        let variance = values.map(|x| (x - mean).powi(2)).sum::<f64>() / count;
+                        (mean, variance.sqrt())
+                    }
+                };
+                // Constant columns are only shifted
+                (offset, if scale > f64::EPSILON { scale } else { 1.0 })
+            })
+            .unzip();
+        Scaler {
+            method,
+            offsets,
+            scales,
+        }
+    }
+
+    pub fn get_method(&self) -> Normalization {
+        self.method
+    }
+
+    /// Normalises the raw values.
+    pub fn transform(&self, values: &[f64]) -> Vec<f64> {
+        values
+            .iter()
+            .zip(self.offsets.iter().zip(self.scales.iter()))
+            .map(|(x, (offset, scale))| (x - offset) / scale)
+            .collect()
+    }
+
+    /// Brings the normalised values back to the original scale.
+    pub fn inverse_transform(&self, values: &[f64]) -> Vec<f64> {
+        values
+            .iter()
+            .zip(self.offsets.iter().zip(self.scales.iter()))
+            .map(|(x, (offset, scale))| x * scale + offset)
+            .collect()
+    }
+}
+
+/// Checks that all rows have the same number of values as the first one.
+fn check_widths<'a>(rows: impl Iterator<Item = &'a [f64]>, name: &str) -> Result<(), String> {
+    let mut width = None;
+    for (index, row) in rows.enumerate() {
+        let expected = *width.get_or_insert(row.len());
+        if row.len() != expected {
+            return Err(format!(
+                "{} {} has {} values instead of {}",
+                name,
+                index + 1,
+                row.len(),
+                expected
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Collection of labelled samples, e.g. loaded from a CSV file.
+#[derive(Clone, Debug, Default)]
+pub struct Dataset {
+    samples: Vec<Sample>,
+}
+
+impl Dataset {
+    /// Creates the dataset from samples whose inputs, as well as targets,
+    /// all have the same length.
+    pub fn new(samples: Vec<Sample>) -> Result<Dataset, String> {
+        check_widths(
+            samples.iter().map(|sample| &sample.input[..]),
+            "Input of sample",
+        )?;
+        check_widths(
+            samples.iter().map(|sample| &sample.target[..]),
+            "Target of sample",
+        )?;
+        Ok(Dataset { samples })
+    }
+
+    /// Loads numeric, comma separated values.
+    ///
+    /// `input_columns` and `target_columns` are zero-based column indices.
+    /// Set `has_header` to skip the first line. Empty lines are ignored.
+    pub fn from_csv<R: Read>(
+        reader: R,
+        input_columns: &[usize],
+        target_columns: &[usize],
+        has_header: bool,
+    ) -> Result<Dataset, String> {
+        let mut samples = vec![];
+        for (line_number, line) in BufReader::new(reader)
+            .lines()
+            .enumerate()
+            .skip(if has_header { 1 } else { 0 })
+        {
+            let line = line.map_err(|e| e.to_string())?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let values = line
+                .split(',')
+                .map(|value| value.trim().parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|e| format!("Line {}: {}", line_number + 1, e))?;
+            let pick = |columns: &[usize]| {
+                columns
+                    .iter()
+                    .map(|column| {
+                        values.get(*column).copied().ok_or_else(|| {
+                            format!("Line {}: no column {}", line_number + 1, column)
+                        })
+                    })
+                    .collect::<Result<Vec<f64>, String>>()
+            };
+            samples.push(Sample {
+                input: pick(input_columns)?,
+                target: pick(target_columns)?,
+            });
+        }
+        Ok(Dataset { samples })
+    }
+
+    /// Loads numeric CSV file, see [`from_csv`](#method.from_csv).
+    pub fn from_csv_file<P: AsRef<Path>>(
+        path: P,
+        input_columns: &[usize],
+        target_columns: &[usize],
+        has_header: bool,
+    ) -> Result<Dataset, String> {
+        let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+        Dataset::from_csv(file, input_columns, target_columns, has_header)
+    }
+
+    pub fn get_samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Fits the scaler on the inputs of all samples.
+    pub fn fit_input_scaler(&self, method: Normalization) -> Scaler {
+        let rows: Vec<&[f64]> = self
+            .samples
+            .iter()
+            .map(|sample| &sample.input[..])
+            .collect();
+        Scaler::fit_rows(method, &rows)
+    }
+
+    /// Fits the scaler on the targets of all samples.
+    pub fn fit_target_scaler(&self, method: Normalization) -> Scaler {
+        let rows: Vec<&[f64]> = self
+            .samples
+            .iter()
+            .map(|sample| &sample.target[..])
+            .collect();
+        Scaler::fit_rows(method, &rows)
+    }
+
+    /// Returns a copy of the dataset with scalers applied to inputs and/or targets.
+    pub fn scale(&self, input_scaler: Option<&Scaler>, target_scaler: Option<&Scaler>) -> Dataset {
+        Dataset {
+            samples: self
+                .samples
+                .iter()
+                .map(|sample| Sample {
+                    input: input_scaler.map_or_else(
+                        || sample.input.clone(),
+                        |scaler| scaler.transform(&sample.input),
+                    ),
+                    target: target_scaler.map_or_else(
+                        || sample.target.clone(),
+                        |scaler| scaler.transform(&sample.target),
+                    ),
+                })
+                .collect(),
+        }
+    }
+
+    /// Shuffles the samples in place.
+    pub fn shuffle(&mut self, randomizer: &mut dyn RandomProvider) {
+        shuffle(&mut self.samples, randomizer);
+    }
+
+    /// Shuffles the samples and splits them into training, validation
+    /// and test datasets. Training set gets all the remaining samples.
+    pub fn split(
+        &self,
+        validation_fraction: f64,
+        test_fraction: f64,
+        randomizer: &mut dyn RandomProvider,
+    ) -> Result<(Dataset, Dataset, Dataset), String> {
+        if validation_fraction < 0.0
+            || test_fraction < 0.0
+            || validation_fraction + test_fraction >= 1.0
+        {
+            return Err("Validation and test fractions must leave room for training".to_string());
+        }
+        let mut shuffled = self.samples.clone();
+        shuffle(&mut shuffled, randomizer);
+
+        let validation_size = (self.len() as f64 * validation_fraction).round() as usize;
+        let test_size = (self.len() as f64 * test_fraction).round() as usize;
+        let test = shuffled.split_off(shuffled.len() - test_size);
+        let validation = shuffled.split_off(shuffled.len() - validation_size);
+        Ok((
+            Dataset { samples: shuffled },
+            Dataset {
+                samples: validation,
+            },
+            Dataset { samples: test },
+        ))
+    }
+
+    /// Iterates over consecutive mini-batches. The last one may be smaller.
+    pub fn batches(&self, batch_size: usize) -> std::slice::Chunks<'_, Sample> {
+        self.samples.chunks(batch_size.max(1))
+    }
+}
+
+/// Placeholder world for simulations that take the fitness from labelled samples.
+///
+/// It has no state, so it only makes sense together with
+/// [`Simulation::with_dataset_fitness`](../simulation/struct.Simulation.html#method.with_dataset_fitness).
+/// Without it the simulation returns an error.
+pub struct DatasetWorld;
+
+impl SimulatingWorld for DatasetWorld {
+    fn new() -> DatasetWorld {
+        DatasetWorld {}
+    }
+
+    fn tick(&mut self, _: &[f64]) -> SimulationStatus {
+        SimulationStatus {
+            specimen_status: SpecimenStatus::DEAD(0.0),
+            current_tick: 0,
+        }
+    }
+
+    fn get_world_state(&self) -> Vec<f64> {
+        vec![]
+    }
+}
+
+/// Specimen bundled with the preprocessing it was trained with.
+///
+/// Raw inputs are normalised before they reach the network and the
+/// outputs are brought back to the scale of the original targets.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NormalizedSpecimen {
+    pub specimen: crate::Specimen,
+    pub input_scaler: Option<Scaler>,
+    pub target_scaler: Option<Scaler>,
+}
+
+impl NormalizedSpecimen {
+    pub fn from_json(j: &str) -> Result<NormalizedSpecimen, String> {
+        let mut bundle: NormalizedSpecimen = serde_json::from_str(j).map_err(|e| e.to_string())?;
+        bundle.specimen.brain.validate()?;
+        Ok(bundle)
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string(self).map_err(|e| e.to_string())
+    }
+
+    /// Runs the network on raw (not normalised) inputs.
+    pub fn get_output(&self, inputs: &[f64]) -> Vec<f64> {
+        let inputs = self
+            .input_scaler
+            .as_ref()
+            .map_or_else(|| inputs.to_vec(), |scaler| scaler.transform(inputs));
+        let output = Exercise::new(&self.specimen).get_output(&inputs);
+        self.target_scaler.as_ref().map_or_else(
+            || output.clone(),
+            |scaler| scaler.inverse_transform(&output),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dataset::{Dataset, Normalization, NormalizedSpecimen, Scaler};
+    use crate::network::{Activation, NetworkBuilder};
+    use crate::randomizer::{DefaultRandomizer, RandomProvider};
+    use crate::supervised::Sample;
+    use crate::training_ground::Exercise;
+
+    const CSV: &str = "a,b,c\n1.0, 10.0, 0\n2.0, 20.0, 1\n\n3.0, 30.0, 0\n4.0, 40.0, 1\n";
+
+    #[test]
+    fn loads_selected_columns() {
+        let dataset = Dataset::from_csv(CSV.as_bytes(), &[1, 0], &[2], true).unwrap();
+        assert_eq!(dataset.len(), 4);
+        assert_eq!(dataset.get_samples()[1].input, vec![20.0, 2.0]);
+        assert_eq!(dataset.get_samples()[3].target, vec![1.0]);
+
+        assert!(Dataset::from_csv(CSV.as_bytes(), &[0], &[2], false).is_err());
+        assert!(Dataset::from_csv(CSV.as_bytes(), &[0], &[7], true).is_err());
+    }
+
+    #[test]
+    fn scalers() {
+        let rows = [vec![1.0, 5.0], vec![3.0, 5.0], vec![5.0, 5.0]];
+        let min_max = Scaler::fit(Normalization::MinMax, rows.iter().map(|r| &r[..])).unwrap();
+        assert_eq!(min_max.transform(&[3.0, 5.0]), vec![0.5, 0.0]);
+
+        let z_score = Scaler::fit(Normalization::ZScore, rows.iter().map(|r| &r[..])).unwrap();
+        let transformed = z_score.transform(&[5.0, 5.0]);
+        assert!(relative_eq!(transformed[0], 1.224744871391589));
+        let restored = z_score.inverse_transform(&transformed);
+        assert!(relative_eq!(restored[0], 5.0));
+        assert!(relative_eq!(restored[1], 5.0));
+
+        let ragged = [vec![1.0, 5.0], vec![3.0]];
+        assert!(Scaler::fit(Normalization::MinMax, ragged.iter().map(|r| &r[..])).is_err());
+    }
+
+    #[test]
+    fn ragged_samples_are_rejected() {
+        let sample = |input: &[f64], target: &[f64]| Sample {
+            input: input.to_vec(),
+            target: target.to_vec(),
+        };
+        assert!(Dataset::new(vec![
+            sample(&[1.0, 2.0], &[0.0]),
+            sample(&[3.0, 4.0], &[1.0])
+        ])
+        .is_ok());
+        assert!(Dataset::new(vec![sample(&[1.0, 2.0], &[0.0]), sample(&[3.0], &[1.0])]).is_err());
+        assert!(Dataset::new(vec![sample(&[1.0], &[0.0]), sample(&[3.0], &[1.0, 2.0])]).is_err());
+    }
+
+    #[test]
+    fn split_and_batches() {
+        struct Countdown(f64);
+        impl RandomProvider for Countdown {
+            fn get_number(&mut self) -> f64 {
+                self.0 -= 1.0;
+                self.0
+            }
+        }
+
+        let dataset = Dataset::from_csv(CSV.as_bytes(), &[0, 1], &[2], true).unwrap();
+        // Decreasing keys reverse the samples
+        let (training, validation, test) = dataset.split(0.25, 0.25, &mut Countdown(0.0)).unwrap();
+        let first_inputs = |dataset: &Dataset| -> Vec<f64> {
+            dataset.samples.iter().map(|s| s.input[0]).collect()
+        };
+        assert_eq!(first_inputs(&training), vec![4.0, 3.0]);
+        assert_eq!(first_inputs(&validation), vec![2.0]);
+        assert_eq!(first_inputs(&test), vec![1.0]);
+        assert!(dataset.split(0.5, 0.5, &mut Countdown(0.0)).is_err());
+
+        let sizes: Vec<usize> = dataset.batches(3).map(|batch| batch.len()).collect();
+        assert_eq!(sizes, vec![3, 1]);
+    }
+
+    #[test]
+    fn normalized_specimen_applies_preprocessing() {
+        let dataset = Dataset::from_csv(CSV.as_bytes(), &[0, 1], &[1], true).unwrap();
+        let input_scaler = dataset.fit_input_scaler(Normalization::ZScore);
+        let target_scaler = dataset.fit_target_scaler(Normalization::MinMax);
+
+        let mut randomizer = DefaultRandomizer::new();
+        let specimen = crate::Specimen {
+            brain: NetworkBuilder::new()
+                .with_neurons_per_layer(&[2, 1])
+                .with_randomizer(&mut randomizer)
+                .with_activation(Activation::Identity)
+                .build()
+                .layout,
+            fitness: 0.0,
+        };
+        let bundle = NormalizedSpecimen {
+            specimen: specimen.clone(),
+            input_scaler: Some(input_scaler.clone()),
+            target_scaler: Some(target_scaler.clone()),
+        };
+        let restored = NormalizedSpecimen::from_json(&bundle.to_json().unwrap()).unwrap();
+        assert_eq!(
+            restored.specimen.brain.get_weights(),
+            specimen.brain.get_weights()
+        );
+
+        let raw = [2.5, 25.0];
+        let expected = target_scaler
+            .inverse_transform(&Exercise::new(&specimen).get_output(&input_scaler.transform(&raw)));
+        assert!(relative_eq!(restored.get_output(&raw)[0], expected[0]));
+    }
+}
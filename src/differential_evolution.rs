@@ -0,0 +1,369 @@
+use std::marker::PhantomData;
+
+use rand::Rng;
+
+use crate::network::{NetworkBuilder, NetworkLayout};
+use crate::randomizer::{DefaultRandomizer, RandomProvider};
+use crate::simulating_world::SimulatingWorld;
+use crate::simulation::{evaluate, Finish};
+
+const DEFAULT_DIFFERENTIAL_WEIGHT: f64 = 0.5;
+const DEFAULT_CROSSOVER_PROBABILITY: f64 = 0.9;
+const JDE_ADAPTATION_PROBABILITY: f64 = 0.1;
+const JDE_MINIMUM_DIFFERENTIAL_WEIGHT: f64 = 0.1;
+const JDE_DIFFERENTIAL_WEIGHT_RANGE: f64 = 0.9;
+
+/// Scheme used to create the mutant vector.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    /// `v = x_r1 + F * (x_r2 - x_r3)`
+    Rand1Bin,
+    /// `v = x_best + F * (x_r1 - x_r2)`
+    Best1Bin,
+    /// `v = x_i + F * (x_best - x_i) + F * (x_r1 - x_r2)`
+    CurrentToBest1,
+}
+
+struct Member {
+    weights: Vec<f64>,
+    fitness: f64,
+    differential_weight: f64,
+    crossover_probability: f64,
+}
+
+/// Trainer that uses differential evolution over the flattened network weights.
+///
+/// Each member of the population is challenged by a trial vector built according
+/// to the selected [`Strategy`](enum.Strategy.html) and replaced if the trial plays
+/// the episode in the `SimulatingWorld` at least as well.
+pub struct DifferentialEvolution<T: SimulatingWorld> {
+    population: Vec<Member>,
+    template: NetworkLayout,
+    strategy: Strategy,
+    self_adaptive: bool,
+    /// Used by the output heads sampling the actions.
+    randomizer: DefaultRandomizer,
+    counter: usize,
+    world: PhantomData<T>,
+}
+
+impl<T: SimulatingWorld> DifferentialEvolution<T> {
+    /// Creates new differential evolution trainer.
+    ///
+    /// `differential_weight` (F) defaults to `0.5` and `crossover_probability` (CR)
+    /// to `0.9`. When self-adaptation is enabled, these are only the starting values.
+    pub fn new(
+        population_size: usize,
+        neurons_per_layer: &[usize],
+        randomizer: &mut dyn RandomProvider,
+        strategy: Strategy,
+        differential_weight: Option<f64>,
+        crossover_probability: Option<f64>,
+    ) -> Result<DifferentialEvolution<T>, String> {
+        use crate::MINIMUM_POPULATION_SIZE;
+
+        if population_size < MINIMUM_POPULATION_SIZE {
+            return Err(format!(
+                "Population too small, minimum size={}",
+                MINIMUM_POPULATION_SIZE
+            ));
+        }
+
+        let differential_weight = differential_weight.unwrap_or(DEFAULT_DIFFERENTIAL_WEIGHT);
+        let crossover_probability = crossover_probability.unwrap_or(DEFAULT_CROSSOVER_PROBABILITY);
+        if !(0.0..=1.0).contains(&crossover_probability) {
+            return Err("Crossover probability must be from range [0.0, 1.0]".to_string());
+        }
+
+        let layouts: Vec<NetworkLayout> = std::iter::repeat_with(|| {
+            NetworkBuilder::new()
+                .with_neurons_per_layer(neurons_per_layer)
+                .with_randomizer(randomizer)
+                .build()
+                .layout
+        })
+        .take(population_size)
+        .collect();
+        let population = layouts
+            .iter()
+            .map(|layout| Member {
+                fitness: evaluate::<T>(layout, randomizer).fitness,
+                weights: layout.get_weights(),
+                differential_weight,
+                crossover_probability,
+            })
+            .collect();
+
+        let template = layouts.into_iter().next().expect("Empty population");
+        if template.get_number_of_weights() == 0 {
+            return Err("Network has no weights to optimize".to_string());
+        }
+
+        Ok(DifferentialEvolution {
+            population,
+            template,
+            strategy,
+            self_adaptive: false,
+            randomizer: DefaultRandomizer::new(),
+            counter: 0,
+            world: PhantomData,
+        })
+    }
+
+    /// Enables jDE-style self-adaptation, where each member carries
+    /// its own F and CR which evolve together with the weights.
+    pub fn with_self_adaptation(mut self) -> Self {
+        self.self_adaptive = true;
+        self
+    }
+
+    /// Runs the learning round.
+    ///
+    /// Returns two best specimen from the current population.
+    pub fn run(&mut self, finish: Finish) -> Result<[crate::Specimen; 2], String> {
+        match finish {
+            Finish::Occurences(count) => {
+                for _ in 0..count {
+                    self.generation();
+                }
+                let mut order: Vec<usize> = (0..self.population.len()).collect();
+                order.sort_by(|a, b| {
+                    self.population[*b]
+                        .fitness
+                        .partial_cmp(&self.population[*a].fitness)
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+                Ok([self.specimen(order[0]), self.specimen(order[1])])
+            }
+            _ => Err("Simulation end trigger not supported yet".to_string()),
+        }
+    }
+
+    /// Returns number of generations used in recent learning session.
+    pub fn get_number_of_iterations(&self) -> usize {
+        self.counter
+    }
+
+    fn specimen(&self, index: usize) -> crate::Specimen {
+        let mut brain = self.template.clone();
+        brain.set_weights(&self.population[index].weights);
+        crate::Specimen {
+            brain,
+            fitness: self.population[index].fitness,
+        }
+    }
+
+    fn best_index(&self) -> usize {
+        (0..self.population.len())
+            .max_by(|a, b| {
+                self.population[*a]
+                    .fitness
+                    .partial_cmp(&self.population[*b].fitness)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .expect("Empty population")
+    }
+
+    /// Picks `count` distinct member indices, all different from `current`.
+    fn pick_distinct(
+        rng: &mut rand::rngs::ThreadRng,
+        size: usize,
+        current: usize,
+        count: usize,
+    ) -> Vec<usize> {
+        let mut picked = Vec::with_capacity(count);
+        while picked.len() < count {
+            let candidate = rng.gen_range(0, size);
+            if candidate != current && !picked.contains(&candidate) {
+                picked.push(candidate);
+            }
+        }
+        picked
+    }
+
+    fn mutant(
+        &self,
+        current: usize,
+        best: usize,
+        f: f64,
+        rng: &mut rand::rngs::ThreadRng,
+    ) -> Vec<f64> {
+        let size = self.population.len();
+        let x = |index: usize| &self.population[index].weights;
+        match self.strategy {
+            Strategy::Rand1Bin => {
+                let r = Self::pick_distinct(rng, size, current, 3);
+                (0..x(current).len())
+                    .map(|k| x(r[0])[k] + f * (x(r[1])[k] - x(r[2])[k]))
+                    .collect()
+            }
+            Strategy::Best1Bin => {
+                let r = Self::pick_distinct(rng, size, current, 2);
+                (0..x(current).len())
+                    .map(|k| x(best)[k] + f * (x(r[0])[k] - x(r[1])[k]))
+                    .collect()
+            }
+            Strategy::CurrentToBest1 => {
+                let r = Self::pick_distinct(rng, size, current, 2);
+                (0..x(current).len())
+                    .map(|k| {
+                        x(current)[k]
+                            + f * (x(best)[k] - x(current)[k])
+                            + f * (x(r[0])[k] - x(r[1])[k])
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    fn generation(&mut self) {
+        self.counter += 1;
+        let mut rng = rand::thread_rng();
+        let best = self.best_index();
+
+        for current in 0..self.population.len() {
+            let (mut f, mut cr) = (
+                self.population[current].differential_weight,
+                self.population[current].crossover_probability,
+            );
+            if self.self_adaptive {
+                if rng.gen::<f64>() < JDE_ADAPTATION_PROBABILITY {
+                    f = JDE_MINIMUM_DIFFERENTIAL_WEIGHT
+                        + rng.gen::<f64>() * JDE_DIFFERENTIAL_WEIGHT_RANGE;
+                }
+                if rng.gen::<f64>() < JDE_ADAPTATION_PROBABILITY {
+                    cr = rng.gen::<f64>();
+                }
+            }
+
+            let mutant = self.mutant(current, best, f, &mut rng);
+            let forced = rng.gen_range(0, mutant.len());
+            let trial: Vec<f64> = self.population[current]
+                .weights
+                .iter()
+                .zip(mutant.iter())
+                .enumerate()
+                .map(|(k, (original, mutated))| {
+                    if k == forced || rng.gen::<f64>() < cr {
+                        *mutated
+                    } else {
+                        *original
+                    }
+                })
+                .collect();
+
+            self.template.set_weights(&trial);
+            let fitness = evaluate::<T>(&self.template, &mut self.randomizer).fitness;
+            if fitness >= self.population[current].fitness {
+                self.population[current] = Member {
+                    weights: trial,
+                    fitness,
+                    differential_weight: f,
+                    crossover_probability: cr,
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::differential_evolution::{
+        DifferentialEvolution, Strategy, DEFAULT_CROSSOVER_PROBABILITY,
+        DEFAULT_DIFFERENTIAL_WEIGHT, JDE_DIFFERENTIAL_WEIGHT_RANGE,
+        JDE_MINIMUM_DIFFERENTIAL_WEIGHT,
+    };
+    use crate::randomizer::DefaultRandomizer;
+    use crate::simulating_world::{assert_reaches_target, TargetWorld};
+    use crate::simulation::Finish;
+
+    #[test]
+    fn all_strategies_converge() {
+        for strategy in [
+            Strategy::Rand1Bin,
+            Strategy::Best1Bin,
+            Strategy::CurrentToBest1,
+        ]
+        .iter()
+        {
+            let mut randomizer = DefaultRandomizer::new();
+            let mut trainer = DifferentialEvolution::<TargetWorld>::new(
+                10,
+                &[2, 2, 1],
+                &mut randomizer,
+                *strategy,
+                None,
+                None,
+            )
+            .unwrap();
+            let best = trainer.run(Finish::Occurences(50)).unwrap();
+            assert_eq!(trainer.get_number_of_iterations(), 50);
+            assert_reaches_target(&best, 1e-3);
+        }
+    }
+
+    #[test]
+    fn self_adaptation_never_worsens_population() {
+        let mut randomizer = DefaultRandomizer::new();
+        let mut trainer = DifferentialEvolution::<TargetWorld>::new(
+            8,
+            &[2, 3, 1],
+            &mut randomizer,
+            Strategy::Rand1Bin,
+            None,
+            None,
+        )
+        .unwrap()
+        .with_self_adaptation();
+        let mut previous = trainer.run(Finish::Occurences(1)).unwrap()[0].fitness;
+        for _ in 0..10 {
+            let current = trainer.run(Finish::Occurences(1)).unwrap()[0].fitness;
+            assert!(current >= previous);
+            previous = current;
+        }
+        trainer.run(Finish::Occurences(20)).unwrap();
+        assert!(trainer.population.iter().any(|member| {
+            member.differential_weight != DEFAULT_DIFFERENTIAL_WEIGHT
+                || member.crossover_probability != DEFAULT_CROSSOVER_PROBABILITY
+        }));
+        assert!(trainer.population.iter().all(|member| {
+            (JDE_MINIMUM_DIFFERENTIAL_WEIGHT
+                ..=JDE_MINIMUM_DIFFERENTIAL_WEIGHT + JDE_DIFFERENTIAL_WEIGHT_RANGE)
+                .contains(&member.differential_weight)
+                && (0.0..=1.0).contains(&member.crossover_probability)
+        }));
+    }
+
+    #[test]
+    fn parameters_are_fixed_without_self_adaptation() {
+        let mut randomizer = DefaultRandomizer::new();
+        let mut trainer = DifferentialEvolution::<TargetWorld>::new(
+            8,
+            &[2, 3, 1],
+            &mut randomizer,
+            Strategy::Rand1Bin,
+            None,
+            None,
+        )
+        .unwrap();
+        trainer.run(Finish::Occurences(10)).unwrap();
+        assert!(trainer.population.iter().all(|member| {
+            member.differential_weight == DEFAULT_DIFFERENTIAL_WEIGHT
+                && member.crossover_probability == DEFAULT_CROSSOVER_PROBABILITY
+        }));
+    }
+
+    #[test]
+    fn invalid_crossover_probability() {
+        let mut randomizer = DefaultRandomizer::new();
+        assert!(DifferentialEvolution::<TargetWorld>::new(
+            8,
+            &[2, 1],
+            &mut randomizer,
+            Strategy::Best1Bin,
+            None,
+            Some(1.5),
+        )
+        .is_err());
+    }
+}
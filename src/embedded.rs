@@ -0,0 +1,360 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::f32::consts::{LN_2, LOG2_E};
+
+#[cfg(feature = "std")]
+use crate::network::{Activation, DenseLayout};
+
+/// Leading bytes of the exported networks, the last one is the format version.
+const MAGIC: [u8; 4] = *b"ENN\x01";
+
+const SIGMOID: u8 = 0;
+const TANH: u8 = 1;
+const RELU: u8 = 2;
+const IDENTITY: u8 = 3;
+
+#[derive(Clone, Debug, PartialEq)]
+struct EmbeddedNeuron {
+    /// Positions of the inputs the weights belong to, empty if all inputs are connected.
+    positions: Vec<usize>,
+    weights: Vec<f32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct EmbeddedLayer {
+    index: usize,
+    sources: Vec<usize>,
+    neurons: Vec<EmbeddedNeuron>,
+}
+
+/// Network that can only be fired, available without the standard library
+/// (`default-features = false`), as long as the target provides `alloc`.
+///
+/// It is loaded from the bytes written by [`to_bytes`](#method.to_bytes),
+/// e.g. embedded into the firmware with `include_bytes!`. Weights and values
+/// are `f32`. Only networks consisting of dense layers can be exported
+/// and the output head is not applied.
+///
+/// All numbers of the format are little-endian:
+/// - magic `ENN` followed by the version `1`,
+/// - activation (`u8`: sigmoid, tanh, ReLU, identity),
+/// - number of layers (`u32`), then the size of each layer (`u32`, bias excluded),
+/// - number of fired layers (`u32`), then each of them in the evaluation order:
+///   its index (`u32`), number of sources (`u32`) and sources (`u32`),
+///   sparse flag (`u8`) and for each neuron the number of weights (`u32`),
+///   input positions (`u32`, only if sparse) and weights (`f32`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmbeddedNetwork {
+    activation: u8,
+    sizes: Vec<usize>,
+    layers: Vec<EmbeddedLayer>,
+}
+
+impl EmbeddedNetwork {
+    /// Loads the network, checking that the bytes describe a valid one.
+    pub fn from_bytes(bytes: &[u8]) -> Result<EmbeddedNetwork, String> {
+        let mut reader = Reader { bytes, position: 0 };
+        if reader.read(MAGIC.len())? != MAGIC {
+            return Err("Bytes do not contain an exported network".to_string());
+        }
+        let activation = reader.read_u8()?;
+        if activation > IDENTITY {
+            return Err(format!("Unknown activation {}", activation));
+        }
+        let number_of_layers = reader.read_usize()?;
+        let sizes = (0..number_of_layers)
+            .map(|_| reader.read_usize())
+            .collect::<Result<Vec<_>, String>>()?;
+        let last = number_of_layers
+            .checked_sub(1)
+            .ok_or_else(|| "Network has no layers".to_string())?;
+
+        let mut fired = vec![false; number_of_layers];
+        if let Some(input) = fired.first_mut() {
+            *input = true;
+        }
+        let number_of_fired = reader.read_usize()?;
+        let mut layers = vec![];
+        for _ in 0..number_of_fired {
+            let index = reader.read_usize()?;
+            if index >= number_of_layers || fired[index] {
+                return Err(format!("Layer {} cannot be fired", index));
+            }
+            let number_of_sources = reader.read_usize()?;
+            let sources = (0..number_of_sources)
+                .map(|_| reader.read_usize())
+                .collect::<Result<Vec<_>, String>>()?;
+            if sources
+                .iter()
+                .any(|source| *source == last || !fired.get(*source).copied().unwrap_or(false))
+            {
+                return Err(format!("Layer {} is fired before its sources", index));
+            }
+            let number_of_inputs = sources
+                .iter()
+                .try_fold(0usize, |total, source| {
+                    sizes[*source]
+                        .checked_add(1)
+                        .and_then(|size| total.checked_add(size))
+                })
+                .ok_or_else(|| format!("Layer {} has too many inputs", index))?;
+            let sparse = reader.read_u8()? != 0;
+            let neurons = (0..sizes[index])
+                .map(|_| {
+                    let count = reader.read_usize()?;
+                    let positions = if sparse {
+                        (0..count)
+                            .map(|_| reader.read_usize())
+                            .collect::<Result<Vec<_>, String>>()?
+                    } else {
+                        vec![]
+                    };
+                    if (sparse && positions.iter().any(|p| *p >= number_of_inputs))
+                        || (!sparse && count != number_of_inputs)
+                    {
+                        return Err(format!("Neuron of layer {} has invalid inputs", index));
+                    }
+                    let weights = (0..count)
+                        .map(|_| reader.read_f32())
+                        .collect::<Result<Vec<_>, String>>()?;
+                    Ok(EmbeddedNeuron { positions, weights })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            fired[index] = true;
+            layers.push(EmbeddedLayer {
+                index,
+                sources,
+                neurons,
+            });
+        }
+        if fired.iter().any(|f| !f) {
+            return Err("Some layers are never fired".to_string());
+        }
+        if reader.position != bytes.len() {
+            return Err("Unexpected bytes after the network".to_string());
+        }
+        Ok(EmbeddedNetwork {
+            activation,
+            sizes,
+            layers,
+        })
+    }
+
+    /// Exports the brain of the specimen into the format read by
+    /// [`from_bytes`](#method.from_bytes).
+    #[cfg(feature = "std")]
+    pub fn to_bytes(specimen: &crate::Specimen) -> Result<Vec<u8>, String> {
+        let layout = &specimen.brain;
+        let DenseLayout { sizes, order } = layout.get_dense_layout("exported")?;
+        let push_usize = |bytes: &mut Vec<u8>, value: usize| {
+            bytes.extend_from_slice(&(value as u32).to_le_bytes())
+        };
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(match layout.activation {
+            Activation::Sigmoid => SIGMOID,
+            Activation::Tanh => TANH,
+            Activation::Relu => RELU,
+            Activation::Identity => IDENTITY,
+        });
+        push_usize(&mut bytes, sizes.len());
+        sizes
+            .into_iter()
+            .for_each(|size| push_usize(&mut bytes, size));
+        push_usize(&mut bytes, order.len() - 1);
+        for layer_index in order.into_iter().skip(1) {
+            push_usize(&mut bytes, layer_index);
+            let sources = layout.get_layer_sources(layer_index);
+            push_usize(&mut bytes, sources.len());
+            sources
+                .into_iter()
+                .for_each(|source| push_usize(&mut bytes, source));
+            let neurons: Vec<_> = layout.layers[layer_index]
+                .iter()
+                .map(|neuron_id| &layout.neurons[*neuron_id])
+                .filter(|neuron| !neuron.bias)
+                .collect();
+            let sparse = neurons.iter().any(|neuron| neuron.connections.is_some());
+            bytes.push(sparse as u8);
+            for neuron in neurons {
+                push_usize(&mut bytes, neuron.inputs.len());
+                if sparse {
+                    (0..neuron.inputs.len())
+                        .for_each(|j| push_usize(&mut bytes, neuron.get_input_position(j)));
+                }
+                neuron
+                    .inputs
+                    .iter()
+                    .for_each(|weight| bytes.extend_from_slice(&(*weight as f32).to_le_bytes()));
+            }
+        }
+        Ok(bytes)
+    }
+
+    pub fn get_number_of_inputs(&self) -> usize {
+        self.sizes[0]
+    }
+
+    pub fn get_number_of_outputs(&self) -> usize {
+        self.sizes[self.sizes.len() - 1]
+    }
+
+    /// Fires the network and returns the values of the output layer.
+    pub fn get_output(&self, inputs: &[f32]) -> Vec<f32> {
+        assert_eq!(
+            inputs.len(),
+            self.get_number_of_inputs(),
+            "Incorrect number of inputs"
+        );
+        let last = self.sizes.len() - 1;
+        let with_bias = |mut values: Vec<f32>, layer_index: usize| {
+            if layer_index != last {
+                values.push(1.0);
+            }
+            values
+        };
+        let mut values: Vec<Vec<f32>> = vec![vec![]; self.sizes.len()];
+        values[0] = with_bias(inputs.to_vec(), 0);
+        for layer in &self.layers {
+            let inputs: Vec<f32> = layer
+                .sources
+                .iter()
+                .flat_map(|source| values[*source].iter().copied())
+                .collect();
+            let outputs = layer
+                .neurons
+                .iter()
+                .map(|neuron| {
+                    let total: f32 = if neuron.positions.is_empty() {
+                        neuron
+                            .weights
+                            .iter()
+                            .zip(inputs.iter())
+                            .map(|(w, x)| w * x)
+                            .sum()
+                    } else {
+                        neuron
+                            .positions
+                            .iter()
+                            .zip(neuron.weights.iter())
+                            .map(|(position, w)| w * inputs[*position])
+                            .sum()
+                    };
+                    self.activate(total)
+                })
+                .collect();
+            values[layer.index] = with_bias(outputs, layer.index);
+        }
+        values.swap_remove(last)
+    }
+
+    fn activate(&self, x: f32) -> f32 {
+        match self.activation {
+            SIGMOID => 1.0 / (1.0 + exp(-x)),
+            TANH => 1.0 - 2.0 / (exp(2.0 * x) + 1.0),
+            RELU => {
+                if x > 0.0 {
+                    x
+                } else {
+                    0.0
+                }
+            }
+            _ => x,
+        }
+    }
+}
+
+/// Exponential function for targets without `libm`: `2^k * e^r` with `|r| <= ln(2) / 2`,
+/// `e^r` approximated with its Taylor polynomial.
+fn exp(x: f32) -> f32 {
+    if x > 88.0 {
+        return f32::INFINITY;
+    }
+    if x < -87.0 {
+        return 0.0;
+    }
+    let rounding = if x < 0.0 { -0.5 } else { 0.5 };
+    let k = (x * LOG2_E + rounding) as i32;
+    let r = x - k as f32 * LN_2;
+    let polynomial = 1.0
+        + r * (1.0
+            + r * (1.0 / 2.0 + r * (1.0 / 6.0 + r * (1.0 / 24.0 + r * (1.0 / 120.0 + r / 720.0)))));
+    polynomial * f32::from_bits(((k + 127) as u32) << 23)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + count)
+            .ok_or_else(|| "Unexpected end of the network bytes".to_string())?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.read(1)?[0])
+    }
+
+    fn read_usize(&mut self) -> Result<usize, String> {
+        let mut word = [0; 4];
+        word.copy_from_slice(self.read(4)?);
+        Ok(u32::from_le_bytes(word) as usize)
+    }
+
+    fn read_f32(&mut self) -> Result<f32, String> {
+        let mut word = [0; 4];
+        word.copy_from_slice(self.read(4)?);
+        Ok(f32::from_le_bytes(word))
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::embedded::{exp, EmbeddedNetwork};
+    use crate::network::{build_exporter_fixture, Activation};
+    use crate::randomizer::DefaultRandomizer;
+    use crate::training_ground::Exercise;
+
+    #[test]
+    fn exported_network_matches_original() {
+        for x in [-20.0f32, -3.3, -0.1, 0.0, 0.7, 5.0, 30.0] {
+            assert!(relative_eq!(exp(x), x.exp(), max_relative = 1e-6));
+        }
+
+        let mut randomizer = DefaultRandomizer::new();
+        for activation in [Activation::Sigmoid, Activation::Tanh, Activation::Relu] {
+            let specimen = build_exporter_fixture(activation, &mut randomizer);
+
+            let bytes = EmbeddedNetwork::to_bytes(&specimen).unwrap();
+            let network = EmbeddedNetwork::from_bytes(&bytes).unwrap();
+            assert_eq!(network.get_number_of_inputs(), 3);
+            assert_eq!(network.get_number_of_outputs(), 2);
+            let exercise = Exercise::new(&specimen);
+            for input in [[0.5, -1.0, 0.25], [2.0, 0.0, -0.75]] {
+                let expected = exercise.get_output(&input);
+                let actual = network.get_output(&input.map(|x| x as f32));
+                expected.iter().zip(actual.iter()).for_each(|(e, a)| {
+                    assert!(relative_eq!(*e, *a as f64, epsilon = 1e-4));
+                });
+            }
+
+            assert!(EmbeddedNetwork::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+            assert!(EmbeddedNetwork::from_bytes(&[bytes.as_slice(), &[0]].concat()).is_err());
+        }
+    }
+
+    #[test]
+    fn untrusted_counts_are_not_preallocated() {
+        // Two layers of u32::MAX neurons and u32::MAX fired layers, but no layer data
+        let bytes: Vec<u8> = [&b"ENN\x01\x03"[..], &[2, 0, 0, 0], &[0xff; 12]].concat();
+        assert!(EmbeddedNetwork::from_bytes(&bytes).is_err());
+    }
+}
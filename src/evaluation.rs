@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+
+use crate::action::argmax;
+
+/// Quality of the network used for regression.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RegressionReport {
+    pub mean_squared_error: f64,
+    pub mean_absolute_error: f64,
+    /// Coefficient of determination, `1.0` for a perfect fit.
+    pub r_squared: f64,
+}
+
+impl RegressionReport {
+    /// Compares the outputs of the network with the expected values.
+    pub fn from_predictions(
+        predictions: &[Vec<f64>],
+        targets: &[Vec<f64>],
+    ) -> Result<RegressionReport, String> {
+        check_sizes(predictions, targets)?;
+        let count = predictions.iter().map(|p| p.len()).sum::<usize>() as f64;
+        let columns = targets[0].len();
+        let means: Vec<f64> = (0..columns)
+            .map(|column| targets.iter().map(|t| t[column]).sum::<f64>() / targets.len() as f64)
+            .collect();
+
+        let (mut squared, mut absolute, mut total) = (0.0, 0.0, 0.0);
+        for (prediction, target) in predictions.iter().zip(targets.iter()) {
+            for ((p, t), mean) in prediction.iter().zip(target.iter()).zip(means.iter()) {
+                squared += (p - t).powi(2);
+                absolute += (p - t).abs();
+                total += (t - mean).powi(2);
+            }
+        }
+        Ok(RegressionReport {
+            mean_squared_error: squared / count,
+            mean_absolute_error: absolute / count,
+            r_squared: if total > 0.0 {
+                1.0 - squared / total
+            } else if squared > 0.0 {
+                f64::NEG_INFINITY
+            } else {
+                1.0
+            },
+        })
+    }
+}
+
+/// Metrics of a single class.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ClassReport {
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    /// Number of samples that actually belong to the class.
+    pub support: usize,
+}
+
+/// Quality of the network used for classification.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ClassificationReport {
+    pub accuracy: f64,
+    pub classes: Vec<ClassReport>,
+    /// `confusion_matrix[actual][predicted]` holds the number of samples.
+    pub confusion_matrix: Vec<Vec<usize>>,
+}
+
+impl ClassificationReport {
+    /// Compares the classes chosen by the network with the expected ones.
+    ///
+    /// Networks with a single output are treated as binary classifiers with
+    /// the threshold of `0.5`. Otherwise the class is the index of the
+    /// highest output, so targets are expected to be one-hot encoded.
+    pub fn from_predictions(
+        predictions: &[Vec<f64>],
+        targets: &[Vec<f64>],
+    ) -> Result<ClassificationReport, String> {
+        check_sizes(predictions, targets)?;
+        let number_of_classes = match targets[0].len() {
+            1 => 2,
+            outputs => outputs,
+        };
+        let mut confusion_matrix = vec![vec![0; number_of_classes]; number_of_classes];
+        for (prediction, target) in predictions.iter().zip(targets.iter()) {
+            confusion_matrix[get_class(target)][get_class(prediction)] += 1;
+        }
+
+        let classes = (0..number_of_classes)
+            .map(|class| {
+                let true_positives = confusion_matrix[class][class] as f64;
+                let predicted = confusion_matrix.iter().map(|row| row[class]).sum::<usize>();
+                let support = confusion_matrix[class].iter().sum::<usize>();
+                let ratio = |count: usize| {
+                    if count > 0 {
+                        true_positives / count as f64
+                    } else {
+                        0.0
+                    }
+                };
+                let (precision, recall) = (ratio(predicted), ratio(support));
+                ClassReport {
+                    precision,
+                    recall,
+                    f1: if precision + recall > 0.0 {
+                        2.0 * precision * recall / (precision + recall)
+                    } else {
+                        0.0
+                    },
+                    support,
+                }
+            })
+            .collect();
+
+        let correct = (0..number_of_classes)
+            .map(|class| confusion_matrix[class][class])
+            .sum::<usize>();
+        Ok(ClassificationReport {
+            accuracy: correct as f64 / predictions.len() as f64,
+            classes,
+            confusion_matrix,
+        })
+    }
+}
+
+fn check_sizes(predictions: &[Vec<f64>], targets: &[Vec<f64>]) -> Result<(), String> {
+    if predictions.is_empty() || predictions.len() != targets.len() {
+        return Err("Number of predictions must match the number of samples".to_string());
+    }
+    let columns = targets[0].len();
+    if columns == 0
+        || predictions
+            .iter()
+            .zip(targets.iter())
+            .any(|(p, t)| p.len() != columns || t.len() != columns)
+    {
+        return Err("Number of outputs must match the number of targets".to_string());
+    }
+    Ok(())
+}
+
+fn get_class(values: &[f64]) -> usize {
+    if values.len() == 1 {
+        return if values[0] >= 0.5 { 1 } else { 0 };
+    }
+    argmax(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluation::{ClassificationReport, RegressionReport};
+
+    #[test]
+    fn regression_metrics() {
+        let targets = vec![vec![1.0], vec![2.0], vec![3.0]];
+        let predictions = vec![vec![1.0], vec![2.5], vec![2.0]];
+        let report = RegressionReport::from_predictions(&predictions, &targets).unwrap();
+        assert!(relative_eq!(report.mean_squared_error, 1.25 / 3.0));
+        assert!(relative_eq!(report.mean_absolute_error, 0.5));
+        assert!(relative_eq!(report.r_squared, 1.0 - 1.25 / 2.0));
+
+        let perfect = RegressionReport::from_predictions(&targets, &targets).unwrap();
+        assert!(relative_eq!(perfect.r_squared, 1.0));
+        assert!(RegressionReport::from_predictions(&predictions[..2], &targets).is_err());
+    }
+
+    #[test]
+    fn classification_metrics() {
+        let targets = vec![
+            vec![1.0, 0.0, 0.0],
+            vec![1.0, 0.0, 0.0],
+            vec![0.0, 1.0, 0.0],
+            vec![0.0, 0.0, 1.0],
+        ];
+        let predictions = vec![
+            vec![0.9, 0.1, 0.0],
+            vec![0.2, 0.7, 0.1],
+            vec![0.1, 0.8, 0.1],
+            vec![0.3, 0.3, 0.4],
+        ];
+        let report = ClassificationReport::from_predictions(&predictions, &targets).unwrap();
+        assert!(relative_eq!(report.accuracy, 0.75));
+        assert_eq!(
+            report.confusion_matrix,
+            vec![vec![1, 1, 0], vec![0, 1, 0], vec![0, 0, 1]]
+        );
+        assert!(relative_eq!(report.classes[0].precision, 1.0));
+        assert!(relative_eq!(report.classes[0].recall, 0.5));
+        assert!(relative_eq!(report.classes[1].precision, 0.5));
+        assert!(relative_eq!(report.classes[1].f1, 2.0 / 3.0));
+        assert_eq!(report.classes[0].support, 2);
+    }
+
+    #[test]
+    fn binary_classification_uses_threshold() {
+        let targets = vec![vec![0.0], vec![1.0], vec![1.0]];
+        let predictions = vec![vec![0.2], vec![0.6], vec![0.4]];
+        let report = ClassificationReport::from_predictions(&predictions, &targets).unwrap();
+        assert_eq!(report.confusion_matrix, vec![vec![1, 0], vec![1, 1]]);
+        assert!(relative_eq!(report.accuracy, 2.0 / 3.0));
+    }
+}
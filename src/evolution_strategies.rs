@@ -0,0 +1,262 @@
+use std::marker::PhantomData;
+
+use rand_distr::{Distribution, StandardNormal};
+
+use crate::network::{NetworkBuilder, NetworkLayout};
+use crate::optimizer::{Adam, Optimizer};
+use crate::randomizer::{DefaultRandomizer, RandomProvider};
+use crate::simulating_world::SimulatingWorld;
+use crate::simulation::{evaluate, Finish};
+
+const DEFAULT_SIGMA: f64 = 0.1;
+const DEFAULT_LEARNING_RATE: f64 = 0.03;
+
+/// Trainer that uses natural evolution strategies in the flavour popularized by OpenAI.
+///
+/// Only a single, mean network is kept. In each generation it is perturbed with
+/// antithetic pairs of Gaussian noise, the perturbed networks play an episode in
+/// the `SimulatingWorld` and the mean is moved towards the better performing ones
+/// using the rank-normalised fitness. The step is taken by the
+/// [`Optimizer`](../optimizer/trait.Optimizer.html), Adam by default.
+pub struct EvolutionStrategies<T: SimulatingWorld, O: Optimizer = Adam> {
+    mean: Vec<f64>,
+    template: NetworkLayout,
+    population_size: usize,
+    sigma: f64,
+    optimizer: O,
+    /// Used by the output heads sampling the actions.
+    randomizer: DefaultRandomizer,
+    counter: usize,
+    world: PhantomData<T>,
+}
+
+/// Replaces each fitness by its rank, scaled into `[-0.5, 0.5]`.
+fn centered_ranks(fitness: &[f64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..fitness.len()).collect();
+    order.sort_by(|a, b| {
+        fitness[*a]
+            .partial_cmp(&fitness[*b])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let mut ranks = vec![0.0; fitness.len()];
+    let scale = (fitness.len() - 1).max(1) as f64;
+    order
+        .iter()
+        .enumerate()
+        .for_each(|(rank, index)| ranks[*index] = rank as f64 / scale - 0.5);
+    ranks
+}
+
+impl<T: SimulatingWorld> EvolutionStrategies<T> {
+    /// Creates new evolution strategies trainer.
+    ///
+    /// `population_size` is the number of perturbed networks evaluated in each
+    /// generation and must be even, since perturbations come in antithetic pairs.
+    /// `sigma` is the standard deviation of the perturbations.
+    pub fn new(
+        population_size: usize,
+        neurons_per_layer: &[usize],
+        randomizer: &mut dyn RandomProvider,
+        sigma: Option<f64>,
+    ) -> Result<EvolutionStrategies<T>, String> {
+        use crate::MINIMUM_POPULATION_SIZE;
+
+        if population_size < MINIMUM_POPULATION_SIZE {
+            return Err(format!(
+                "Population too small, minimum size={}",
+                MINIMUM_POPULATION_SIZE
+            ));
+        }
+
+        if population_size % 2 != 0 {
+            return Err("Population size must be an even number".to_string());
+        };
+
+        let template = NetworkBuilder::new()
+            .with_neurons_per_layer(neurons_per_layer)
+            .with_randomizer(randomizer)
+            .build()
+            .layout;
+        let mean = template.get_weights();
+
+        Ok(EvolutionStrategies {
+            mean,
+            template,
+            population_size,
+            sigma: sigma.unwrap_or(DEFAULT_SIGMA),
+            optimizer: Adam::new(DEFAULT_LEARNING_RATE),
+            randomizer: DefaultRandomizer::new(),
+            counter: 0,
+            world: PhantomData,
+        })
+    }
+}
+
+impl<T: SimulatingWorld, O: Optimizer> EvolutionStrategies<T, O> {
+    /// Replaces the optimizer used to update the mean network.
+    pub fn with_optimizer<P: Optimizer>(self, optimizer: P) -> EvolutionStrategies<T, P> {
+        EvolutionStrategies {
+            mean: self.mean,
+            template: self.template,
+            population_size: self.population_size,
+            sigma: self.sigma,
+            optimizer,
+            randomizer: self.randomizer,
+            counter: self.counter,
+            world: PhantomData,
+        }
+    }
+
+    /// Starts the search from the brain of an already trained specimen.
+    pub fn with_mean(mut self, specimen: &crate::Specimen) -> Result<Self, String> {
+        let weights = specimen.brain.get_weights();
+        if weights.len() != self.mean.len() {
+            return Err("Specimen does not match the network layout".to_string());
+        }
+        self.template = specimen.brain.clone();
+        self.mean = weights;
+        Ok(self)
+    }
+
+    /// Runs the learning round.
+    ///
+    /// Returns the mean network and the best perturbed network from the most
+    /// recent generation, the better one first.
+    pub fn run(&mut self, finish: Finish) -> Result<[crate::Specimen; 2], String> {
+        match finish {
+            Finish::Occurences(count) => {
+                let mut best_so_far = None;
+                for _ in 0..count {
+                    best_so_far = Some(self.generation());
+                }
+                best_so_far.ok_or_else(|| {
+                    "Simulation finished, but no best specimen could be selected".to_string()
+                })
+            }
+            _ => Err("Simulation end trigger not supported yet".to_string()),
+        }
+    }
+
+    /// Returns number of generations used in recent learning session.
+    pub fn get_number_of_iterations(&self) -> usize {
+        self.counter
+    }
+
+    fn specimen_at(&mut self, weights: &[f64]) -> crate::Specimen {
+        self.template.set_weights(weights);
+        crate::Specimen {
+            fitness: evaluate::<T>(&self.template, &mut self.randomizer).fitness,
+            brain: self.template.clone(),
+        }
+    }
+
+    fn generation(&mut self) -> [crate::Specimen; 2] {
+        self.counter += 1;
+        let mut rng = rand::thread_rng();
+        let mut best: Option<crate::Specimen> = None;
+        let mut noises = Vec::with_capacity(self.population_size / 2);
+        let mut fitness = Vec::with_capacity(self.population_size);
+
+        for _ in 0..self.population_size / 2 {
+            let noise: Vec<f64> = (0..self.mean.len())
+                .map(|_| StandardNormal.sample(&mut rng))
+                .collect();
+            for sign in &[1.0, -1.0] {
+                let weights: Vec<f64> = self
+                    .mean
+                    .iter()
+                    .zip(noise.iter())
+                    .map(|(m, n)| m + sign * self.sigma * n)
+                    .collect();
+                let candidate = self.specimen_at(&weights);
+                fitness.push(candidate.fitness);
+                if best
+                    .as_ref()
+                    .map_or(true, |b| candidate.fitness > b.fitness)
+                {
+                    best = Some(candidate);
+                }
+            }
+            noises.push(noise);
+        }
+
+        let ranks = centered_ranks(&fitness);
+        let scale = 1.0 / (self.population_size as f64 * self.sigma);
+        let mut gradient = vec![0.0; self.mean.len()];
+        noises.iter().enumerate().for_each(|(pair, noise)| {
+            let difference = ranks[pair * 2] - ranks[pair * 2 + 1];
+            gradient
+                .iter_mut()
+                .zip(noise.iter())
+                .for_each(|(g, n)| *g += scale * difference * n);
+        });
+        self.ascend(&gradient);
+
+        let mean = self.mean.clone();
+        let mean = self.specimen_at(&mean);
+        let best = best.expect("Empty population");
+        if mean.fitness >= best.fitness {
+            [mean, best]
+        } else {
+            [best, mean]
+        }
+    }
+
+    /// Optimizers descend, so the fitness gradient is negated.
+    fn ascend(&mut self, gradient: &[f64]) {
+        let descent: Vec<f64> = gradient.iter().map(|g| -g).collect();
+        self.optimizer.step(&mut self.mean, &descent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evolution_strategies::{centered_ranks, EvolutionStrategies};
+    use crate::optimizer::{Adam, Sgd};
+    use crate::randomizer::DefaultRandomizer;
+    use crate::simulating_world::{assert_reaches_target, TargetWorld};
+    use crate::simulation::Finish;
+
+    #[test]
+    fn ranks_are_centered() {
+        let ranks = centered_ranks(&[10.0, -3.0, 7.0, 0.5]);
+        [0.5, -0.5, 1.0 / 6.0, -1.0 / 6.0]
+            .iter()
+            .zip(ranks.iter())
+            .for_each(|(expected, rank)| assert!(relative_eq!(expected, rank)));
+    }
+
+    #[test]
+    fn odd_population_is_rejected() {
+        let mut randomizer = DefaultRandomizer::new();
+        assert!(
+            EvolutionStrategies::<TargetWorld>::new(7, &[2, 1], &mut randomizer, None).is_err()
+        );
+    }
+
+    #[test]
+    fn adam_converges_towards_target() {
+        let mut randomizer = DefaultRandomizer::new();
+        let mut trainer =
+            EvolutionStrategies::<TargetWorld>::new(20, &[2, 2, 1], &mut randomizer, None)
+                .unwrap()
+                .with_optimizer(Adam::new(0.05));
+        let best = trainer.run(Finish::Occurences(100)).unwrap();
+        assert_eq!(trainer.get_number_of_iterations(), 100);
+        assert_reaches_target(&best, 1e-2);
+    }
+
+    #[test]
+    fn sgd_improves_mean() {
+        let mut randomizer = DefaultRandomizer::new();
+        let mut trainer =
+            EvolutionStrategies::<TargetWorld>::new(20, &[2, 2, 1], &mut randomizer, None)
+                .unwrap()
+                .with_optimizer(Sgd::new(0.02).with_momentum(0.9));
+        let initial = trainer.mean.clone();
+        let initial = trainer.specimen_at(&initial).fitness;
+        trainer.run(Finish::Occurences(100)).unwrap();
+        let trained = trainer.mean.clone();
+        assert!(trainer.specimen_at(&trained).fitness > initial);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::randomizer::RandomProvider;
+use crate::regularization::Regularization;
 use rand::Rng;
 
 fn should_mutate(rng: &mut rand::rngs::ThreadRng, probability: f64) -> bool {
@@ -14,23 +15,33 @@ pub(crate) fn crossover(parents: &[crate::Specimen; 2]) -> [crate::Specimen; 2]
             &mut offspring_2.brain.neurons[i],
         );
     }
+    // Weights shared by the layers (e.g. convolution kernels) follow the same rule
+    let crossover_point = parents[0].brain.parameters.len() / 2;
+    for i in 0..crossover_point {
+        std::mem::swap(
+            &mut offspring_1.brain.parameters[i],
+            &mut offspring_2.brain.parameters[i],
+        );
+    }
     [offspring_1, offspring_2]
 }
 
+/// Replaces random weights with new numbers of the randomizer, then keeps
+/// the offspring within the constraints of the `regularization`.
 pub(crate) fn mutate(
     mut parents: [crate::Specimen; 2],
     randomizer: &mut dyn RandomProvider,
     mutation_probability: f64,
+    regularization: &Regularization,
 ) -> [crate::Specimen; 2] {
     let mut uniform_randomizer = rand::thread_rng();
     parents.iter_mut().for_each(|parent| {
-        parent.brain.neurons.iter_mut().for_each(|neuron| {
-            neuron.inputs.iter_mut().for_each(|input| {
-                if should_mutate(&mut uniform_randomizer, mutation_probability) {
-                    *input = randomizer.get_number();
-                }
-            })
+        parent.brain.weights_mut().for_each(|weight| {
+            if should_mutate(&mut uniform_randomizer, mutation_probability) {
+                *weight = randomizer.get_number();
+            }
         });
+        regularization.constrain(&mut parent.brain);
     });
 
     parents
@@ -42,7 +53,9 @@ mod tests {
     use crate::network::NetworkLayout;
     use crate::neuron::Neuron;
     use crate::randomizer::RandomProvider;
+    use crate::regularization::Regularization;
 
+    #[allow(clippy::needless_lifetimes)]
     fn create_test_pops<'a>(
         neurons: usize,
         inputs: usize,
@@ -58,6 +71,7 @@ mod tests {
                     .take(neurons)
                     .collect(),
                     layers: vec![],
+                    ..NetworkLayout::default()
                 },
             },
             crate::Specimen {
@@ -69,6 +83,7 @@ mod tests {
                     .take(neurons)
                     .collect(),
                     layers: vec![],
+                    ..NetworkLayout::default()
                 },
             },
         )
@@ -125,6 +140,7 @@ mod tests {
         }
         let mut randomizer = TestRandomizer { current: 100.0 };
 
+        #[allow(dead_code)]
         pub(crate) struct MutationRandomizer {
             current: f64,
         }
@@ -144,7 +160,12 @@ mod tests {
         const MUTATION_PROBABILITY: f64 = 0.5;
         let (pop1, pop2) = create_test_pops(NEURON_COUNT, INPUT_COUNT, &mut randomizer);
 
-        let mutated = mutate([pop1, pop2], &mut mutation_randomizer, MUTATION_PROBABILITY);
+        let mutated = mutate(
+            [pop1, pop2],
+            &mut mutation_randomizer,
+            MUTATION_PROBABILITY,
+            &Regularization::default(),
+        );
         let mut counter = 0;
         mutated.iter().for_each(|pop| {
             pop.brain.neurons.iter().for_each(|neuron| {
@@ -164,4 +185,36 @@ mod tests {
             epsilon = TOLERANCE
         ))
     }
+
+    #[test]
+    fn mutate_covers_gates_and_kernels() {
+        pub(crate) struct TestRandomizer {
+            current: f64,
+        }
+        impl RandomProvider for TestRandomizer {
+            fn get_number(&mut self) -> f64 {
+                self.current
+            }
+        }
+        let mut randomizer = TestRandomizer { current: 1.0 };
+        let (mut pop1, pop2) = create_test_pops(3, 2, &mut randomizer);
+        pop1.brain
+            .neurons
+            .iter_mut()
+            .for_each(|neuron| neuron.gates = vec![vec![1.0; 2], vec![1.0]]);
+
+        pop1.brain.parameters = vec![vec![], vec![1.0; 4]];
+
+        let mut mutation_randomizer = TestRandomizer { current: -1.0 };
+        let [mutated, _] = mutate(
+            [pop1, pop2],
+            &mut mutation_randomizer,
+            1.0,
+            &Regularization::default(),
+        );
+        mutated.brain.neurons.iter().for_each(|neuron| {
+            assert_eq!(neuron.gates, vec![vec![-1.0; 2], vec![-1.0]]);
+        });
+        assert_eq!(mutated.brain.parameters[1], vec![-1.0; 4]);
+    }
 }
@@ -0,0 +1,238 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::SQRT_2;
+
+use crate::randomizer::RandomProvider;
+
+/// Scheme used to draw the initial weights of a layer.
+///
+/// Schemes are scaled by the fan-in (number of inputs of a neuron, biases
+/// included) and the fan-out (number of neurons of the layer). Numbers of the
+/// randomizer are expected to follow the standard normal distribution, like
+/// the ones of the [`DefaultRandomizer`](../randomizer/struct.DefaultRandomizer.html).
+/// Uniform schemes map them into the range with the normal cumulative distribution.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Initializer {
+    /// Numbers of the randomizer are used as they are.
+    #[default]
+    Randomizer,
+    /// Glorot uniform, `U(-l, l)` with `l = sqrt(6 / (fan_in + fan_out))`.
+    XavierUniform,
+    /// Glorot normal, `N(0, 2 / (fan_in + fan_out))`.
+    XavierNormal,
+    /// Kaiming uniform, `U(-l, l)` with `l = sqrt(6 / fan_in)`. Suits ReLU.
+    HeUniform,
+    /// Kaiming normal, `N(0, 2 / fan_in)`. Suits ReLU.
+    HeNormal,
+    /// `U(-l, l)` with `l = sqrt(3 / fan_in)`.
+    LeCunUniform,
+    /// `N(0, 1 / fan_in)`.
+    LeCunNormal,
+    /// Weights of the layer form a (semi-)orthogonal matrix.
+    Orthogonal,
+    /// Every weight has the same value.
+    Constant(f64),
+    /// Weights are drawn uniformly from `[low, high)`.
+    Uniform { low: f64, high: f64 },
+}
+
+impl Initializer {
+    /// Draws the weights of a layer with `fan_out` neurons, `fan_in` weights each.
+    pub fn initialize(
+        self,
+        fan_out: usize,
+        fan_in: usize,
+        randomizer: &mut dyn RandomProvider,
+    ) -> Vec<Vec<f64>> {
+        if self == Initializer::Orthogonal {
+            return orthogonal(fan_out, fan_in, randomizer);
+        }
+        (0..fan_out)
+            .map(|_| {
+                (0..fan_in)
+                    .map(|_| self.sample(fan_out as f64, fan_in as f64, randomizer))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn sample(self, fan_out: f64, fan_in: f64, randomizer: &mut dyn RandomProvider) -> f64 {
+        let number = randomizer.get_number();
+        let uniform = |limit: f64| limit * (2.0 * normal_distribution(number) - 1.0);
+        match self {
+            Initializer::XavierUniform => uniform((6.0 / (fan_in + fan_out)).sqrt()),
+            Initializer::XavierNormal => number * (2.0 / (fan_in + fan_out)).sqrt(),
+            Initializer::HeUniform => uniform((6.0 / fan_in).sqrt()),
+            Initializer::HeNormal => number * (2.0 / fan_in).sqrt(),
+            Initializer::LeCunUniform => uniform((3.0 / fan_in).sqrt()),
+            Initializer::LeCunNormal => number * (1.0 / fan_in).sqrt(),
+            Initializer::Constant(value) => value,
+            Initializer::Uniform { low, high } => low + (high - low) * normal_distribution(number),
+            Initializer::Randomizer | Initializer::Orthogonal => number,
+        }
+    }
+}
+
+/// Cumulative distribution function of the standard normal distribution.
+fn normal_distribution(x: f64) -> f64 {
+    0.5 * (1.0 + erf(x / SQRT_2))
+}
+
+/// Error function, approximated with the formula 7.1.26
+/// of Abramowitz and Stegun (error below `1.5e-7`).
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let polynomial = t
+        * (0.254_829_592
+            + t * (-0.284_496_736
+                + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    (1.0 - polynomial * (-x * x).exp()).copysign(x)
+}
+
+/// Orthonormalises random vectors along the longer side of the matrix
+/// with the Gram-Schmidt process.
+fn orthogonal(rows: usize, columns: usize, randomizer: &mut dyn RandomProvider) -> Vec<Vec<f64>> {
+    let transposed = rows > columns;
+    let (count, length) = if transposed {
+        (columns, rows)
+    } else {
+        (rows, columns)
+    };
+    let mut vectors: Vec<Vec<f64>> = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut vector: Vec<f64> = (0..length).map(|_| randomizer.get_number()).collect();
+        for previous in &vectors {
+            let dot: f64 = vector.iter().zip(previous).map(|(a, b)| a * b).sum();
+            vector
+                .iter_mut()
+                .zip(previous)
+                .for_each(|(value, p)| *value -= dot * p);
+        }
+        let norm = vector.iter().map(|value| value * value).sum::<f64>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|value| *value /= norm);
+        }
+        vectors.push(vector);
+    }
+    if transposed {
+        (0..rows)
+            .map(|row| vectors.iter().map(|vector| vector[row]).collect())
+            .collect()
+    } else {
+        vectors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::initializer::{erf, Initializer};
+    use crate::network::{LayerKind, NetworkBuilder};
+    use crate::randomizer::{DefaultRandomizer, RandomProvider};
+
+    #[test]
+    fn schemes_follow_fan_in_and_fan_out() {
+        assert!(relative_eq!(erf(0.5), 0.520_499_877_8, epsilon = 1e-6));
+        assert!(relative_eq!(erf(-1.5), -0.966_105_146_5, epsilon = 1e-6));
+
+        let mut randomizer = DefaultRandomizer::new();
+        let limit = (6.0f64 / 300.0).sqrt();
+        let weights = Initializer::XavierUniform.initialize(100, 200, &mut randomizer);
+        assert_eq!((weights.len(), weights[0].len()), (100, 200));
+        let all: Vec<f64> = weights.concat();
+        assert!(all.iter().all(|w| w.abs() < limit));
+        // Variance of U(-l, l) is l^2 / 3
+        let variance = all.iter().map(|w| w * w).sum::<f64>() / all.len() as f64;
+        assert!(relative_eq!(
+            variance,
+            limit * limit / 3.0,
+            max_relative = 0.05
+        ));
+
+        let he: Vec<f64> = Initializer::HeNormal
+            .initialize(50, 400, &mut randomizer)
+            .concat();
+        let variance = he.iter().map(|w| w * w).sum::<f64>() / he.len() as f64;
+        assert!(relative_eq!(variance, 2.0 / 400.0, max_relative = 0.05));
+
+        let uniform = Initializer::Uniform {
+            low: 2.0,
+            high: 3.0,
+        };
+        assert!(uniform
+            .initialize(10, 10, &mut randomizer)
+            .concat()
+            .iter()
+            .all(|w| (2.0..3.0).contains(w)));
+    }
+
+    #[test]
+    fn orthogonal_rows_and_columns() {
+        let mut randomizer = DefaultRandomizer::new();
+        let dot = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f64>();
+        let wide = Initializer::Orthogonal.initialize(3, 5, &mut randomizer);
+        for i in 0..3 {
+            for j in 0..3 {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!(relative_eq!(
+                    dot(&wide[i], &wide[j]),
+                    expected,
+                    epsilon = 1e-9
+                ));
+            }
+        }
+        let tall = Initializer::Orthogonal.initialize(5, 2, &mut randomizer);
+        let column = |c: usize| tall.iter().map(|row| row[c]).collect::<Vec<f64>>();
+        assert!(relative_eq!(
+            dot(&column(0), &column(0)),
+            1.0,
+            epsilon = 1e-9
+        ));
+        assert!(relative_eq!(
+            dot(&column(0), &column(1)),
+            0.0,
+            epsilon = 1e-9
+        ));
+    }
+
+    #[test]
+    fn builder_uses_initializer() {
+        let mut randomizer = DefaultRandomizer::new();
+        let net = NetworkBuilder::new()
+            .with_neurons_per_layer(&[3, 2, 1])
+            .with_randomizer(&mut randomizer)
+            .with_initializer(Initializer::Constant(0.25))
+            .build();
+        assert!(net.layout.get_weights().iter().all(|w| *w == 0.25));
+    }
+
+    #[test]
+    fn gates_are_scaled_by_their_fan_in() {
+        struct Two;
+        impl RandomProvider for Two {
+            fn get_number(&mut self) -> f64 {
+                2.0
+            }
+        }
+        let gates = |kind: LayerKind| -> Vec<Vec<f64>> {
+            let net = NetworkBuilder::new()
+                .with_neurons_per_layer(&[3, 2])
+                .with_randomizer(&mut Two)
+                .with_layer_kind(1, kind)
+                .with_initializer(Initializer::HeNormal)
+                .build();
+            net.layout.neurons[net.layout.layers[1][0]].gates.clone()
+        };
+        // Gates over the inputs (three plus the bias), then over the hidden state
+        let scaled = |fan_in: f64| 2.0 * (2.0 / fan_in).sqrt();
+        let gru = gates(LayerKind::Gru);
+        [4.0, 4.0, 2.0, 2.0, 2.0]
+            .iter()
+            .zip(gru.iter())
+            .for_each(|(fan_in, gate)| {
+                assert!(gate.iter().all(|w| relative_eq!(*w, scaled(*fan_in))))
+            });
+
+        let ctrnn = gates(LayerKind::Ctrnn { step: 0.1 });
+        assert!(ctrnn[0].iter().all(|w| relative_eq!(*w, scaled(2.0))));
+        assert_eq!(ctrnn[1], vec![2.0; 3]);
+    }
+}
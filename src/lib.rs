@@ -1,47 +1,126 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #[cfg(test)]
 #[macro_use]
 extern crate approx;
+extern crate alloc;
 
-use serde::Deserialize;
+/// Inference without the standard library.
+pub mod embedded;
 
-mod genetic;
-pub(crate) mod network;
-mod neuron;
+/// Marks the items as available with the standard library only.
+macro_rules! with_std {
+    ($($item:item)*) => {
+        $(
+            #[cfg(feature = "std")]
+            $item
+        )*
+    };
+}
 
-/// Randomizer implementation.
-pub mod randomizer;
+with_std! {
+    use serde::{Deserialize, Serialize};
 
-/// World used for training the network.
-pub mod simulating_world;
+    mod genetic;
+    mod neuron;
 
-/// Lerning routines.
-pub mod simulation;
+    /// Output heads turning the network outputs into actions.
+    pub mod action;
 
-/// Interfacing with `easyneural`.
-pub mod specimen;
+    /// Training with the Covariance Matrix Adaptation Evolution Strategy.
+    pub mod cma_es;
 
-/// Training ground for testing the trained network.
-pub mod training_ground;
+    /// Generating standalone Rust and C code of trained networks.
+    pub mod codegen;
 
-const BIAS_VALUE: f64 = 1.0;
-const MINIMUM_POPULATION_SIZE: usize = 4;
+    /// Loading and preprocessing of labelled data.
+    pub mod dataset;
 
-/// Specimen is used to exchange the neural network data
-/// with the outside world.
-///
-/// This is the struct you use for transferring the
-/// neural network instances to and from the `easyneural` crate.
-#[derive(Clone, Debug, Deserialize)]
-pub struct Specimen {
-    pub brain: network::NetworkLayout,
-    pub fitness: f64,
-}
+    /// Training with differential evolution.
+    pub mod differential_evolution;
+
+    /// Regression and classification metrics.
+    pub mod evaluation;
+
+    /// Training with OpenAI-style natural evolution strategies.
+    pub mod evolution_strategies;
+
+    /// Quality-diversity training with the MAP-Elites algorithm.
+    pub mod map_elites;
+
+    /// Weight initialisation schemes.
+    pub mod initializer;
+
+    /// Structure of the neural network.
+    pub mod network;
+
+    /// Exporting trained networks as ONNX models.
+    pub mod onnx;
+
+    /// Gradient based optimizers and learning rate schedules.
+    pub mod optimizer;
+
+    /// Training with particle swarm optimisation.
+    pub mod particle_swarm;
+
+    /// Removing unimportant connections and neurons of trained networks.
+    pub mod pruning;
+
+    /// Integer quantisation of trained networks for embedded inference.
+    pub mod quantization;
+
+    /// Randomizer implementation.
+    pub mod randomizer;
+
+    /// Weight penalties and constraints against overfitting.
+    pub mod regularization;
+
+    /// World used for training the network.
+    pub mod simulating_world;
+
+    /// Lerning routines.
+    pub mod simulation;
+
+    /// Interfacing with `easyneural`.
+    pub mod specimen;
+
+    /// Training with backpropagation on labelled samples.
+    pub mod supervised;
+
+    /// Growing and shrinking the layers of trained networks.
+    pub mod surgery;
+
+    /// Training ground for testing the trained network.
+    pub mod training_ground;
+
+    const BIAS_VALUE: f64 = 1.0;
+    const MINIMUM_POPULATION_SIZE: usize = 4;
+
+    /// Specimen is used to exchange the neural network data
+    /// with the outside world.
+    ///
+    /// This is the struct you use for transferring the
+    /// neural network instances to and from the `easyneural` crate.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Specimen {
+        pub brain: network::NetworkLayout,
+        pub fitness: f64,
+    }
+
+    impl Specimen {
+        /// Loads the network layout, panics if it is malformed.
+        pub fn from_json(j: &str) -> Self {
+            Specimen::try_from_json(j).unwrap()
+        }
 
-impl Specimen {
-    pub fn from_json(j: &str) -> Self {
-        Specimen {
-            fitness: 0.0,
-            brain: serde_json::from_str(j).unwrap(), // TODO: Add error checking
+        /// Loads the network layout, checking that its connections form no cycle.
+        pub fn try_from_json(j: &str) -> Result<Self, String> {
+            let mut brain: network::NetworkLayout =
+                serde_json::from_str(j).map_err(|e| e.to_string())?;
+            brain.validate()?;
+            Ok(Specimen {
+                fitness: 0.0,
+                brain,
+            })
         }
     }
 }
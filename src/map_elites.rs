@@ -0,0 +1,502 @@
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::genetic::mutate;
+use crate::network::NetworkBuilder;
+use crate::randomizer::RandomProvider;
+use crate::regularization::Regularization;
+use crate::simulating_world::SimulatingWorld;
+use crate::simulation::{evaluate, Finish, DEFAULT_MUTATION_PROBABILITY};
+
+/// Single, discretised dimension of the behaviour space.
+///
+/// Values reported by [`SimulatingWorld::get_behaviour_descriptor`](../simulating_world/trait.SimulatingWorld.html#method.get_behaviour_descriptor)
+/// are clamped to `[min, max]` and split into `bins` equally sized cells.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Dimension {
+    pub min: f64,
+    pub max: f64,
+    pub bins: usize,
+}
+
+impl Dimension {
+    fn bin(&self, value: f64) -> usize {
+        let normalized = (value.clamp(self.min, self.max) - self.min) / (self.max - self.min);
+        ((normalized * self.bins as f64) as usize).min(self.bins - 1)
+    }
+}
+
+/// Summary of the archive contents.
+#[derive(Clone, Debug)]
+pub struct ArchiveStatistics {
+    /// Number of cells that hold an elite.
+    pub filled_cells: usize,
+    /// Total number of cells in the grid.
+    pub total_cells: usize,
+    /// Fraction of filled cells, from range `[0.0, 1.0]`.
+    pub coverage: f64,
+    /// Sum of the fitness of all elites.
+    pub qd_score: f64,
+    /// Fitness of the best elite, if there is any.
+    pub best_fitness: Option<f64>,
+}
+
+/// Grid of elites, indexed by the discretised behaviour descriptor.
+///
+/// Each cell holds the best specimen found so far that exhibited
+/// the behaviour falling into that cell.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Archive {
+    dimensions: Vec<Dimension>,
+    cells: BTreeMap<usize, crate::Specimen>,
+}
+
+impl Archive {
+    /// Creates an empty archive spanning the specified behaviour dimensions.
+    pub fn new(dimensions: Vec<Dimension>) -> Result<Archive, String> {
+        if dimensions.is_empty() {
+            return Err("Archive needs at least one behaviour dimension".to_string());
+        }
+        if let Some(dimension) = dimensions
+            .iter()
+            .find(|dimension| dimension.bins == 0 || dimension.max <= dimension.min)
+        {
+            return Err(format!("Invalid behaviour dimension: {:?}", dimension));
+        }
+        Ok(Archive {
+            dimensions,
+            cells: BTreeMap::new(),
+        })
+    }
+
+    /// Restores the archive previously saved with [`to_json`](#method.to_json).
+    pub fn from_json(j: &str) -> Result<Archive, String> {
+        let mut archive: Archive = serde_json::from_str(j).map_err(|e| e.to_string())?;
+        Archive::new(archive.dimensions.clone())?;
+        let number_of_cells = archive.get_number_of_cells();
+        for (index, elite) in archive.cells.iter_mut() {
+            if *index >= number_of_cells {
+                return Err(format!("Archive has no cell {}", index));
+            }
+            elite.brain.validate()?;
+        }
+        Ok(archive)
+    }
+
+    /// Serializes the archive, including all of its elites.
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string(self).map_err(|e| e.to_string())
+    }
+
+    /// Returns the index of the cell that corresponds to the given
+    /// behaviour descriptor.
+    pub fn get_cell_index(&self, behaviour: &[f64]) -> Result<usize, String> {
+        if behaviour.len() != self.dimensions.len() {
+            return Err(format!(
+                "Behaviour descriptor has {} dimensions, archive expects {}",
+                behaviour.len(),
+                self.dimensions.len()
+            ));
+        }
+        Ok(self
+            .dimensions
+            .iter()
+            .zip(behaviour.iter())
+            .fold(0, |index, (dimension, value)| {
+                index * dimension.bins + dimension.bin(*value)
+            }))
+    }
+
+    /// Puts the specimen into the archive if its cell is empty or if it
+    /// outperforms the current elite. Returns `true` if the specimen was stored.
+    pub fn try_insert(
+        &mut self,
+        specimen: crate::Specimen,
+        behaviour: &[f64],
+    ) -> Result<bool, String> {
+        let index = self.get_cell_index(behaviour)?;
+        match self.cells.get(&index) {
+            Some(elite) if elite.fitness >= specimen.fitness => Ok(false),
+            _ => {
+                self.cells.insert(index, specimen);
+                Ok(true)
+            }
+        }
+    }
+
+    /// Returns the elite stored in the cell with given index.
+    pub fn get(&self, index: usize) -> Option<&crate::Specimen> {
+        self.cells.get(&index)
+    }
+
+    /// Iterates over all elites, together with the indices of their cells.
+    pub fn elites(&self) -> impl Iterator<Item = (usize, &crate::Specimen)> {
+        self.cells
+            .iter()
+            .map(|(index, specimen)| (*index, specimen))
+    }
+
+    /// Returns the best elite across the whole archive.
+    pub fn get_best(&self) -> Option<&crate::Specimen> {
+        self.cells.values().max_by(|a, b| {
+            a.fitness
+                .partial_cmp(&b.fitness)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+    }
+
+    /// Total number of cells in the grid.
+    pub fn get_number_of_cells(&self) -> usize {
+        self.dimensions
+            .iter()
+            .map(|dimension| dimension.bins)
+            .product()
+    }
+
+    /// Calculates the coverage and QD-score of the archive.
+    pub fn get_statistics(&self) -> ArchiveStatistics {
+        let total_cells = self.get_number_of_cells();
+        ArchiveStatistics {
+            filled_cells: self.cells.len(),
+            total_cells,
+            coverage: self.cells.len() as f64 / total_cells as f64,
+            qd_score: self.cells.values().map(|elite| elite.fitness).sum(),
+            best_fitness: self.get_best().map(|elite| elite.fitness),
+        }
+    }
+}
+
+/// Trainer that fills the [`Archive`](struct.Archive.html) with
+/// diverse, high performing specimen.
+///
+/// The first generation is random. Each subsequent generation is created by
+/// mutating elites picked at random from the archive.
+pub struct MapElites<'a, T: SimulatingWorld> {
+    archive: Archive,
+    neurons_per_layer: Vec<usize>,
+    population_size: usize,
+    randomizer: &'a mut dyn RandomProvider,
+    mutation_probability: f64,
+    regularization: Regularization,
+    counter: usize,
+    world: PhantomData<T>,
+}
+
+impl<'a, T: SimulatingWorld> MapElites<'a, T> {
+    /// Creates new MAP-Elites trainer.
+    ///
+    /// `population_size` is the number of candidates evaluated in every
+    /// generation. `mutation_probability` is represented as float number
+    /// from range `[0.0, 1.0)`.
+    pub fn new(
+        population_size: usize,
+        neurons_per_layer: &[usize],
+        dimensions: Vec<Dimension>,
+        randomizer: &'a mut dyn RandomProvider,
+        mutation_probability: Option<f64>,
+    ) -> Result<MapElites<'a, T>, String> {
+        Self::with_archive(
+            population_size,
+            neurons_per_layer,
+            Archive::new(dimensions)?,
+            randomizer,
+            mutation_probability,
+        )
+    }
+
+    /// Creates new MAP-Elites trainer that continues to fill
+    /// already existing archive.
+    pub fn with_archive(
+        population_size: usize,
+        neurons_per_layer: &[usize],
+        archive: Archive,
+        randomizer: &'a mut dyn RandomProvider,
+        mutation_probability: Option<f64>,
+    ) -> Result<MapElites<'a, T>, String> {
+        if population_size == 0 || population_size % 2 != 0 {
+            return Err("Population size must be a positive, even number".to_string());
+        }
+
+        Ok(MapElites {
+            archive,
+            neurons_per_layer: neurons_per_layer.to_vec(),
+            population_size,
+            randomizer,
+            mutation_probability: mutation_probability.unwrap_or(DEFAULT_MUTATION_PROBABILITY),
+            regularization: Regularization::default(),
+            counter: 0,
+            world: PhantomData,
+        })
+    }
+
+    /// Subtracts weight penalties from the fitness of each candidate and
+    /// constrains the weights of the offspring after the mutation.
+    pub fn with_regularization(mut self, regularization: Regularization) -> Result<Self, String> {
+        regularization.validate()?;
+        self.regularization = regularization;
+        Ok(self)
+    }
+
+    /// Runs the learning round.
+    ///
+    /// Returns the statistics of the archive after the most recent generation.
+    pub fn run(&mut self, finish: Finish) -> Result<ArchiveStatistics, String> {
+        match finish {
+            Finish::Occurences(count) => {
+                for _ in 0..count {
+                    self.generation()?;
+                }
+                Ok(self.archive.get_statistics())
+            }
+            _ => Err("Simulation end trigger not supported yet".to_string()),
+        }
+    }
+
+    /// Returns the archive of elites collected so far.
+    pub fn get_archive(&self) -> &Archive {
+        &self.archive
+    }
+
+    /// Returns number of generations used in recent learning session.
+    pub fn get_number_of_iterations(&self) -> usize {
+        self.counter
+    }
+
+    fn random_specimen(&mut self) -> crate::Specimen {
+        crate::Specimen {
+            brain: NetworkBuilder::new()
+                .with_neurons_per_layer(&self.neurons_per_layer)
+                .with_randomizer(self.randomizer)
+                .build()
+                .layout,
+            fitness: 0.0,
+        }
+    }
+
+    fn random_elite(&self, rng: &mut rand::rngs::ThreadRng) -> crate::Specimen {
+        let index = rng.gen_range(0, self.archive.cells.len());
+        self.archive
+            .cells
+            .values()
+            .nth(index)
+            .expect("Elite vanished from the archive")
+            .clone()
+    }
+
+    fn spawn_candidates(&mut self) -> Vec<crate::Specimen> {
+        if self.archive.cells.is_empty() {
+            return (0..self.population_size)
+                .map(|_| self.random_specimen())
+                .collect();
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut candidates = Vec::with_capacity(self.population_size);
+        while candidates.len() < self.population_size {
+            let parents = [self.random_elite(&mut rng), self.random_elite(&mut rng)];
+            candidates.extend_from_slice(&mutate(
+                parents,
+                self.randomizer,
+                self.mutation_probability,
+                &self.regularization,
+            ));
+        }
+        candidates
+    }
+
+    fn generation(&mut self) -> Result<(), String> {
+        self.counter += 1;
+        for mut candidate in self.spawn_candidates() {
+            let episode = evaluate::<T>(&candidate.brain, self.randomizer);
+            candidate.fitness = episode.fitness - self.regularization.penalty(&candidate.brain);
+            self.archive.try_insert(candidate, &episode.behaviour)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::map_elites::{Archive, Dimension, MapElites};
+    use crate::network::NetworkLayout;
+    use crate::randomizer::DefaultRandomizer;
+    use crate::regularization::Regularization;
+    use crate::simulating_world::SimulatingWorld;
+    use crate::simulation::{evaluate, Finish, SimulationStatus};
+    use crate::specimen::SpecimenStatus;
+
+    fn empty_specimen(fitness: f64) -> crate::Specimen {
+        crate::Specimen {
+            brain: NetworkLayout {
+                neurons: vec![],
+                layers: vec![],
+                ..NetworkLayout::default()
+            },
+            fitness,
+        }
+    }
+
+    fn test_dimensions() -> Vec<Dimension> {
+        vec![
+            Dimension {
+                min: 0.0,
+                max: 1.0,
+                bins: 4,
+            },
+            Dimension {
+                min: -1.0,
+                max: 1.0,
+                bins: 2,
+            },
+        ]
+    }
+
+    struct OutputWorld {
+        output: f64,
+    }
+    impl SimulatingWorld for OutputWorld {
+        fn new() -> OutputWorld {
+            OutputWorld { output: 0.0 }
+        }
+        fn tick(&mut self, input: &[f64]) -> SimulationStatus {
+            self.output = input[0];
+            SimulationStatus {
+                specimen_status: SpecimenStatus::DEAD(1.0 - (self.output - 0.5).abs()),
+                current_tick: 1,
+            }
+        }
+        fn get_world_state(&self) -> Vec<f64> {
+            vec![0.3, -0.7]
+        }
+        fn get_behaviour_descriptor(&self) -> Vec<f64> {
+            vec![self.output]
+        }
+    }
+
+    #[test]
+    fn cell_indexing() {
+        let archive = Archive::new(test_dimensions()).unwrap();
+        assert_eq!(archive.get_number_of_cells(), 8);
+        assert_eq!(archive.get_cell_index(&[0.0, -1.0]), Ok(0));
+        assert_eq!(archive.get_cell_index(&[0.0, 1.0]), Ok(1));
+        assert_eq!(archive.get_cell_index(&[0.3, 0.5]), Ok(3));
+        assert_eq!(archive.get_cell_index(&[7.0, 7.0]), Ok(7));
+        assert!(archive.get_cell_index(&[0.5]).is_err());
+    }
+
+    #[test]
+    fn only_better_specimen_replaces_elite() {
+        let mut archive = Archive::new(test_dimensions()).unwrap();
+        assert_eq!(
+            archive.try_insert(empty_specimen(2.0), &[0.1, 0.1]),
+            Ok(true)
+        );
+        assert_eq!(
+            archive.try_insert(empty_specimen(1.0), &[0.2, 0.2]),
+            Ok(false)
+        );
+        assert_eq!(
+            archive.try_insert(empty_specimen(3.0), &[0.2, 0.2]),
+            Ok(true)
+        );
+        assert_eq!(
+            archive.try_insert(empty_specimen(1.0), &[0.9, -0.9]),
+            Ok(true)
+        );
+
+        let statistics = archive.get_statistics();
+        assert_eq!(statistics.filled_cells, 2);
+        assert!(relative_eq!(statistics.coverage, 0.25));
+        assert!(relative_eq!(statistics.qd_score, 4.0));
+        assert!(relative_eq!(statistics.best_fitness.unwrap(), 3.0));
+    }
+
+    #[test]
+    fn archive_serialization() {
+        let mut archive = Archive::new(test_dimensions()).unwrap();
+        archive
+            .try_insert(empty_specimen(5.0), &[0.6, 0.6])
+            .unwrap();
+        let restored = Archive::from_json(&archive.to_json().unwrap()).unwrap();
+        let index = archive.get_cell_index(&[0.6, 0.6]).unwrap();
+        assert!(relative_eq!(restored.get(index).unwrap().fitness, 5.0));
+        assert_eq!(
+            restored.get_number_of_cells(),
+            archive.get_number_of_cells()
+        );
+
+        let json = archive.to_json().unwrap();
+        let empty_dimension = json.replacen("\"bins\":4", "\"bins\":0", 1);
+        assert_ne!(empty_dimension, json);
+        assert!(Archive::from_json(&empty_dimension).is_err());
+        let missing_cell = json.replacen(&format!("\"{}\":", index), "\"1000\":", 1);
+        assert!(Archive::from_json(&missing_cell).is_err());
+    }
+
+    #[test]
+    fn run_fills_archive() {
+        let mut randomizer = DefaultRandomizer::new();
+        let mut trainer = MapElites::<OutputWorld>::new(
+            10,
+            &[2, 3, 1],
+            vec![Dimension {
+                min: 0.0,
+                max: 1.0,
+                bins: 10,
+            }],
+            &mut randomizer,
+            Some(0.3),
+        )
+        .unwrap();
+        let statistics = trainer.run(Finish::Occurences(5)).unwrap();
+        assert_eq!(trainer.get_number_of_iterations(), 5);
+        assert!(statistics.filled_cells > 0);
+        assert_eq!(
+            statistics.filled_cells,
+            trainer.get_archive().elites().count()
+        );
+    }
+
+    #[test]
+    fn offspring_are_regularized() {
+        let regularization = Regularization {
+            l2: 0.01,
+            max_norm: Some(0.5),
+            ..Regularization::default()
+        };
+        let mut randomizer = DefaultRandomizer::new();
+        let mut trainer = MapElites::<OutputWorld>::new(
+            10,
+            &[2, 3, 1],
+            vec![Dimension {
+                min: 0.0,
+                max: 1.0,
+                bins: 10,
+            }],
+            &mut randomizer,
+            Some(0.3),
+        )
+        .unwrap()
+        .with_regularization(regularization)
+        .unwrap();
+        trainer.run(Finish::Occurences(3)).unwrap();
+        trainer.get_archive().elites().for_each(|(_, elite)| {
+            let fitness =
+                evaluate::<OutputWorld>(&elite.brain, &mut DefaultRandomizer::new()).fitness;
+            assert!(relative_eq!(
+                elite.fitness,
+                fitness - regularization.penalty(&elite.brain)
+            ));
+        });
+
+        for candidate in trainer.spawn_candidates() {
+            candidate.brain.neurons.iter().for_each(|neuron| {
+                let norm = neuron.inputs.iter().map(|w| w * w).sum::<f64>().sqrt();
+                assert!(norm <= 0.5 + 1e-9);
+            });
+        }
+    }
+}
@@ -1,21 +1,659 @@
+use crate::action::{Action, OutputHead};
+use crate::initializer::Initializer;
 use crate::neuron::Neuron;
 use crate::randomizer::RandomProvider;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// Added to the variance by the normalisation layers to avoid division by zero.
+const NORMALIZATION_EPSILON: f64 = 1e-5;
+
+/// Activation function applied to the neurons of the network.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Activation {
+    #[default]
+    Sigmoid,
+    Tanh,
+    Relu,
+    Identity,
+}
+
+impl Activation {
+    pub(crate) fn function(self) -> fn(f64) -> f64 {
+        match self {
+            Activation::Sigmoid => NetworkBuilder::get_default_activator(),
+            Activation::Tanh => f64::tanh,
+            Activation::Relu => |x| x.max(0.0),
+            Activation::Identity => |x| x,
+        }
+    }
+
+    /// Calculates the derivative, expressed in terms of the activated value.
+    pub(crate) fn derivative(self, output: f64) -> f64 {
+        match self {
+            Activation::Sigmoid => output * (1.0 - output),
+            Activation::Tanh => 1.0 - output * output,
+            Activation::Relu => {
+                if output > 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Activation::Identity => 1.0,
+        }
+    }
+}
+
+/// Kind of computation performed by the neurons of a layer.
+///
+/// Recurrent layers remember their state between the ticks of an episode,
+/// so the network can integrate information over time. The state is reset
+/// at the start of each episode.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum LayerKind {
+    /// Plain feed-forward layer.
+    #[default]
+    Dense,
+    /// Each neuron also receives its own output from the previous tick,
+    /// weighted by a single recurrent weight.
+    Elman,
+    /// Gated recurrent unit. Gates use sigmoid and the candidate state
+    /// uses tanh, regardless of the activation of the network.
+    Gru,
+    /// Long short-term memory cell. Gates use sigmoid, the cell input
+    /// and output use tanh, regardless of the activation of the network.
+    Lstm,
+    /// Continuous-time recurrent neurons, fully connected within the layer.
+    ///
+    /// Each neuron integrates `tau * dy/dt = -y + inputs + recurrent` using
+    /// a single Euler step of length `step` per tick, and outputs
+    /// `activation(gain * (y + bias))`. Time constant, gain and bias are
+    /// evolvable genes of each neuron; the time constant is stored as
+    /// its natural logarithm, so it always stays positive.
+    ///
+    /// The Euler step is clamped to `min(step / tau, 1.0)`: a neuron whose
+    /// time constant evolves below `step` jumps straight to its equilibrium
+    /// instead of overshooting it, i.e. it behaves as if `tau` was `step`.
+    Ctrnn { step: f64 },
+    /// One dimensional convolution over the width of the source layer.
+    /// Each of the `filters` shares a single kernel (plus bias) across all positions.
+    Conv1d {
+        filters: usize,
+        kernel: usize,
+        stride: usize,
+    },
+    /// Two dimensional convolution with square kernels.
+    Conv2d {
+        filters: usize,
+        kernel: usize,
+        stride: usize,
+    },
+    /// Maximum over non-overlapping windows of each channel. Windows are
+    /// `size` wide for one dimensional sources and `size` x `size` otherwise.
+    MaxPool { size: usize },
+    /// Average over non-overlapping windows, see [`MaxPool`](#variant.MaxPool).
+    AveragePool { size: usize },
+    /// Copies the source layer, turning its shape into a plain vector.
+    Flatten,
+    /// Copies the source layer, keeping its shape. During the supervised
+    /// training each value is zeroed with probability `rate` and the remaining
+    /// ones are scaled by `1 / (1 - rate)`. Does nothing when the network is
+    /// evaluated, which includes the fitness evaluation of all trainers.
+    Dropout { rate: f64 },
+    /// Normalises the values of the source layer to zero mean and unit
+    /// variance, then applies learned scale and shift to each of them.
+    LayerNorm,
+    /// Normalises each value of the source layer with its running mean and
+    /// variance, then applies learned scale and shift.
+    ///
+    /// The network is fired one sample at a time, so the statistics are
+    /// exponential moving averages (decayed by `momentum`) updated with each
+    /// sample during the supervised training. They are frozen when the network
+    /// is evaluated, which includes the fitness evaluation of all trainers.
+    BatchNorm { momentum: f64 },
+}
+
+impl LayerKind {
+    /// Returns the lengths of the gate weight vectors of a single neuron.
+    ///
+    /// GRU: update and reset gates over the inputs, then update, reset and
+    /// candidate over the hidden state. LSTM: input, forget and output gates
+    /// over the inputs, then the same gates and the cell input over the hidden state.
+    /// CTRNN: recurrent weights, then the time constant, gain and bias.
+    fn get_gate_sizes(self, inputs: usize, width: usize) -> Vec<usize> {
+        match self {
+            LayerKind::Dense => vec![],
+            LayerKind::Elman => vec![1],
+            LayerKind::Gru => vec![inputs, inputs, width, width, width],
+            LayerKind::Lstm => vec![inputs, inputs, inputs, width, width, width, width],
+            LayerKind::Ctrnn { .. } => vec![width, 3],
+            _ => vec![],
+        }
+    }
+
+    /// Returns `false` for the gate holding the time constant, gain and bias
+    /// of CTRNN neurons, all other gates hold connection weights.
+    pub(crate) fn gate_holds_weights(self, gate: usize) -> bool {
+        !matches!(self, LayerKind::Ctrnn { .. }) || gate == 0
+    }
+
+    /// Returns `true` for the layers whose neurons are arranged by the [`Shape`](struct.Shape.html)
+    /// of the source layer, instead of having their own input weights.
+    pub(crate) fn is_spatial(self) -> bool {
+        matches!(
+            self,
+            LayerKind::Conv1d { .. }
+                | LayerKind::Conv2d { .. }
+                | LayerKind::MaxPool { .. }
+                | LayerKind::AveragePool { .. }
+                | LayerKind::Flatten
+                | LayerKind::Dropout { .. }
+                | LayerKind::LayerNorm
+                | LayerKind::BatchNorm { .. }
+        )
+    }
+
+    pub(crate) fn is_normalization(self) -> bool {
+        matches!(self, LayerKind::LayerNorm | LayerKind::BatchNorm { .. })
+    }
+
+    /// Returns kernel height, kernel width, vertical and horizontal stride.
+    fn get_window(self, input: Shape) -> (usize, usize, usize, usize) {
+        match self {
+            LayerKind::Conv1d { kernel, stride, .. } => (1, kernel, 1, stride),
+            LayerKind::Conv2d { kernel, stride, .. } => (kernel, kernel, stride, stride),
+            LayerKind::MaxPool { size } | LayerKind::AveragePool { size } => {
+                if input.height == 1 {
+                    (1, size, 1, size)
+                } else {
+                    (size, size, size, size)
+                }
+            }
+            _ => (1, 1, 1, 1),
+        }
+    }
+
+    /// Calculates the shape of a spatial layer reading from the `input` shape.
+    pub fn get_output_shape(self, input: Shape) -> Result<Shape, String> {
+        if let LayerKind::Dropout { rate } = self {
+            return if (0.0..1.0).contains(&rate) {
+                Ok(input)
+            } else {
+                Err("Dropout rate must be in range [0.0, 1.0)".to_string())
+            };
+        }
+        match self {
+            LayerKind::LayerNorm => return Ok(input),
+            LayerKind::BatchNorm { momentum } => {
+                return if (0.0..1.0).contains(&momentum) {
+                    Ok(input)
+                } else {
+                    Err("Momentum must be in range [0.0, 1.0)".to_string())
+                };
+            }
+            _ => {}
+        }
+        let (kernel_height, kernel_width, stride_y, stride_x) = self.get_window(input);
+        if kernel_height == 0 || kernel_width == 0 || stride_y == 0 || stride_x == 0 {
+            return Err("Kernel, pool size and stride must be positive".to_string());
+        }
+        if kernel_height > input.height || kernel_width > input.width {
+            return Err(format!(
+                "Window of {:?} exceeds the shape {:?}",
+                self, input
+            ));
+        }
+        let (height, width) = (
+            (input.height - kernel_height) / stride_y + 1,
+            (input.width - kernel_width) / stride_x + 1,
+        );
+        match self {
+            LayerKind::Conv1d { filters, .. } | LayerKind::Conv2d { filters, .. } => {
+                Ok(Shape::new_2d(filters, height, width))
+            }
+            LayerKind::MaxPool { .. } | LayerKind::AveragePool { .. } => {
+                Ok(Shape::new_2d(input.channels, height, width))
+            }
+            LayerKind::Flatten => Ok(Shape::new_1d(1, input.size())),
+            _ => Err(format!("{:?} layer has no spatial shape", self)),
+        }
+    }
+
+    /// Number of weights shared by the whole layer: kernels and their biases,
+    /// or scales followed by shifts of the normalisation layers.
+    fn get_number_of_parameters(self, input: Shape) -> usize {
+        let (kernel_height, kernel_width, ..) = self.get_window(input);
+        match self {
+            LayerKind::Conv1d { filters, .. } | LayerKind::Conv2d { filters, .. } => {
+                filters * (input.channels * kernel_height * kernel_width + 1)
+            }
+            LayerKind::LayerNorm | LayerKind::BatchNorm { .. } => 2 * input.size(),
+            _ => 0,
+        }
+    }
+
+    /// Number of running statistics kept by the layer: means followed by variances.
+    fn get_number_of_statistics(self, input: Shape) -> usize {
+        match self {
+            LayerKind::BatchNorm { .. } => 2 * input.size(),
+            _ => 0,
+        }
+    }
+
+    /// Normalises the values of a normalisation layer. Returns the normalised
+    /// values together with the inverse of the standard deviation used for each of them.
+    fn normalize(self, values: &[f64], statistics: &[f64]) -> (Vec<f64>, Vec<f64>) {
+        let size = values.len();
+        let (means, variances) = match self {
+            LayerKind::BatchNorm { .. } => {
+                (statistics[..size].to_vec(), statistics[size..].to_vec())
+            }
+            _ => {
+                let mean = values.iter().sum::<f64>() / size as f64;
+                let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / size as f64;
+                (vec![mean; size], vec![variance; size])
+            }
+        };
+        let inverse_deviations: Vec<f64> = variances
+            .iter()
+            .map(|variance| 1.0 / (variance + NORMALIZATION_EPSILON).sqrt())
+            .collect();
+        let normalized = values
+            .iter()
+            .zip(means.iter().zip(inverse_deviations.iter()))
+            .map(|(value, (mean, inverse_deviation))| (value - mean) * inverse_deviation)
+            .collect();
+        (normalized, inverse_deviations)
+    }
+}
+
+/// Arrangement of the neurons of a layer into channels of 2D grids
+/// (or 1D arrays, when `height` is `1`), stored channel after channel, row after row.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Shape {
+    pub channels: usize,
+    pub height: usize,
+    pub width: usize,
+}
+
+impl Shape {
+    pub fn new_1d(channels: usize, width: usize) -> Shape {
+        Shape {
+            channels,
+            height: 1,
+            width,
+        }
+    }
+
+    pub fn new_2d(channels: usize, height: usize, width: usize) -> Shape {
+        Shape {
+            channels,
+            height,
+            width,
+        }
+    }
+
+    /// Total number of values.
+    pub fn size(&self) -> usize {
+        self.channels * self.height * self.width
+    }
+}
+
+/// Predefined patterns of connections between the layers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Connectivity {
+    /// Each layer reads from the previous one only.
+    Sequential,
+    /// Each layer reads from the previous one and, like in ResNet,
+    /// also from the one before it (skip connection).
+    Residual,
+    /// Each layer reads from all the layers before it (DenseNet-like).
+    Dense,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct NetworkLayout {
     pub(crate) neurons: Vec<Neuron>,
     pub(crate) layers: Vec<Vec<usize>>,
+    #[serde(default)]
+    pub(crate) activation: Activation,
+    /// Kind of each layer. Missing entries stand for dense layers.
+    #[serde(default)]
+    pub(crate) layer_kinds: Vec<LayerKind>,
+    /// Indices of the layers each layer reads from. Inputs of a neuron are
+    /// the neurons of these layers, concatenated in the given order. Missing
+    /// or empty entries stand for the previous layer.
+    #[serde(default)]
+    pub(crate) sources: Vec<Vec<usize>>,
+    /// Shape of each layer. Missing entries stand for plain vectors.
+    #[serde(default)]
+    pub(crate) shapes: Vec<Shape>,
+    /// Weights shared by all neurons of a layer, e.g. convolution kernels.
+    #[serde(default)]
+    pub(crate) parameters: Vec<Vec<f64>>,
+    /// Turns the outputs into the action passed to the world.
+    #[serde(default)]
+    pub(crate) head: OutputHead,
+    /// Running statistics of the batch normalisation layers. These are not weights,
+    /// so they are neither evolved nor trained directly.
+    #[serde(default)]
+    pub(crate) statistics: Vec<Vec<f64>>,
+    /// Derived from the structure by [`validate`](#method.validate),
+    /// so that it is not recalculated each time the network is fired.
+    #[serde(skip)]
+    pub(crate) topology: Option<Topology>,
+}
+
+/// Sizes and firing order of a network consisting of dense layers only,
+/// as walked by the exporters.
+pub(crate) struct DenseLayout {
+    /// Sizes of the layers, excluding the bias neurons.
+    pub(crate) sizes: Vec<usize>,
+    /// Layers in the order they need to be fired, starting with the input layer.
+    pub(crate) order: Vec<usize>,
+}
+
+/// Order in which the layers are fired and the ids
+/// of the neurons each layer reads from.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Topology {
+    order: Vec<usize>,
+    inputs: Vec<Vec<usize>>,
+}
+
+impl NetworkLayout {
+    pub(crate) fn get_layer_sources(&self, layer_index: usize) -> Vec<usize> {
+        match self.sources.get(layer_index) {
+            Some(sources) if !sources.is_empty() => sources.clone(),
+            _ if layer_index == 0 => vec![],
+            _ => vec![layer_index - 1],
+        }
+    }
+
+    pub(crate) fn get_layer_shape(&self, layer_index: usize) -> Shape {
+        self.shapes.get(layer_index).copied().unwrap_or_else(|| {
+            Shape::new_1d(
+                1,
+                self.layers[layer_index]
+                    .iter()
+                    .filter(|neuron_id| !self.neurons[**neuron_id].bias)
+                    .count(),
+            )
+        })
+    }
+
+    fn get_layer_parameters(&self, layer_index: usize) -> &[f64] {
+        self.parameters
+            .get(layer_index)
+            .map_or(&[], |parameters| parameters)
+    }
+
+    fn get_layer_statistics(&self, layer_index: usize) -> &[f64] {
+        self.statistics
+            .get(layer_index)
+            .map_or(&[], |statistics| statistics)
+    }
+
+    /// Returns the position of the first shared weight of the layer
+    /// in the vector returned by [`get_weights`](#method.get_weights).
+    fn get_parameter_offset(&self, layer_index: usize) -> usize {
+        self.get_number_of_weights()
+            - self
+                .parameters
+                .iter()
+                .skip(layer_index)
+                .map(|p| p.len())
+                .sum::<usize>()
+    }
+
+    /// Returns ids of all neurons the neurons of the specified layer read from.
+    pub(crate) fn get_layer_inputs(&self, layer_index: usize) -> Vec<usize> {
+        self.get_layer_sources(layer_index)
+            .iter()
+            .flat_map(|source| self.layers[*source].iter().copied())
+            .collect()
+    }
+
+    /// Returns the indices of the layers in the order they need to be fired,
+    /// starting with the input layer.
+    pub(crate) fn get_evaluation_order(&self) -> Result<Vec<usize>, String> {
+        let count = self.layers.len();
+        if count == 0 {
+            return Ok(vec![]);
+        }
+        let sources: Vec<Vec<usize>> = (0..count).map(|i| self.get_layer_sources(i)).collect();
+        if let Some(source) = sources.iter().flatten().find(|source| **source >= count) {
+            return Err(format!("Connection from non-existent layer {}", source));
+        }
+        if !sources.first().map_or(true, |sources| sources.is_empty()) {
+            return Err("Input layer cannot have any sources".to_string());
+        }
+        if sources.iter().flatten().any(|source| *source == count - 1) {
+            return Err("Output layer cannot feed other layers".to_string());
+        }
+
+        let mut missing: Vec<usize> = sources.iter().map(|sources| sources.len()).collect();
+        let mut ready: Vec<usize> = (0..count).filter(|i| missing[*i] == 0).collect();
+        let mut order = Vec::with_capacity(count);
+        while let Some(layer_index) = ready.pop() {
+            order.push(layer_index);
+            for (consumer, sources) in sources.iter().enumerate() {
+                for _ in sources.iter().filter(|source| **source == layer_index) {
+                    missing[consumer] -= 1;
+                    if missing[consumer] == 0 {
+                        ready.push(consumer);
+                    }
+                }
+            }
+        }
+        if order.len() != count {
+            return Err("Connections between the layers contain a cycle".to_string());
+        }
+        if order.first() != Some(&0) {
+            return Err("Only the input layer may have no sources".to_string());
+        }
+        Ok(order)
+    }
+
+    /// Checks that the network can be exported and describes its layers.
+    ///
+    /// Only networks of at least two dense layers with finite weights are
+    /// supported. `operation` completes the error message, e.g. `"exported"`.
+    pub(crate) fn get_dense_layout(&self, operation: &str) -> Result<DenseLayout, String> {
+        if self.layers.len() < 2 {
+            return Err("Network needs at least the input and the output layer".to_string());
+        }
+        if (0..self.layers.len()).any(|i| self.get_layer_kind(i) != LayerKind::Dense) {
+            return Err(format!("Only dense layers can be {}", operation));
+        }
+        if self.get_weights().iter().any(|weight| !weight.is_finite()) {
+            return Err("Network has weights which are not finite".to_string());
+        }
+        Ok(DenseLayout {
+            sizes: (0..self.layers.len())
+                .map(|layer_index| self.get_layer_shape(layer_index).size())
+                .collect(),
+            order: self.get_evaluation_order()?,
+        })
+    }
+
+    fn get_topology(&self) -> Result<Topology, String> {
+        Ok(Topology {
+            order: self.get_evaluation_order()?,
+            inputs: (0..self.layers.len())
+                .map(|layer_index| self.get_layer_inputs(layer_index))
+                .collect(),
+        })
+    }
+
+    /// Checks that the layout, e.g. freshly deserialized, can be fired
+    /// and remembers its topology.
+    pub(crate) fn validate(&mut self) -> Result<(), String> {
+        self.topology = None;
+        if self
+            .layers
+            .iter()
+            .flatten()
+            .any(|neuron_id| *neuron_id >= self.neurons.len())
+        {
+            return Err("Layers refer to non-existent neurons".to_string());
+        }
+        if self.layers.first().map_or(false, |layer| layer.is_empty()) {
+            return Err("Input layer has no neurons".to_string());
+        }
+        let topology = self.get_topology()?;
+        for layer_index in 1..self.layers.len() {
+            let kind = self.get_layer_kind(layer_index);
+            if kind.is_spatial() {
+                let sources = self.get_layer_sources(layer_index);
+                if sources.len() != 1 {
+                    return Err(format!("{:?} layer must have exactly one source", kind));
+                }
+                let input = self.get_layer_shape(sources[0]);
+                if kind.get_output_shape(input)? != self.get_layer_shape(layer_index)
+                    || kind.get_number_of_parameters(input)
+                        != self.get_layer_parameters(layer_index).len()
+                    || kind.get_number_of_statistics(input)
+                        != self.get_layer_statistics(layer_index).len()
+                {
+                    return Err(format!("Layer {} does not match its shape", layer_index));
+                }
+                continue;
+            }
+            let inputs = topology.inputs[layer_index].len();
+            let gate_sizes = kind.get_gate_sizes(inputs, self.get_layer_shape(layer_index).size());
+            if self.layers[layer_index].iter().any(|neuron_id| {
+                let neuron = &self.neurons[*neuron_id];
+                !neuron.bias
+                    && (!neuron.matches_inputs(inputs)
+                        || (neuron.connections.is_some() && kind != LayerKind::Dense)
+                        || !neuron
+                            .gates
+                            .iter()
+                            .map(Vec::len)
+                            .eq(gate_sizes.iter().copied()))
+            }) {
+                return Err(format!(
+                    "Neurons of layer {} do not match the number of inputs",
+                    layer_index
+                ));
+            }
+        }
+        if let Some(layer) = self.layers.last() {
+            self.head.validate(layer.len(), self.activation)?;
+        }
+        self.topology = Some(topology);
+        Ok(())
+    }
+
+    /// Applies the output head to the outputs of the network.
+    pub(crate) fn get_action(
+        &self,
+        outputs: &[f64],
+        randomizer: &mut dyn RandomProvider,
+    ) -> Action {
+        self.head.apply(outputs, randomizer)
+    }
+
+    /// Returns the number of values the network expects as its input,
+    /// i.e. the size of the first layer without the bias neuron.
+    pub(crate) fn get_number_of_inputs(&self) -> usize {
+        self.layers
+            .first()
+            .map_or(0, |layer| layer.len().saturating_sub(1))
+    }
+
+    pub(crate) fn get_layer_kind(&self, layer_index: usize) -> LayerKind {
+        self.layer_kinds
+            .get(layer_index)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns `true` if the network consists of dense, dropout and normalisation
+    /// layers only, as only these can be trained by the backpropagation.
+    pub(crate) fn supports_backpropagation(&self) -> bool {
+        self.layer_kinds.iter().all(|kind| {
+            matches!(kind, LayerKind::Dense | LayerKind::Dropout { .. }) || kind.is_normalization()
+        })
+    }
+
+    /// Returns all weights of the network, flattened into a single vector.
+    ///
+    /// Weights of the neurons come first, followed by the weights shared by the layers.
+    pub(crate) fn get_weights(&self) -> Vec<f64> {
+        self.neurons
+            .iter()
+            .flat_map(|neuron| neuron.weights())
+            .chain(self.parameters.iter().flatten())
+            .copied()
+            .collect()
+    }
+
+    /// Replaces all weights of the network. `weights` must be laid out
+    /// in the same order as returned by [`get_weights`](#method.get_weights).
+    pub(crate) fn set_weights(&mut self, weights: &[f64]) {
+        assert_eq!(
+            weights.len(),
+            self.get_number_of_weights(),
+            "Incorrect number of weights"
+        );
+        self.weights_mut()
+            .zip(weights.iter())
+            .for_each(|(input, weight)| *input = *weight);
+    }
+
+    /// Iterates over all weights in the order of [`get_weights`](#method.get_weights).
+    pub(crate) fn weights_mut(&mut self) -> impl Iterator<Item = &mut f64> {
+        self.neurons
+            .iter_mut()
+            .flat_map(|neuron| neuron.weights_mut())
+            .chain(self.parameters.iter_mut().flatten())
+    }
+
+    pub(crate) fn get_number_of_weights(&self) -> usize {
+        self.neurons
+            .iter()
+            .map(|neuron| neuron.get_number_of_weights())
+            .sum::<usize>()
+            + self.parameters.iter().map(|p| p.len()).sum::<usize>()
+    }
+
+    /// Replaces all weights, including the gates and kernels, with new random numbers.
+    pub(crate) fn randomize(&mut self, randomizer: &mut dyn RandomProvider) {
+        self.weights_mut()
+            .for_each(|weight| *weight = randomizer.get_number());
+    }
+
+    /// Returns the position of the first weight of each neuron
+    /// in the vector returned by [`get_weights`](#method.get_weights).
+    fn get_weight_offsets(&self) -> Vec<usize> {
+        self.neurons
+            .iter()
+            .scan(0, |offset, neuron| {
+                let current = *offset;
+                *offset += neuron.get_number_of_weights();
+                Some(current)
+            })
+            .collect()
+    }
 }
 
 #[derive(Clone)]
 pub struct Network {
     pub(crate) layout: NetworkLayout,
     activator: fn(f64) -> f64,
+    /// Set when the activator is not the function of `layout.activation`,
+    /// whose derivative is then unknown.
+    custom_activator: bool,
 }
 
 impl Network {
-    fn new(neurons_per_layers: &[usize], activator: fn(f64) -> f64) -> Network {
+    fn new(
+        neurons_per_layers: &[usize],
+        activator: fn(f64) -> f64,
+        activation: Activation,
+    ) -> Network {
         Network {
             layout: NetworkLayout {
                 neurons: Vec::with_capacity(
@@ -26,11 +664,46 @@ impl Network {
                     layers.resize(neurons_per_layers.len(), Vec::new());
                     layers
                 },
+                activation,
+                ..NetworkLayout::default()
             },
             activator,
+            custom_activator: false,
         }
     }
 
+    pub fn get_layout(&self) -> &NetworkLayout {
+        &self.layout
+    }
+
+    /// Forgets the state kept by the recurrent layers, e.g. before a new episode.
+    pub(crate) fn reset_state(&mut self) {
+        self.layout
+            .neurons
+            .iter_mut()
+            .filter(|neuron| !neuron.bias)
+            .for_each(|neuron| {
+                neuron.value = None;
+                neuron.state = 0.0;
+            });
+    }
+
+    /// Wraps already existing layout (e.g. the brain of a trained specimen)
+    /// into a network that can be fired.
+    pub(crate) fn from_layout(layout: NetworkLayout) -> Network {
+        Network {
+            activator: layout.activation.function(),
+            layout,
+            custom_activator: false,
+        }
+    }
+
+    /// Tells whether the network was built with a custom activator,
+    /// which cannot be differentiated by the backpropagation.
+    pub(crate) fn has_custom_activator(&self) -> bool {
+        self.custom_activator
+    }
+
     pub(crate) fn get_output(&self) -> Vec<f64> {
         let last_layer = &self
             .layout
@@ -47,7 +720,7 @@ impl Network {
             .collect()
     }
 
-    #[allow(dead_code)]
+    #[allow(dead_code, clippy::ptr_arg)]
     fn set_layer_values(layer: &mut Vec<usize>, input_values: &[f64], neurons: &mut Vec<Neuron>) {
         layer
             .iter()
@@ -57,7 +730,7 @@ impl Network {
             });
     }
 
-    #[allow(dead_code)]
+    #[allow(dead_code, clippy::ptr_arg)]
     fn fire_layer(
         layer: &[usize],
         prev_layer: &[usize],
@@ -70,10 +743,10 @@ impl Network {
             let mut value = 0.0;
             let neuron_index = layer[i];
 
-            for (j, _) in prev_layer.iter().enumerate() {
-                let input_index = j;
-                let input_value = neurons[neuron_index].inputs[input_index];
-                let prev_layer_neuron_index = prev_layer[j];
+            // Pruned neurons skip the inputs they are not connected to
+            let neuron = &neurons[neuron_index];
+            for (j, input_value) in neuron.inputs.iter().enumerate() {
+                let prev_layer_neuron_index = prev_layer[neuron.get_input_position(j)];
                 let prev_layer_neuron_value = neurons[prev_layer_neuron_index]
                     .value
                     .expect("Neuron w/o value found");
@@ -86,6 +759,21 @@ impl Network {
 
     #[allow(dead_code)]
     pub(crate) fn fire(&mut self, input_values: &[f64]) {
+        self.fire_with(input_values, None);
+    }
+
+    /// Fires the network in the training mode of the supervised training:
+    /// dropout zeroes values chosen with the randomizer and batch normalisation
+    /// updates its running statistics.
+    pub(crate) fn fire_training(
+        &mut self,
+        input_values: &[f64],
+        randomizer: &mut dyn RandomProvider,
+    ) {
+        self.fire_with(input_values, Some(randomizer));
+    }
+
+    fn fire_with(&mut self, input_values: &[f64], mut training: Option<&mut dyn RandomProvider>) {
         assert!(
             !self.layout.layers.is_empty(),
             "Trying to fire network with no layers"
@@ -101,15 +789,381 @@ impl Network {
             input_values,
             &mut self.layout.neurons,
         );
-        for layer_index in 1..self.layout.layers.len() {
-            Network::fire_layer(
-                &self.layout.layers[layer_index],
-                &self.layout.layers[layer_index - 1],
-                &mut self.layout.neurons,
-                layer_index == self.layout.layers.len() - 1,
-                self.activator,
-            );
+        let topology = match self.layout.topology.take() {
+            Some(topology) => topology,
+            None => self
+                .layout
+                .get_topology()
+                .expect("Invalid network topology"),
+        };
+        for layer_index in topology.order.iter().copied().skip(1) {
+            let inputs = &topology.inputs[layer_index];
+            match self.layout.get_layer_kind(layer_index) {
+                kind if kind.is_spatial() => {
+                    Network::fire_spatial_layer(
+                        kind,
+                        &self.layout.layers[layer_index],
+                        inputs,
+                        self.layout
+                            .get_layer_shape(self.layout.get_layer_sources(layer_index)[0]),
+                        self.layout.get_layer_shape(layer_index),
+                        self.layout
+                            .parameters
+                            .get(layer_index)
+                            .map_or(&[], |parameters| parameters),
+                        &mut self.layout.neurons,
+                        self.activator,
+                    );
+                    if let LayerKind::Dropout { rate } = kind {
+                        Network::drop_out(
+                            rate,
+                            training.as_deref_mut(),
+                            &self.layout.layers[layer_index],
+                            &mut self.layout.neurons,
+                        );
+                    }
+                    if kind.is_normalization() {
+                        Network::fire_normalization_layer(
+                            kind,
+                            training.is_some(),
+                            &self.layout.layers[layer_index],
+                            &self.layout.parameters[layer_index],
+                            self.layout
+                                .statistics
+                                .get_mut(layer_index)
+                                .map_or(&mut [], |statistics| statistics),
+                            &mut self.layout.neurons,
+                        );
+                    }
+                }
+                LayerKind::Dense => Network::fire_layer(
+                    &self.layout.layers[layer_index],
+                    inputs,
+                    &mut self.layout.neurons,
+                    layer_index == self.layout.layers.len() - 1,
+                    self.activator,
+                ),
+                kind => Network::fire_recurrent_layer(
+                    kind,
+                    &self.layout.layers[layer_index],
+                    inputs,
+                    &mut self.layout.neurons,
+                    layer_index == self.layout.layers.len() - 1,
+                    self.activator,
+                ),
+            }
         }
+        self.layout.topology = Some(topology);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn fire_spatial_layer(
+        kind: LayerKind,
+        layer: &[usize],
+        inputs: &[usize],
+        input_shape: Shape,
+        output_shape: Shape,
+        parameters: &[f64],
+        neurons: &mut [Neuron],
+        activator: fn(f64) -> f64,
+    ) {
+        let (kernel_height, kernel_width, stride_y, stride_x) = kind.get_window(input_shape);
+        let kernel_size = input_shape.channels * kernel_height * kernel_width;
+        let input = |channel: usize, y: usize, x: usize| {
+            neurons[inputs[(channel * input_shape.height + y) * input_shape.width + x]]
+                .value
+                .expect("Neuron w/o value found")
+        };
+        let window = |channel: usize, y: usize, x: usize| {
+            (0..kernel_height).flat_map(move |ky| {
+                (0..kernel_width).map(move |kx| (channel, y * stride_y + ky, x * stride_x + kx))
+            })
+        };
+
+        let mut values = Vec::with_capacity(output_shape.size());
+        for channel in 0..output_shape.channels {
+            for y in 0..output_shape.height {
+                for x in 0..output_shape.width {
+                    values.push(match kind {
+                        LayerKind::Conv1d { .. } | LayerKind::Conv2d { .. } => {
+                            let kernel = &parameters
+                                [channel * (kernel_size + 1)..(channel + 1) * (kernel_size + 1)];
+                            let total = (0..input_shape.channels)
+                                .flat_map(|c| window(c, y, x))
+                                .zip(kernel.iter())
+                                .map(|((c, iy, ix), weight)| weight * input(c, iy, ix))
+                                .sum::<f64>();
+                            activator(total + kernel[kernel_size])
+                        }
+                        LayerKind::MaxPool { .. } => window(channel, y, x)
+                            .map(|(c, iy, ix)| input(c, iy, ix))
+                            .fold(f64::NEG_INFINITY, f64::max),
+                        LayerKind::AveragePool { .. } => {
+                            window(channel, y, x)
+                                .map(|(c, iy, ix)| input(c, iy, ix))
+                                .sum::<f64>()
+                                / (kernel_height * kernel_width) as f64
+                        }
+                        _ => neurons[inputs[values.len()]]
+                            .value
+                            .expect("Neuron w/o value found"),
+                    });
+                }
+            }
+        }
+        layer
+            .iter()
+            .zip(values)
+            .for_each(|(neuron_id, value)| neurons[*neuron_id].value = Some(value));
+    }
+
+    /// Zeroes random values of the layer during the training. Factor applied
+    /// to each value is kept in the state of the neuron for the backpropagation.
+    fn drop_out<'r>(
+        rate: f64,
+        mut randomizer: Option<&mut (dyn RandomProvider + 'r)>,
+        layer: &[usize],
+        neurons: &mut [Neuron],
+    ) {
+        for neuron_id in layer {
+            let neuron = &mut neurons[*neuron_id];
+            if neuron.bias {
+                continue;
+            }
+            neuron.state = match randomizer.as_deref_mut() {
+                None => 1.0,
+                Some(randomizer) => {
+                    if randomizer.get_uniform() < rate {
+                        0.0
+                    } else {
+                        1.0 / (1.0 - rate)
+                    }
+                }
+            };
+            neuron.value = neuron.value.map(|value| value * neuron.state);
+        }
+    }
+
+    /// Normalises the values copied from the source layer. Batch normalisation
+    /// first updates its running statistics with the values, during the training only.
+    fn fire_normalization_layer(
+        kind: LayerKind,
+        training: bool,
+        layer: &[usize],
+        parameters: &[f64],
+        statistics: &mut [f64],
+        neurons: &mut [Neuron],
+    ) {
+        let layer: Vec<usize> = layer
+            .iter()
+            .copied()
+            .filter(|neuron_id| !neurons[*neuron_id].bias)
+            .collect();
+        let values: Vec<f64> = layer
+            .iter()
+            .map(|neuron_id| neurons[*neuron_id].value.expect("Neuron w/o value found"))
+            .collect();
+        if let (LayerKind::BatchNorm { momentum }, true) = (kind, training) {
+            let (means, variances) = statistics.split_at_mut(values.len());
+            for (i, value) in values.iter().enumerate() {
+                // Deviation from the mean before it moves towards the value
+                let delta = value - means[i];
+                means[i] += (1.0 - momentum) * delta;
+                variances[i] = momentum * variances[i] + (1.0 - momentum) * delta * delta;
+            }
+        }
+        let (normalized, _) = kind.normalize(&values, statistics);
+        let (scales, shifts) = parameters.split_at(values.len());
+        for (i, neuron_id) in layer.iter().enumerate() {
+            neurons[*neuron_id].value = Some(scales[i] * normalized[i] + shifts[i]);
+        }
+    }
+
+    fn fire_recurrent_layer(
+        kind: LayerKind,
+        layer: &[usize],
+        prev_layer: &[usize],
+        neurons: &mut [Neuron],
+        is_last: bool,
+        activator: fn(f64) -> f64,
+    ) {
+        let layer = &layer[..layer.len() - if is_last { 0 } else { 1 }];
+        let dot = |weights: &[f64], values: &[f64]| -> f64 {
+            weights.iter().zip(values.iter()).map(|(w, v)| w * v).sum()
+        };
+        let sigmoid = Activation::Sigmoid.function();
+        let input: Vec<f64> = prev_layer
+            .iter()
+            .map(|neuron_id| neurons[*neuron_id].value.expect("Neuron w/o value found"))
+            .collect();
+        let hidden: Vec<f64> = layer
+            .iter()
+            .map(|neuron_id| {
+                let neuron = &neurons[*neuron_id];
+                match kind {
+                    LayerKind::Ctrnn { .. } => {
+                        activator(neuron.gates[1][1] * (neuron.state + neuron.gates[1][2]))
+                    }
+                    _ => neuron.value.unwrap_or(0.0),
+                }
+            })
+            .collect();
+        // Reset gates of GRU must be known for the whole layer up front
+        let reset_hidden: Vec<f64> = match kind {
+            LayerKind::Gru => layer
+                .iter()
+                .zip(hidden.iter())
+                .map(|(neuron_id, h)| {
+                    let gates = &neurons[*neuron_id].gates;
+                    sigmoid(dot(&gates[1], &input) + dot(&gates[3], &hidden)) * h
+                })
+                .collect(),
+            _ => vec![],
+        };
+
+        for (j, neuron_id) in layer.iter().enumerate() {
+            let neuron = &mut neurons[*neuron_id];
+            let gates = &neuron.gates;
+            let value = match kind {
+                LayerKind::Elman => {
+                    activator(dot(&neuron.inputs, &input) + gates[0][0] * hidden[j])
+                }
+                LayerKind::Gru => {
+                    let update = sigmoid(dot(&gates[0], &input) + dot(&gates[2], &hidden));
+                    let candidate =
+                        (dot(&neuron.inputs, &input) + dot(&gates[4], &reset_hidden)).tanh();
+                    (1.0 - update) * hidden[j] + update * candidate
+                }
+                LayerKind::Lstm => {
+                    let input_gate = sigmoid(dot(&gates[0], &input) + dot(&gates[3], &hidden));
+                    let forget_gate = sigmoid(dot(&gates[1], &input) + dot(&gates[4], &hidden));
+                    let output_gate = sigmoid(dot(&gates[2], &input) + dot(&gates[5], &hidden));
+                    let cell_input = (dot(&neuron.inputs, &input) + dot(&gates[6], &hidden)).tanh();
+                    neuron.state = forget_gate * neuron.state + input_gate * cell_input;
+                    output_gate * neuron.state.tanh()
+                }
+                LayerKind::Ctrnn { step } => {
+                    let (time_constant, gain, bias) = (gates[1][0].exp(), gates[1][1], gates[1][2]);
+                    let total = dot(&neuron.inputs, &input) + dot(&gates[0], &hidden);
+                    // Larger steps would overshoot the equilibrium, see LayerKind::Ctrnn
+                    neuron.state += (step / time_constant).min(1.0) * (total - neuron.state);
+                    activator(gain * (neuron.state + bias))
+                }
+                _ => unreachable!("{:?} layer fired as recurrent", kind),
+            };
+            neuron.value = Some(value);
+        }
+    }
+
+    /// Calculates the gradient of the loss with respect to all weights,
+    /// laid out in the same order as [`NetworkLayout::get_weights`](struct.NetworkLayout.html#method.get_weights).
+    ///
+    /// The network must have been fired with the input of interest beforehand.
+    /// `output_deltas` are the derivatives of the loss with respect to the
+    /// weighted sums (before activation) of the output neurons.
+    pub(crate) fn backpropagate(&self, output_deltas: &[f64]) -> Vec<f64> {
+        let layout = &self.layout;
+        let value = |neuron_id: usize| {
+            layout.neurons[neuron_id]
+                .value
+                .expect("Neuron has no calculated value")
+        };
+        let offsets = layout.get_weight_offsets();
+        let mut gradient = vec![0.0; layout.get_number_of_weights()];
+        let mut deltas = vec![0.0; layout.neurons.len()];
+        layout
+            .layers
+            .last()
+            .expect("Network has no last layer")
+            .iter()
+            .zip(output_deltas.iter())
+            .for_each(|(neuron_id, delta)| deltas[*neuron_id] = *delta);
+
+        let computed;
+        let topology = match &layout.topology {
+            Some(topology) => topology,
+            None => {
+                computed = layout.get_topology().expect("Invalid network topology");
+                &computed
+            }
+        };
+        let output_layer = layout.layers.len() - 1;
+        let mut propagated = vec![0.0; layout.neurons.len()];
+        for layer_index in topology.order.iter().copied().skip(1).rev() {
+            let kind = layout.get_layer_kind(layer_index);
+            let is_dropout = matches!(kind, LayerKind::Dropout { .. });
+            if layer_index != output_layer {
+                layout.layers[layer_index]
+                    .iter()
+                    .filter(|neuron_id| !layout.neurons[**neuron_id].bias)
+                    .for_each(|neuron_id| {
+                        deltas[*neuron_id] = propagated[*neuron_id]
+                            * if is_dropout || kind.is_normalization() {
+                                1.0
+                            } else {
+                                layout.activation.derivative(value(*neuron_id))
+                            }
+                    });
+            }
+            let inputs = &topology.inputs[layer_index];
+            if is_dropout {
+                // Each neuron passes its delta to the neuron it copies
+                for (neuron_id, input_id) in layout.layers[layer_index].iter().zip(inputs.iter()) {
+                    let neuron = &layout.neurons[*neuron_id];
+                    if !neuron.bias {
+                        propagated[*input_id] += deltas[*neuron_id] * neuron.state;
+                    }
+                }
+                continue;
+            }
+            if kind.is_normalization() {
+                let layer: Vec<usize> = layout.layers[layer_index]
+                    .iter()
+                    .copied()
+                    .filter(|neuron_id| !layout.neurons[*neuron_id].bias)
+                    .collect();
+                let size = layer.len();
+                let values: Vec<f64> = inputs.iter().take(size).map(|id| value(*id)).collect();
+                let (normalized, inverse_deviations) =
+                    kind.normalize(&values, layout.get_layer_statistics(layer_index));
+                let offset = layout.get_parameter_offset(layer_index);
+                let scaled: Vec<f64> = layer
+                    .iter()
+                    .zip(layout.parameters[layer_index].iter())
+                    .map(|(neuron_id, scale)| deltas[*neuron_id] * scale)
+                    .collect();
+                let mean_scaled = scaled.iter().sum::<f64>() / size as f64;
+                let mean_product = scaled
+                    .iter()
+                    .zip(normalized.iter())
+                    .map(|(s, n)| s * n)
+                    .sum::<f64>()
+                    / size as f64;
+                for (i, neuron_id) in layer.iter().enumerate() {
+                    gradient[offset + i] = deltas[*neuron_id] * normalized[i];
+                    gradient[offset + size + i] = deltas[*neuron_id];
+                    propagated[inputs[i]] += inverse_deviations[i]
+                        * match kind {
+                            // Mean and variance depend on all values of the layer
+                            LayerKind::LayerNorm => {
+                                scaled[i] - mean_scaled - normalized[i] * mean_product
+                            }
+                            _ => scaled[i],
+                        };
+                }
+                continue;
+            }
+            for neuron_id in &layout.layers[layer_index] {
+                let neuron = &layout.neurons[*neuron_id];
+                let delta = deltas[*neuron_id];
+                for (j, weight) in neuron.inputs.iter().enumerate() {
+                    let input_id = inputs[neuron.get_input_position(j)];
+                    gradient[offsets[*neuron_id] + j] = delta * value(input_id);
+                    propagated[input_id] += delta * weight;
+                }
+            }
+        }
+        gradient
     }
 }
 
@@ -117,6 +1171,19 @@ pub struct NetworkBuilder<'a> {
     neurons_per_layer: Option<&'a [usize]>,
     randomizer: Option<&'a mut dyn RandomProvider>,
     activator: Option<fn(f64) -> f64>,
+    activation: Activation,
+    layer_kinds: Vec<LayerKind>,
+    connectivity: Connectivity,
+    layer_sources: Vec<(usize, Vec<usize>)>,
+    input_shape: Option<Shape>,
+    head: OutputHead,
+    initializer: Initializer,
+}
+
+impl Default for NetworkBuilder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'a> NetworkBuilder<'a> {
@@ -125,6 +1192,13 @@ impl<'a> NetworkBuilder<'a> {
             neurons_per_layer: None,
             randomizer: None,
             activator: None,
+            activation: Activation::default(),
+            layer_kinds: vec![],
+            connectivity: Connectivity::Sequential,
+            layer_sources: vec![],
+            input_shape: None,
+            head: OutputHead::Raw,
+            initializer: Initializer::Randomizer,
         }
     }
 
@@ -148,51 +1222,297 @@ impl<'a> NetworkBuilder<'a> {
         self
     }
 
-    fn number_of_neurons_on_previous_layer(
-        &self,
-        layer_index: usize,
-        neurons_per_layer: &[usize],
-    ) -> usize {
-        if layer_index == 0 {
-            0
-        } else {
-            neurons_per_layer[layer_index - 1] + 1
+    /// Uses one of the predefined activation functions. Unlike the custom
+    /// activator, it is stored in the network layout and its derivative is known,
+    /// so the network can also be trained with gradient descent.
+    pub fn with_activation(&mut self, activation: Activation) -> &mut Self {
+        self.activation = activation;
+        self
+    }
+
+    /// Scales the numbers of the randomizer according to the scheme, e.g. by
+    /// the fan-in of each layer. Applies to the input weights of the neurons,
+    /// to the convolution kernels and to each gate of recurrent layers, whose
+    /// fan-in is the length of the gate. Time constants, gains and biases
+    /// of CTRNN neurons keep the numbers of the randomizer as they are.
+    pub fn with_initializer(&mut self, initializer: Initializer) -> &mut Self {
+        self.initializer = initializer;
+        self
+    }
+
+    /// Changes the kind of the specified layer. Layers are dense by default
+    /// and the input layer (index `0`) must stay dense.
+    pub fn with_layer_kind(&mut self, layer_index: usize, kind: LayerKind) -> &mut Self {
+        if self.layer_kinds.len() <= layer_index {
+            self.layer_kinds.resize(layer_index + 1, LayerKind::Dense);
         }
+        self.layer_kinds[layer_index] = kind;
+        self
+    }
+
+    /// Changes how the outputs are turned into actions (passed as they are by default).
+    pub fn with_output_head(&mut self, head: OutputHead) -> &mut Self {
+        self.head = head;
+        self
+    }
+
+    /// Arranges the inputs into channels of 1D arrays or 2D grids, so that
+    /// convolution and pooling layers can read from them. Its size must
+    /// match the number of inputs.
+    ///
+    /// The number of neurons of the convolution, pooling and flatten layers
+    /// follows from their shapes, the corresponding entries of
+    /// `neurons_per_layer` are ignored.
+    pub fn with_input_shape(&mut self, shape: Shape) -> &mut Self {
+        self.input_shape = Some(shape);
+        self
+    }
+
+    /// Connects the layers according to the predefined pattern.
+    /// Layers are connected sequentially by default.
+    pub fn with_connectivity(&mut self, connectivity: Connectivity) -> &mut Self {
+        self.connectivity = connectivity;
+        self
+    }
+
+    /// Makes the specified layer read from the listed layers, overriding
+    /// the connectivity pattern. Layers may be listed in any order, as long
+    /// as the connections form no cycle.
+    pub fn with_layer_sources(&mut self, layer_index: usize, sources: &[usize]) -> &mut Self {
+        self.layer_sources
+            .retain(|(index, _)| *index != layer_index);
+        self.layer_sources.push((layer_index, sources.to_vec()));
+        self
+    }
+
+    fn get_sources(&self, number_of_layers: usize) -> Vec<Vec<usize>> {
+        (0..number_of_layers)
+            .map(|layer_index| {
+                if let Some((_, sources)) = self
+                    .layer_sources
+                    .iter()
+                    .find(|(index, _)| *index == layer_index)
+                {
+                    return sources.clone();
+                }
+                match (layer_index, self.connectivity) {
+                    (0, _) => vec![],
+                    (_, Connectivity::Sequential) | (1, Connectivity::Residual) => {
+                        vec![layer_index - 1]
+                    }
+                    (_, Connectivity::Residual) => vec![layer_index - 1, layer_index - 2],
+                    (_, Connectivity::Dense) => (0..layer_index).rev().collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// Counts the neurons of the source layers, including their bias neurons.
+    fn number_of_inputs(sources: &[usize], sizes: &[usize]) -> usize {
+        sources.iter().map(|source| sizes[*source] + 1).sum()
+    }
+
+    /// Calculates the shape of each layer, following the connections
+    /// from the input layer, so that spatial layers know their sources.
+    fn get_shapes(&self, neurons_per_layer: &[usize], sources: &[Vec<usize>]) -> Vec<Shape> {
+        let order = NetworkLayout {
+            layers: vec![vec![]; neurons_per_layer.len()],
+            sources: sources.to_vec(),
+            ..NetworkLayout::default()
+        }
+        .get_evaluation_order()
+        .unwrap_or_else(|message| panic!("Unable to build network: {}", message));
+
+        let mut shapes = vec![Shape::new_1d(1, 0); neurons_per_layer.len()];
+        for layer_index in order {
+            let kind = self
+                .layer_kinds
+                .get(layer_index)
+                .copied()
+                .unwrap_or_default();
+            shapes[layer_index] = if kind.is_spatial() {
+                assert_eq!(
+                    sources[layer_index].len(),
+                    1,
+                    "{:?} layer must have exactly one source",
+                    kind
+                );
+                kind.get_output_shape(shapes[sources[layer_index][0]])
+                    .unwrap_or_else(|message| panic!("Unable to build network: {}", message))
+            } else if layer_index == 0 && self.input_shape.is_some() {
+                let shape = self.input_shape.unwrap();
+                assert_eq!(
+                    shape.size(),
+                    neurons_per_layer[0],
+                    "Input shape does not match the number of inputs"
+                );
+                shape
+            } else {
+                Shape::new_1d(1, neurons_per_layer[layer_index])
+            };
+        }
+        shapes
     }
 
     pub fn build(&mut self) -> Network {
+        let custom_activator = self.activator.is_some();
         if self.activator.is_none() {
-            self.activator = Some(NetworkBuilder::get_default_activator());
+            self.activator = Some(self.activation.function());
         }
         if let Some(neurons_per_layer) = self.neurons_per_layer {
-            let mut net = Network::new(neurons_per_layer, self.activator.unwrap());
+            assert!(
+                self.layer_kinds
+                    .first()
+                    .map_or(true, |kind| *kind == LayerKind::Dense),
+                "Input layer must be dense"
+            );
+            let sources = self.get_sources(neurons_per_layer.len());
+            let shapes = self.get_shapes(neurons_per_layer, &sources);
+            let sizes: Vec<usize> = shapes.iter().map(Shape::size).collect();
+            let mut net = Network::new(&sizes, self.activator.unwrap(), self.activation);
+            net.custom_activator = custom_activator;
 
             // TODO: Do relocation testing only in unit-tests
             net.layout.neurons.push(Neuron::new(true, 0, &mut None));
             let neuron_buffer_address = &net.layout.neurons[0] as *const _;
             net.layout.neurons.clear();
 
-            for layer_index in 0..neurons_per_layer.len() {
-                for _ in 0..neurons_per_layer[layer_index] {
-                    let neurons_on_previous_layer =
-                        self.number_of_neurons_on_previous_layer(layer_index, neurons_per_layer);
-                    net.layout.neurons.push(Neuron::new(
-                        false,
-                        neurons_on_previous_layer,
-                        &mut self.randomizer,
-                    ));
+            net.layout.layer_kinds = self.layer_kinds.clone();
+            if let Err(message) = self.head.validate(sizes[sizes.len() - 1], self.activation) {
+                panic!("Unable to build network: {}", message);
+            }
+            net.layout.head = self.head.clone();
+            for layer_index in 0..sizes.len() {
+                let kind = net.layout.get_layer_kind(layer_index);
+                let number_of_inputs = if kind.is_spatial() {
+                    0
+                } else {
+                    Self::number_of_inputs(&sources[layer_index], &sizes)
+                };
+                let gate_sizes = kind.get_gate_sizes(number_of_inputs, sizes[layer_index]);
+                let mut initialized = match self.initializer {
+                    Initializer::Randomizer => vec![],
+                    initializer => initializer.initialize(
+                        sizes[layer_index],
+                        number_of_inputs,
+                        self.randomizer
+                            .as_deref_mut()
+                            .expect("No randomizer provided"),
+                    ),
+                }
+                .into_iter();
+                let mut initialized_gates = Vec::with_capacity(gate_sizes.len());
+                for (gate, size) in gate_sizes.iter().enumerate() {
+                    initialized_gates.push(
+                        match self.initializer {
+                            Initializer::Randomizer => vec![],
+                            _ if !kind.gate_holds_weights(gate) => vec![],
+                            initializer => initializer.initialize(
+                                sizes[layer_index],
+                                *size,
+                                self.randomizer
+                                    .as_deref_mut()
+                                    .expect("No randomizer provided"),
+                            ),
+                        }
+                        .into_iter(),
+                    );
+                }
+                for _ in 0..sizes[layer_index] {
+                    let mut neuron = match initialized.next() {
+                        Some(inputs) => Neuron {
+                            inputs,
+                            ..Neuron::new(false, 0, &mut None)
+                        },
+                        None => Neuron::new(false, number_of_inputs, &mut self.randomizer),
+                    };
+                    if !gate_sizes.is_empty() {
+                        let randomizer = self.randomizer.as_mut().expect("No randomizer provided");
+                        neuron.gates = gate_sizes
+                            .iter()
+                            .zip(initialized_gates.iter_mut())
+                            .map(|(size, initialized)| {
+                                initialized.next().unwrap_or_else(|| {
+                                    (0..*size).map(|_| randomizer.get_number()).collect()
+                                })
+                            })
+                            .collect();
+                    }
+                    net.layout.neurons.push(neuron);
                     net.layout.layers[layer_index].push(net.layout.neurons.len() - 1);
                 }
 
-                if layer_index != neurons_per_layer.len() - 1 {
+                if layer_index != sizes.len() - 1 {
                     net.layout.neurons.push(Neuron::new(true, 0, &mut None));
                     net.layout.layers[layer_index].push(net.layout.neurons.len() - 1);
                 }
             }
+
+            if self.input_shape.is_some() || self.layer_kinds.iter().any(|kind| kind.is_spatial()) {
+                net.layout.parameters = (0..shapes.len())
+                    .map(|layer_index| {
+                        let kind = net.layout.get_layer_kind(layer_index);
+                        let number_of_parameters = match sources[layer_index].first() {
+                            Some(source) => kind.get_number_of_parameters(shapes[*source]),
+                            None => 0,
+                        };
+                        if kind.is_normalization() {
+                            // Unit scales and zero shifts
+                            return (0..number_of_parameters)
+                                .map(|i| {
+                                    if i < number_of_parameters / 2 {
+                                        1.0
+                                    } else {
+                                        0.0
+                                    }
+                                })
+                                .collect();
+                        }
+                        let filters = match kind {
+                            LayerKind::Conv1d { filters, .. }
+                            | LayerKind::Conv2d { filters, .. } => filters.max(1),
+                            _ => 1,
+                        };
+                        self.initializer
+                            .initialize(
+                                filters,
+                                number_of_parameters / filters,
+                                self.randomizer
+                                    .as_deref_mut()
+                                    .expect("No randomizer provided"),
+                            )
+                            .concat()
+                    })
+                    .collect();
+                if self
+                    .layer_kinds
+                    .iter()
+                    .any(|kind| matches!(kind, LayerKind::BatchNorm { .. }))
+                {
+                    // Zero means and unit variances
+                    net.layout.statistics = (0..shapes.len())
+                        .map(|layer_index| {
+                            let size = net
+                                .layout
+                                .get_layer_kind(layer_index)
+                                .get_number_of_statistics(shapes[layer_index])
+                                / 2;
+                            [vec![0.0; size], vec![1.0; size]].concat()
+                        })
+                        .collect();
+                }
+                net.layout.shapes = shapes;
+            }
             assert_eq!(
                 &net.layout.neurons[0] as *const _, neuron_buffer_address,
                 "Reallocation of the neuron buffer detected"
             );
+            if (0..sources.len()).any(|i| sources[i] != net.layout.get_layer_sources(i)) {
+                net.layout.sources = sources;
+            }
+            if let Err(message) = net.layout.validate() {
+                panic!("Unable to build network: {}", message);
+            }
             net
         } else {
             panic!("Unable to build network");
@@ -200,6 +1520,28 @@ impl<'a> NetworkBuilder<'a> {
     }
 }
 
+/// Residual `[3, 5, 4, 2]` network with one connection removed,
+/// shared by the tests of the exporters.
+#[cfg(test)]
+pub(crate) fn build_exporter_fixture(
+    activation: Activation,
+    randomizer: &mut dyn RandomProvider,
+) -> crate::Specimen {
+    let mut specimen = crate::Specimen {
+        brain: NetworkBuilder::new()
+            .with_neurons_per_layer(&[3, 5, 4, 2])
+            .with_randomizer(randomizer)
+            .with_activation(activation)
+            .with_connectivity(Connectivity::Residual)
+            .build()
+            .layout,
+        fitness: 0.0,
+    };
+    let neuron_id = specimen.brain.layers[2][1];
+    specimen.brain.neurons[neuron_id].disconnect(0);
+    specimen
+}
+
 #[cfg(test)]
 mod tests {
     use crate::network::*;
@@ -243,6 +1585,27 @@ mod tests {
     }
 
     #[test]
+    fn flattened_weights() {
+        use crate::randomizer::DefaultRandomizer;
+        let mut randomizer = DefaultRandomizer::new();
+        let mut net = NetworkBuilder::new()
+            .with_neurons_per_layer(&[3, 2, 1])
+            .with_randomizer(&mut randomizer)
+            .build();
+
+        let weights = net.layout.get_weights();
+        assert_eq!(weights.len(), 2 * 4 + 3);
+        assert_eq!(weights.len(), net.layout.get_number_of_weights());
+
+        let replaced: Vec<f64> = (0..weights.len()).map(|x| x as f64).collect();
+        net.layout.set_weights(&replaced);
+        assert_eq!(net.layout.get_weights(), replaced);
+        assert!(relative_eq!(net.layout.neurons[4].inputs[0], 0.0));
+        assert!(relative_eq!(net.layout.neurons[7].inputs[2], 10.0));
+    }
+
+    #[test]
+    #[allow(clippy::neg_multiply)]
     fn calculations_with_default_activation_function() {
         pub(crate) struct TestRandomizer {
             current: f64,
@@ -384,4 +1747,553 @@ mod tests {
         let serialized = serde_json::to_string(&net.layout).unwrap();
         println!("{}", serialized);
     }
+
+    #[test]
+    fn elman_layer_remembers_previous_output() {
+        use crate::randomizer::DefaultRandomizer;
+        let mut randomizer = DefaultRandomizer::new();
+        let mut net = NetworkBuilder::new()
+            .with_neurons_per_layer(&[1, 1])
+            .with_randomizer(&mut randomizer)
+            .with_activation(Activation::Identity)
+            .with_layer_kind(1, LayerKind::Elman)
+            .build();
+        // Input weight, bias weight, recurrent weight
+        net.layout.set_weights(&[1.0, 0.0, 0.5]);
+
+        let mut outputs = vec![];
+        for input in [1.0, 0.0, 0.0].iter() {
+            net.fire(&[*input]);
+            outputs.push(net.get_output()[0]);
+        }
+        assert_eq!(outputs, vec![1.0, 0.5, 0.25]);
+
+        net.reset_state();
+        net.fire(&[0.0]);
+        assert!(relative_eq!(net.get_output()[0], 0.0));
+    }
+
+    #[test]
+    fn gated_layers() {
+        use crate::randomizer::DefaultRandomizer;
+        for (kind, gates) in [(LayerKind::Gru, 5), (LayerKind::Lstm, 7)].iter() {
+            let mut randomizer = DefaultRandomizer::new();
+            let mut net = NetworkBuilder::new()
+                .with_neurons_per_layer(&[2, 3, 1])
+                .with_randomizer(&mut randomizer)
+                .with_layer_kind(1, *kind)
+                .build();
+            assert_eq!(net.layout.neurons[3].gates.len(), *gates);
+            assert_eq!(
+                net.layout.get_number_of_weights(),
+                net.layout.get_weights().len()
+            );
+            assert!(!net.layout.supports_backpropagation());
+
+            let serialized = serde_json::to_string(&net.layout).unwrap();
+            let restored: NetworkLayout = serde_json::from_str(&serialized).unwrap();
+            assert_eq!(restored.get_layer_kind(1), *kind);
+
+            net.fire(&[1.0, -1.0]);
+            let first = net.get_output();
+            net.fire(&[1.0, -1.0]);
+            assert!(first != net.get_output(), "{:?}", kind);
+            net.reset_state();
+            net.fire(&[1.0, -1.0]);
+            assert_eq!(first, net.get_output());
+        }
+    }
+
+    #[test]
+    fn ctrnn_integrates_inputs() {
+        use crate::randomizer::DefaultRandomizer;
+        let mut randomizer = DefaultRandomizer::new();
+        let mut net = NetworkBuilder::new()
+            .with_neurons_per_layer(&[1, 1])
+            .with_randomizer(&mut randomizer)
+            .with_activation(Activation::Identity)
+            .with_layer_kind(1, LayerKind::Ctrnn { step: 0.5 })
+            .build();
+        // Input weight, bias weight, recurrent weight, ln(time constant), gain, bias
+        net.layout.set_weights(&[1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+
+        let mut outputs = vec![];
+        for _ in 0..3 {
+            net.fire(&[1.0]);
+            outputs.push(net.get_output()[0]);
+        }
+        assert_eq!(outputs, vec![0.5, 0.75, 0.875]);
+
+        let serialized = serde_json::to_string(&net.layout).unwrap();
+        let restored: NetworkLayout = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(restored.get_layer_kind(1), LayerKind::Ctrnn { step: 0.5 });
+        assert_eq!(restored.get_weights(), net.layout.get_weights());
+    }
+
+    #[test]
+    fn ctrnn_step_is_clamped_to_time_constant() {
+        use crate::randomizer::DefaultRandomizer;
+        let mut randomizer = DefaultRandomizer::new();
+        let mut net = NetworkBuilder::new()
+            .with_neurons_per_layer(&[1, 1])
+            .with_randomizer(&mut randomizer)
+            .with_activation(Activation::Identity)
+            .with_layer_kind(1, LayerKind::Ctrnn { step: 0.5 })
+            .build();
+        // Time constant of 0.1 is shorter than the step
+        net.layout
+            .set_weights(&[2.0, 0.0, 0.0, (0.1_f64).ln(), 1.0, 0.0]);
+        net.fire(&[1.0]);
+        assert!(relative_eq!(net.get_output()[0], 2.0));
+        net.fire(&[1.0]);
+        assert!(relative_eq!(net.get_output()[0], 2.0));
+    }
+
+    #[test]
+    fn ctrnn_genes_evolve() {
+        use crate::genetic::{crossover, mutate};
+        use crate::randomizer::DefaultRandomizer;
+        use crate::regularization::Regularization;
+        let mut randomizer = DefaultRandomizer::new();
+        let mut parents: Vec<crate::Specimen> = (0..2)
+            .map(|_| crate::Specimen {
+                brain: NetworkBuilder::new()
+                    .with_neurons_per_layer(&[1, 4, 1])
+                    .with_randomizer(&mut randomizer)
+                    .with_layer_kind(1, LayerKind::Ctrnn { step: 0.1 })
+                    .build()
+                    .layout,
+                fitness: 0.0,
+            })
+            .collect();
+        let genes = |specimen: &crate::Specimen| -> Vec<Vec<f64>> {
+            specimen.brain.layers[1]
+                .iter()
+                .filter(|index| !specimen.brain.neurons[**index].bias)
+                .map(|index| specimen.brain.neurons[*index].gates[1].clone())
+                .collect()
+        };
+        let parents = [parents.remove(0), parents.remove(0)];
+
+        // The first half of the neurons holds two of the four CTRNN neurons
+        let children = crossover(&parents);
+        assert_eq!(genes(&children[0])[..2], genes(&parents[1])[..2]);
+        assert_eq!(genes(&children[0])[2..], genes(&parents[0])[2..]);
+        assert_eq!(genes(&children[1])[..2], genes(&parents[0])[..2]);
+
+        let mutants = mutate(
+            parents.clone(),
+            &mut randomizer,
+            1.0,
+            &Regularization::default(),
+        );
+        for (mutant, parent) in mutants.iter().zip(parents.iter()) {
+            genes(mutant)
+                .iter()
+                .flatten()
+                .zip(genes(parent).iter().flatten())
+                .for_each(|(mutated, original)| assert!(mutated != original));
+        }
+    }
+
+    #[test]
+    fn skip_connections() {
+        use crate::randomizer::DefaultRandomizer;
+        let mut randomizer = DefaultRandomizer::new();
+        let mut net = NetworkBuilder::new()
+            .with_neurons_per_layer(&[2, 2, 2, 1])
+            .with_randomizer(&mut randomizer)
+            .with_activation(Activation::Identity)
+            .with_connectivity(Connectivity::Residual)
+            .build();
+        assert_eq!(net.layout.get_layer_sources(3), vec![2, 1]);
+        // Layer 3 reads from layer 2 and layer 1, including their bias neurons
+        assert_eq!(net.layout.neurons[net.layout.layers[3][0]].inputs.len(), 6);
+
+        let mut weights = vec![0.0; net.layout.get_number_of_weights()];
+        // Layer 1 copies the inputs, layer 2 outputs zeros
+        weights[0] = 1.0;
+        weights[4] = 1.0;
+        // Output sums the skipped layer 1
+        let output_offset = weights.len() - 6;
+        weights[output_offset + 3] = 1.0;
+        weights[output_offset + 4] = 1.0;
+        net.layout.set_weights(&weights);
+        net.fire(&[0.25, 0.5]);
+        assert!(relative_eq!(net.get_output()[0], 0.75));
+
+        let mut dense = NetworkBuilder::new()
+            .with_neurons_per_layer(&[2, 2, 2, 1])
+            .with_randomizer(&mut randomizer)
+            .with_connectivity(Connectivity::Dense)
+            .build();
+        assert_eq!(dense.layout.get_layer_sources(3), vec![2, 1, 0]);
+        assert!(dense.layout.validate().is_ok());
+    }
+
+    #[test]
+    fn topological_order_and_cycles() {
+        use crate::randomizer::DefaultRandomizer;
+        let mut randomizer = DefaultRandomizer::new();
+        let net = NetworkBuilder::new()
+            .with_neurons_per_layer(&[2, 3, 3, 1])
+            .with_randomizer(&mut randomizer)
+            .with_layer_sources(1, &[0, 2])
+            .with_layer_sources(2, &[0])
+            .with_layer_sources(3, &[1])
+            .build();
+        assert_eq!(net.layout.get_evaluation_order().unwrap(), vec![0, 2, 1, 3]);
+
+        let mut layout = net.layout.clone();
+        layout.sources[2] = vec![1];
+        assert!(layout.get_evaluation_order().is_err());
+
+        let json = serde_json::to_string(&layout).unwrap();
+        assert!(crate::Specimen::try_from_json(&json).is_err());
+        let json = serde_json::to_string(&net.layout).unwrap();
+        assert!(crate::Specimen::try_from_json(&json).is_ok());
+    }
+
+    #[test]
+    fn backpropagation_through_skip_connections() {
+        use crate::randomizer::DefaultRandomizer;
+        let mut randomizer = DefaultRandomizer::new();
+        let mut net = NetworkBuilder::new()
+            .with_neurons_per_layer(&[2, 3, 2, 1])
+            .with_randomizer(&mut randomizer)
+            .with_activation(Activation::Tanh)
+            .with_connectivity(Connectivity::Dense)
+            .build();
+        let input = [0.3, -0.7];
+        // Loss is half of the squared output
+        let loss = |net: &mut Network| {
+            net.fire(&input);
+            net.get_output()[0].powi(2) / 2.0
+        };
+
+        loss(&mut net);
+        let output = net.get_output()[0];
+        let gradient = net.backpropagate(&[output * Activation::Tanh.derivative(output)]);
+
+        let weights = net.layout.get_weights();
+        const EPSILON: f64 = 1e-6;
+        for k in 0..weights.len() {
+            let mut shifted = weights.clone();
+            shifted[k] += EPSILON;
+            net.layout.set_weights(&shifted);
+            let plus = loss(&mut net);
+            shifted[k] -= 2.0 * EPSILON;
+            net.layout.set_weights(&shifted);
+            let minus = loss(&mut net);
+            let numeric = (plus - minus) / (2.0 * EPSILON);
+            assert!(relative_eq!(gradient[k], numeric, epsilon = 1e-6), "{}", k);
+        }
+    }
+
+    #[test]
+    fn convolution_1d() {
+        use crate::randomizer::DefaultRandomizer;
+        let mut randomizer = DefaultRandomizer::new();
+        let mut net = NetworkBuilder::new()
+            .with_neurons_per_layer(&[5, 0, 1])
+            .with_randomizer(&mut randomizer)
+            .with_activation(Activation::Identity)
+            .with_input_shape(Shape::new_1d(1, 5))
+            .with_layer_kind(
+                1,
+                LayerKind::Conv1d {
+                    filters: 2,
+                    kernel: 3,
+                    stride: 2,
+                },
+            )
+            .build();
+        assert_eq!(net.layout.get_layer_shape(1), Shape::new_2d(2, 1, 2));
+        assert_eq!(net.layout.parameters[1].len(), 2 * (3 + 1));
+        assert!(net.layout.validate().is_ok());
+
+        // Output neuron has 4 convolution outputs and bias as inputs
+        let mut weights = vec![1.0, 10.0, 100.0, 1000.0, 0.0];
+        // First filter sums the window, second one picks its middle plus bias
+        weights.extend_from_slice(&[1.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.5]);
+        net.layout.set_weights(&weights);
+        net.fire(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert!(relative_eq!(
+            net.get_output()[0],
+            6.0 + 10.0 * 12.0 + 100.0 * 2.5 + 1000.0 * 4.5
+        ));
+    }
+
+    #[test]
+    fn convolution_2d_with_pooling() {
+        use crate::randomizer::DefaultRandomizer;
+        let mut randomizer = DefaultRandomizer::new();
+        let mut net = NetworkBuilder::new()
+            .with_neurons_per_layer(&[32, 0, 0, 0, 3])
+            .with_randomizer(&mut randomizer)
+            .with_input_shape(Shape::new_2d(2, 4, 4))
+            .with_layer_kind(
+                1,
+                LayerKind::Conv2d {
+                    filters: 3,
+                    kernel: 3,
+                    stride: 1,
+                },
+            )
+            .with_layer_kind(2, LayerKind::MaxPool { size: 2 })
+            .with_layer_kind(3, LayerKind::Flatten)
+            .build();
+        assert_eq!(net.layout.get_layer_shape(1), Shape::new_2d(3, 2, 2));
+        assert_eq!(net.layout.get_layer_shape(2), Shape::new_2d(3, 1, 1));
+        assert_eq!(net.layout.get_layer_shape(3), Shape::new_1d(1, 3));
+        // Shared kernels, then 3 outputs reading 3 values plus bias
+        assert_eq!(
+            net.layout.get_number_of_weights(),
+            3 * (2 * 3 * 3 + 1) + 3 * 4
+        );
+
+        let input: Vec<f64> = (0..32).map(|x| x as f64 / 32.0).collect();
+        net.fire(&input);
+        let conv = &net.layout.layers[1];
+        let maximum = conv[..4]
+            .iter()
+            .map(|id| net.layout.neurons[*id].value.unwrap())
+            .fold(f64::NEG_INFINITY, f64::max);
+        let pooled = net.layout.layers[2][0];
+        assert!(relative_eq!(
+            net.layout.neurons[pooled].value.unwrap(),
+            maximum
+        ));
+
+        let serialized = serde_json::to_string(&net.layout).unwrap();
+        let restored = crate::Specimen::try_from_json(&serialized).unwrap();
+        restored
+            .brain
+            .get_weights()
+            .iter()
+            .zip(net.layout.get_weights().iter())
+            .for_each(|(a, b)| assert!(relative_eq!(a, b)));
+
+        let mut broken = net.layout.clone();
+        broken.parameters[1].pop();
+        assert!(broken.validate().is_err());
+    }
+
+    #[test]
+    fn bounded_head_reaches_both_bounds() {
+        use crate::action::OutputHead;
+        use crate::randomizer::DefaultRandomizer;
+        let mut randomizer = DefaultRandomizer::new();
+        let mut net = NetworkBuilder::new()
+            .with_neurons_per_layer(&[1, 2])
+            .with_randomizer(&mut randomizer)
+            .with_activation(Activation::Identity)
+            .with_output_head(OutputHead::Bounded {
+                low: vec![-1.0, 0.0],
+                high: vec![1.0, 10.0],
+            })
+            .build();
+        net.layout.set_weights(&[10.0, 0.0, -10.0, 0.0]);
+        net.fire(&[1.0]);
+        let values = net
+            .layout
+            .get_action(&net.get_output(), &mut randomizer)
+            .to_values();
+        assert!(relative_eq!(values[0], 1.0, epsilon = 1e-6));
+        assert!(relative_eq!(values[1], 0.0, epsilon = 1e-6));
+        net.fire(&[-1.0]);
+        let values = net
+            .layout
+            .get_action(&net.get_output(), &mut randomizer)
+            .to_values();
+        assert!(relative_eq!(values[0], -1.0, epsilon = 1e-6));
+        assert!(relative_eq!(values[1], 10.0, epsilon = 1e-6));
+
+        let mut squashed = net.layout.clone();
+        squashed.activation = Activation::Sigmoid;
+        assert!(squashed.validate().is_err());
+    }
+
+    #[test]
+    fn malformed_layouts_are_rejected() {
+        use crate::randomizer::DefaultRandomizer;
+        let mut randomizer = DefaultRandomizer::new();
+        let net = NetworkBuilder::new()
+            .with_neurons_per_layer(&[2, 3, 1])
+            .with_randomizer(&mut randomizer)
+            .with_layer_kind(1, LayerKind::Gru)
+            .build();
+        assert!(net.layout.topology.is_some());
+
+        let mut broken = net.layout.clone();
+        broken.layers[0][1] = broken.neurons.len();
+        assert!(broken.validate().is_err());
+        assert!(broken.topology.is_none());
+
+        let mut broken = net.layout.clone();
+        broken.layers[0].clear();
+        assert!(broken.validate().is_err());
+
+        let mut broken = net.layout.clone();
+        let neuron_id = broken.layers[1][0];
+        broken.neurons[neuron_id].gates[2].pop();
+        assert!(broken.validate().is_err());
+
+        let mut broken = net.layout.clone();
+        let neuron_id = broken.layers[2][0];
+        broken.neurons[neuron_id].gates.push(vec![1.0]);
+        assert!(broken.validate().is_err());
+
+        let json = serde_json::to_string(&net.layout).unwrap();
+        let mut restored: NetworkLayout = serde_json::from_str(&json).unwrap();
+        assert!(restored.topology.is_none());
+        assert!(restored.validate().is_ok());
+        assert!(restored.topology.is_some());
+    }
+
+    #[test]
+    fn dropout_only_during_training() {
+        use crate::randomizer::DefaultRandomizer;
+        let mut randomizer = DefaultRandomizer::new();
+        let mut dense = NetworkBuilder::new()
+            .with_neurons_per_layer(&[2, 3, 1])
+            .with_randomizer(&mut randomizer)
+            .with_activation(Activation::Tanh)
+            .build();
+        let mut dropout = NetworkBuilder::new()
+            .with_neurons_per_layer(&[2, 3, 0, 1])
+            .with_randomizer(&mut randomizer)
+            .with_activation(Activation::Tanh)
+            .with_layer_kind(2, LayerKind::Dropout { rate: 0.5 })
+            .build();
+        assert!(dropout.layout.supports_backpropagation());
+        dropout.layout.set_weights(&dense.layout.get_weights());
+
+        // Identity outside of the training, including the gradient
+        let input = [0.3, -0.7];
+        dense.fire(&input);
+        dropout.fire(&input);
+        assert_eq!(dense.get_output(), dropout.get_output());
+        let delta = [dense.get_output()[0]];
+        assert_eq!(dense.backpropagate(&delta), dropout.backpropagate(&delta));
+
+        let mut dropped = 0;
+        for _ in 0..50 {
+            dropout.fire_training(&input, &mut randomizer);
+            let layers = &dropout.layout.layers;
+            for (copy, source) in layers[2].iter().zip(&layers[1]).take(3) {
+                let (copy, source) = (
+                    dropout.layout.neurons[*copy].value.unwrap(),
+                    dropout.layout.neurons[*source].value.unwrap(),
+                );
+                if copy == 0.0 {
+                    dropped += 1;
+                } else {
+                    assert!(relative_eq!(copy, 2.0 * source));
+                }
+            }
+        }
+        assert!(dropped > 0 && dropped < 150);
+
+        // Masks come from the randomizer: the angle of (-1, -1) maps to 0.125,
+        // below the rate, the angle of (1, 1) to 0.625
+        struct Constant(f64);
+        impl RandomProvider for Constant {
+            fn get_number(&mut self) -> f64 {
+                self.0
+            }
+        }
+        let kept = |dropout: &mut Network, value: f64| {
+            dropout.fire_training(&input, &mut Constant(value));
+            dropout.layout.layers[2][..3]
+                .iter()
+                .filter(|id| dropout.layout.neurons[**id].value.unwrap() != 0.0)
+                .count()
+        };
+        assert_eq!(kept(&mut dropout, -1.0), 0);
+        assert_eq!(kept(&mut dropout, 1.0), 3);
+    }
+
+    #[test]
+    fn normalization_layers() {
+        use crate::randomizer::DefaultRandomizer;
+        let mut randomizer = DefaultRandomizer::new();
+        for kind in [LayerKind::LayerNorm, LayerKind::BatchNorm { momentum: 0.5 }] {
+            let mut net = NetworkBuilder::new()
+                .with_neurons_per_layer(&[2, 4, 0, 1])
+                .with_randomizer(&mut randomizer)
+                .with_activation(Activation::Tanh)
+                .with_layer_kind(2, kind)
+                .build();
+            assert!(net.layout.validate().is_ok());
+            assert!(net.layout.supports_backpropagation());
+            let input = [0.3, -0.7];
+            let loss = |net: &mut Network| {
+                net.fire(&input);
+                net.get_output()[0].powi(2) / 2.0
+            };
+
+            // Running statistics move during the training only
+            net.fire_training(&input, &mut randomizer);
+            let statistics = net.layout.statistics.clone();
+            loss(&mut net);
+            assert_eq!(net.layout.statistics, statistics);
+            if kind == LayerKind::LayerNorm {
+                let normalized: Vec<f64> = net.layout.layers[2][..4]
+                    .iter()
+                    .map(|id| net.layout.neurons[*id].value.unwrap())
+                    .collect();
+                assert!(relative_eq!(
+                    normalized.iter().sum::<f64>(),
+                    0.0,
+                    epsilon = 1e-9
+                ));
+                let variance = normalized.iter().map(|v| v * v).sum::<f64>() / 4.0;
+                assert!(relative_eq!(variance, 1.0, epsilon = 1e-3));
+            } else {
+                assert_ne!(statistics[2], [vec![0.0; 4], vec![1.0; 4]].concat());
+            }
+
+            // Scales and shifts are trained together with the other weights
+            let mut weights = net.layout.get_weights();
+            weights
+                .iter_mut()
+                .for_each(|weight| *weight = randomizer.get_number());
+            net.layout.set_weights(&weights);
+            loss(&mut net);
+            let output = net.get_output()[0];
+            let gradient = net.backpropagate(&[output * Activation::Tanh.derivative(output)]);
+            const EPSILON: f64 = 1e-6;
+            for k in 0..weights.len() {
+                let mut shifted = weights.clone();
+                shifted[k] += EPSILON;
+                net.layout.set_weights(&shifted);
+                let plus = loss(&mut net);
+                shifted[k] -= 2.0 * EPSILON;
+                net.layout.set_weights(&shifted);
+                let minus = loss(&mut net);
+                let numeric = (plus - minus) / (2.0 * EPSILON);
+                assert!(relative_eq!(gradient[k], numeric, epsilon = 1e-6), "{}", k);
+            }
+        }
+    }
+
+    #[test]
+    fn batch_norm_running_statistics() {
+        use crate::randomizer::DefaultRandomizer;
+        let mut randomizer = DefaultRandomizer::new();
+        let mut net = NetworkBuilder::new()
+            .with_neurons_per_layer(&[1, 0, 1])
+            .with_randomizer(&mut randomizer)
+            .with_layer_kind(1, LayerKind::BatchNorm { momentum: 0.5 })
+            .build();
+        assert_eq!(net.layout.statistics[1], vec![0.0, 1.0]);
+
+        // Mean 0.5 * 0 + 0.5 * 2 = 1, variance 0.5 * 1 + 0.5 * (2 - 0)^2 = 2.5
+        net.fire_training(&[2.0], &mut randomizer);
+        assert_eq!(net.layout.statistics[1], vec![1.0, 2.5]);
+        // Mean 0.5 * 1 + 0.5 * 4 = 2.5, variance 0.5 * 2.5 + 0.5 * (4 - 1)^2 = 5.75
+        net.fire_training(&[4.0], &mut randomizer);
+        assert_eq!(net.layout.statistics[1], vec![2.5, 5.75]);
+    }
 }
@@ -5,8 +5,18 @@ use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Neuron {
     pub(crate) value: Option<f64>,
-    bias: bool,
+    pub(crate) bias: bool,
     pub(crate) inputs: Vec<f64>,
+    /// Additional weight vectors used by recurrent cells, e.g. the gates of LSTM.
+    #[serde(default)]
+    pub(crate) gates: Vec<Vec<f64>>,
+    /// Internal state kept between the ticks, e.g. the cell state of LSTM.
+    #[serde(default)]
+    pub(crate) state: f64,
+    /// Positions of the inputs the weights belong to, once some of the
+    /// connections were pruned. All inputs are connected if `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) connections: Option<Vec<usize>>,
 }
 
 impl Neuron {
@@ -28,6 +38,85 @@ impl Neuron {
                 }
                 inputs
             },
+            gates: vec![],
+            state: 0.0,
+            connections: None,
         }
     }
+
+    /// Returns the position of the input the specified weight belongs to.
+    pub(crate) fn get_input_position(&self, weight_index: usize) -> usize {
+        self.connections
+            .as_ref()
+            .map_or(weight_index, |connections| connections[weight_index])
+    }
+
+    /// Checks that the weights match the number of inputs of the layer.
+    pub(crate) fn matches_inputs(&self, number_of_inputs: usize) -> bool {
+        match &self.connections {
+            Some(connections) => {
+                connections.len() == self.inputs.len()
+                    && connections.windows(2).all(|pair| pair[0] < pair[1])
+                    && connections
+                        .last()
+                        .map_or(true, |last| *last < number_of_inputs)
+            }
+            None => self.inputs.len() == number_of_inputs,
+        }
+    }
+
+    /// Makes room for a new input at the specified position. Pruned neurons
+    /// stay disconnected from it.
+    pub(crate) fn insert_input(&mut self, position: usize, weight: f64) {
+        match &mut self.connections {
+            Some(connections) => connections
+                .iter_mut()
+                .filter(|connection| **connection >= position)
+                .for_each(|connection| *connection += 1),
+            None => self.inputs.insert(position, weight),
+        }
+    }
+
+    /// Removes the input at the specified position, together with its weight.
+    pub(crate) fn remove_input(&mut self, position: usize) {
+        match &mut self.connections {
+            Some(connections) => {
+                if let Some(index) = connections.iter().position(|c| *c == position) {
+                    connections.remove(index);
+                    self.inputs.remove(index);
+                }
+                connections
+                    .iter_mut()
+                    .filter(|connection| **connection > position)
+                    .for_each(|connection| *connection -= 1);
+            }
+            None => {
+                self.inputs.remove(position);
+            }
+        }
+    }
+
+    /// Prunes the connection of the specified weight.
+    pub(crate) fn disconnect(&mut self, weight_index: usize) {
+        let number_of_inputs = self.inputs.len();
+        self.connections
+            .get_or_insert_with(|| (0..number_of_inputs).collect())
+            .remove(weight_index);
+        self.inputs.remove(weight_index);
+    }
+
+    /// Iterates over all weights of the neuron: inputs followed by the gates.
+    pub(crate) fn weights(&self) -> impl Iterator<Item = &f64> {
+        self.inputs.iter().chain(self.gates.iter().flatten())
+    }
+
+    pub(crate) fn weights_mut(&mut self) -> impl Iterator<Item = &mut f64> {
+        self.inputs
+            .iter_mut()
+            .chain(self.gates.iter_mut().flatten())
+    }
+
+    pub(crate) fn get_number_of_weights(&self) -> usize {
+        self.inputs.len() + self.gates.iter().map(|gate| gate.len()).sum::<usize>()
+    }
 }
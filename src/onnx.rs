@@ -0,0 +1,471 @@
+use crate::action::OutputHead;
+use crate::network::{Activation, DenseLayout};
+
+/// Version of the ONNX intermediate representation the models are written in.
+const IR_VERSION: u64 = 8;
+/// Version of the default operator set used by the nodes.
+const OPSET_VERSION: u64 = 13;
+/// `TensorProto.DataType` of 32-bit floats.
+const FLOAT: u64 = 1;
+/// `TensorProto.DataType` of 64-bit integers.
+const INT64: u64 = 7;
+/// `AttributeProto.AttributeType` of integers.
+const INT: u64 = 2;
+
+/// Protocol buffers message, written field by field.
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn varint(&mut self, value: u64) {
+        let mut value = value;
+        while value >= 0x80 {
+            self.0.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn tag(&mut self, field: u64, wire_type: u64) {
+        self.varint(field << 3 | wire_type);
+    }
+
+    fn int(&mut self, field: u64, value: u64) -> &mut Self {
+        self.tag(field, 0);
+        self.varint(value);
+        self
+    }
+
+    fn bytes(&mut self, field: u64, bytes: &[u8]) -> &mut Self {
+        self.tag(field, 2);
+        self.varint(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+        self
+    }
+
+    fn string(&mut self, field: u64, value: &str) -> &mut Self {
+        self.bytes(field, value.as_bytes())
+    }
+
+    fn message(&mut self, field: u64, message: &Message) -> &mut Self {
+        self.bytes(field, &message.0)
+    }
+}
+
+/// `ValueInfoProto` of a tensor with dynamic batch size.
+fn value_info(name: &str, data_type: u64, size: usize) -> Message {
+    let mut batch = Message::default();
+    batch.string(2, "N");
+    let mut width = Message::default();
+    width.int(1, size as u64);
+    let mut shape = Message::default();
+    shape.message(1, &batch).message(1, &width);
+    let mut tensor = Message::default();
+    tensor.int(1, data_type).message(2, &shape);
+    let mut type_proto = Message::default();
+    type_proto.message(1, &tensor);
+    let mut info = Message::default();
+    info.string(1, name).message(2, &type_proto);
+    info
+}
+
+/// `TensorProto` of an initializer, weights stored as little-endian raw data.
+fn tensor(name: &str, dims: &[usize], values: &[f64]) -> Message {
+    let mut tensor = Message::default();
+    dims.iter().for_each(|dim| {
+        tensor.int(1, *dim as u64);
+    });
+    tensor.int(2, FLOAT).string(8, name);
+    let raw: Vec<u8> = values
+        .iter()
+        .flat_map(|value| (*value as f32).to_le_bytes())
+        .collect();
+    tensor.bytes(9, &raw);
+    tensor
+}
+
+fn node(op_type: &str, inputs: &[&str], output: &str, attributes: &[(&str, u64)]) -> Message {
+    let mut node = Message::default();
+    inputs.iter().for_each(|input| {
+        node.string(1, input);
+    });
+    node.string(2, output)
+        .string(3, &format!("{}_{}", op_type, output))
+        .string(4, op_type);
+    for (name, value) in attributes {
+        let mut attribute = Message::default();
+        attribute.string(1, name).int(3, *value).int(20, INT);
+        node.message(5, &attribute);
+    }
+    node
+}
+
+/// Exports the brain of the specimen as an ONNX model.
+///
+/// Each layer becomes a `Gemm` node followed by the node of the activation
+/// (`Sigmoid`, `Tanh`, `Relu` or `Identity`). Layers reading from several
+/// sources concatenate them first. Bias neurons are not part of the tensors,
+/// their weights form the bias vector of the `Gemm` (summed, if the layer
+/// reads from several sources). Model reads the `input` tensor of shape
+/// `[N, inputs]` and writes the `output` tensor of shape `[N, outputs]`,
+/// weights are exported as 32-bit floats.
+///
+/// The softmax head adds the `Div` by the temperature and the `Softmax` node,
+/// the argmax head adds the `ArgMax` node writing the `output` of shape
+/// `[N, 1]` with 64-bit integer indices. Other heads need the randomizer or
+/// bounds and cannot be exported.
+///
+/// Only the networks consisting of dense layers can be exported.
+pub fn export(specimen: &crate::Specimen) -> Result<Vec<u8>, String> {
+    let layout = &specimen.brain;
+    let DenseLayout { sizes, order } = layout.get_dense_layout("exported")?;
+    let last = sizes.len() - 1;
+    let value_name = |layer_index: usize| match layer_index {
+        0 => "input".to_string(),
+        _ if layer_index == last && layout.head == OutputHead::Raw => "output".to_string(),
+        _ => format!("layer_{}", layer_index),
+    };
+
+    let mut graph = Message::default();
+    let mut initializers = vec![];
+    for layer_index in order.into_iter().skip(1) {
+        let sources = layout.get_layer_sources(layer_index);
+        let number_of_inputs: usize = sources.iter().map(|source| sizes[*source]).sum();
+        let mut weights = vec![0.0; sizes[layer_index] * number_of_inputs];
+        let mut biases = vec![0.0; sizes[layer_index]];
+        let neurons = layout.layers[layer_index]
+            .iter()
+            .map(|neuron_id| &layout.neurons[*neuron_id])
+            .filter(|neuron| !neuron.bias);
+        for (row, neuron) in neurons.enumerate() {
+            for (j, weight) in neuron.inputs.iter().enumerate() {
+                // Position among the inputs, without the bias neurons of the sources
+                let mut position = neuron.get_input_position(j);
+                let mut column = 0;
+                for source in &sources {
+                    if position < sizes[*source] {
+                        weights[row * number_of_inputs + column + position] = *weight;
+                        break;
+                    }
+                    if position == sizes[*source] {
+                        biases[row] += weight * crate::BIAS_VALUE;
+                        break;
+                    }
+                    position -= sizes[*source] + 1;
+                    column += sizes[*source];
+                }
+            }
+        }
+
+        let input = if sources.len() == 1 {
+            value_name(sources[0])
+        } else {
+            let names: Vec<String> = sources.iter().map(|source| value_name(*source)).collect();
+            let names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+            let concatenated = format!("concat_{}", layer_index);
+            graph.message(1, &node("Concat", &names, &concatenated, &[("axis", 1)]));
+            concatenated
+        };
+        let (weights_name, biases_name, gemm) = (
+            format!("weights_{}", layer_index),
+            format!("biases_{}", layer_index),
+            format!("gemm_{}", layer_index),
+        );
+        graph.message(
+            1,
+            &node(
+                "Gemm",
+                &[&input, &weights_name, &biases_name],
+                &gemm,
+                &[("transB", 1)],
+            ),
+        );
+        let activation = match layout.activation {
+            Activation::Sigmoid => "Sigmoid",
+            Activation::Tanh => "Tanh",
+            Activation::Relu => "Relu",
+            Activation::Identity => "Identity",
+        };
+        graph.message(
+            1,
+            &node(activation, &[&gemm], &value_name(layer_index), &[]),
+        );
+        initializers.push(tensor(
+            &weights_name,
+            &[sizes[layer_index], number_of_inputs],
+            &weights,
+        ));
+        initializers.push(tensor(&biases_name, &[sizes[layer_index]], &biases));
+    }
+    let output = match &layout.head {
+        OutputHead::Raw => value_info("output", FLOAT, sizes[last]),
+        OutputHead::Softmax { temperature } => {
+            initializers.push(tensor("temperature", &[], &[*temperature]));
+            graph
+                .message(
+                    1,
+                    &node("Div", &[&value_name(last), "temperature"], "scaled", &[]),
+                )
+                .message(1, &node("Softmax", &["scaled"], "output", &[("axis", 1)]));
+            value_info("output", FLOAT, sizes[last])
+        }
+        OutputHead::Argmax => {
+            graph.message(
+                1,
+                &node(
+                    "ArgMax",
+                    &[&value_name(last)],
+                    "output",
+                    &[("axis", 1), ("keepdims", 1)],
+                ),
+            );
+            value_info("output", INT64, 1)
+        }
+        _ => return Err("Only raw, softmax and argmax heads can be exported".to_string()),
+    };
+    graph.string(2, "easyneural");
+    initializers.iter().for_each(|initializer| {
+        graph.message(5, initializer);
+    });
+    graph
+        .message(11, &value_info("input", FLOAT, sizes[0]))
+        .message(12, &output);
+
+    let mut opset = Message::default();
+    opset.string(1, "").int(2, OPSET_VERSION);
+    let mut model = Message::default();
+    model
+        .int(1, IR_VERSION)
+        .string(2, "easyneural")
+        .string(3, env!("CARGO_PKG_VERSION"))
+        .message(7, &graph)
+        .message(8, &opset);
+    Ok(model.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::action::{argmax, softmax, OutputHead};
+    use crate::network::{build_exporter_fixture, Activation};
+    use crate::onnx::export;
+    use crate::randomizer::DefaultRandomizer;
+    use crate::training_ground::Exercise;
+
+    #[derive(Debug)]
+    enum Field {
+        Varint(u64),
+        Bytes(Vec<u8>),
+    }
+
+    fn decode(bytes: &[u8]) -> Vec<(u64, Field)> {
+        let mut position = 0;
+        let varint = |position: &mut usize| {
+            let mut value = 0;
+            let mut shift = 0;
+            loop {
+                let byte = bytes[*position];
+                *position += 1;
+                value |= ((byte & 0x7f) as u64) << shift;
+                shift += 7;
+                if byte < 0x80 {
+                    return value;
+                }
+            }
+        };
+        let mut fields = vec![];
+        while position < bytes.len() {
+            let tag = varint(&mut position);
+            let field = match tag & 7 {
+                0 => Field::Varint(varint(&mut position)),
+                2 => {
+                    let length = varint(&mut position) as usize;
+                    position += length;
+                    Field::Bytes(bytes[position - length..position].to_vec())
+                }
+                wire_type => panic!("Unexpected wire type {}", wire_type),
+            };
+            fields.push((tag >> 3, field));
+        }
+        fields
+    }
+
+    fn messages(fields: &[(u64, Field)], number: u64) -> Vec<Vec<(u64, Field)>> {
+        bytes(fields, number).iter().map(|b| decode(b)).collect()
+    }
+
+    fn bytes(fields: &[(u64, Field)], number: u64) -> Vec<Vec<u8>> {
+        fields
+            .iter()
+            .filter_map(|(n, field)| match field {
+                Field::Bytes(bytes) if *n == number => Some(bytes.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn strings(fields: &[(u64, Field)], number: u64) -> Vec<String> {
+        bytes(fields, number)
+            .into_iter()
+            .map(|bytes| String::from_utf8(bytes).unwrap())
+            .collect()
+    }
+
+    fn ints(fields: &[(u64, Field)], number: u64) -> Vec<u64> {
+        fields
+            .iter()
+            .filter_map(|(n, field)| match field {
+                Field::Varint(value) if *n == number => Some(*value),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Reads the initializers of the graph, by name: dimensions and values.
+    fn initializers(graph: &[(u64, Field)]) -> HashMap<String, (Vec<u64>, Vec<f64>)> {
+        messages(graph, 5)
+            .iter()
+            .map(|initializer| {
+                assert_eq!(ints(initializer, 2), vec![1]);
+                let values = bytes(initializer, 9)[0]
+                    .chunks(4)
+                    .map(|chunk| {
+                        f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as f64
+                    })
+                    .collect();
+                (
+                    strings(initializer, 8)[0].clone(),
+                    (ints(initializer, 1), values),
+                )
+            })
+            .collect()
+    }
+
+    /// Runs the graph, returning the values of the `output`.
+    fn run(graph: &[(u64, Field)], input: &[f64]) -> Vec<f64> {
+        let tensors = initializers(graph);
+        let mut values: HashMap<String, Vec<f64>> = HashMap::new();
+        values.insert("input".to_string(), input.to_vec());
+        for node in &messages(graph, 1) {
+            let inputs = strings(node, 1);
+            let result = match strings(node, 4)[0].as_str() {
+                "Concat" => inputs
+                    .iter()
+                    .flat_map(|name| values[name].clone())
+                    .collect(),
+                "Gemm" => {
+                    let attribute = &messages(node, 5)[0];
+                    assert_eq!(strings(attribute, 1), vec!["transB"]);
+                    assert_eq!(ints(attribute, 3), vec![1]);
+                    let (dims, weights) = &tensors[&inputs[1]];
+                    let biases = &tensors[&inputs[2]].1;
+                    let x = &values[&inputs[0]];
+                    (0..dims[0] as usize)
+                        .map(|row| {
+                            let columns = dims[1] as usize;
+                            biases[row]
+                                + (0..columns)
+                                    .map(|c| weights[row * columns + c] * x[c])
+                                    .sum::<f64>()
+                        })
+                        .collect()
+                }
+                "Tanh" => values[&inputs[0]].iter().map(|x| x.tanh()).collect(),
+                "Div" => {
+                    let divisor = tensors[&inputs[1]].1[0];
+                    values[&inputs[0]].iter().map(|x| x / divisor).collect()
+                }
+                "Softmax" => softmax(&values[&inputs[0]], 1.0),
+                "ArgMax" => vec![argmax(&values[&inputs[0]]) as f64],
+                op_type => panic!("Unexpected operator {}", op_type),
+            };
+            values.insert(strings(node, 2)[0].clone(), result);
+        }
+        values.remove("output").unwrap()
+    }
+
+    #[test]
+    fn exported_graph_matches_network() {
+        let mut randomizer = DefaultRandomizer::new();
+        let specimen = build_exporter_fixture(Activation::Tanh, &mut randomizer);
+
+        let model = decode(&export(&specimen).unwrap());
+        assert_eq!(ints(&model, 1), vec![8]);
+        assert_eq!(ints(&messages(&model, 8)[0], 2), vec![13]);
+        let graph = &messages(&model, 7)[0];
+
+        let shape = |info: &[(u64, Field)]| -> (String, Vec<u64>) {
+            let tensor = &messages(&messages(info, 2)[0], 1)[0];
+            let dims = messages(&messages(tensor, 2)[0], 1);
+            assert_eq!(strings(&dims[0], 2), vec!["N"]);
+            (strings(info, 1)[0].clone(), ints(&dims[1], 1))
+        };
+        assert_eq!(
+            shape(&messages(graph, 11)[0]),
+            ("input".to_string(), vec![3])
+        );
+        assert_eq!(
+            shape(&messages(graph, 12)[0]),
+            ("output".to_string(), vec![2])
+        );
+
+        let tensors = initializers(graph);
+        assert_eq!(tensors["weights_1"].0, vec![5, 3]);
+        assert_eq!(tensors["weights_2"].0, vec![4, 5 + 3]);
+        assert_eq!(tensors["weights_3"].0, vec![2, 4 + 5]);
+        assert_eq!(tensors["biases_3"].0, vec![2]);
+        // Disconnected input has zero weight
+        assert_eq!(tensors["weights_2"].1[8], 0.0);
+
+        let op_types: Vec<String> = messages(graph, 1)
+            .iter()
+            .map(|node| strings(node, 4)[0].clone())
+            .collect();
+        assert_eq!(
+            op_types,
+            vec!["Gemm", "Tanh", "Concat", "Gemm", "Tanh", "Concat", "Gemm", "Tanh"]
+        );
+
+        let input = [0.3, -0.7, 1.2];
+        let expected = Exercise::new(&specimen).get_output(&input);
+        run(graph, &input)
+            .iter()
+            .zip(expected.iter())
+            .for_each(|(actual, expected)| assert!(relative_eq!(actual, expected, epsilon = 1e-5)));
+    }
+
+    #[test]
+    fn heads_are_exported() {
+        let mut randomizer = DefaultRandomizer::new();
+        let mut specimen = build_exporter_fixture(Activation::Tanh, &mut randomizer);
+        let input = [0.3, -0.7, 1.2];
+        let outputs = Exercise::new(&specimen).get_output(&input);
+        let graph = |specimen: &crate::Specimen| {
+            let model = decode(&export(specimen).unwrap());
+            messages(&model, 7).remove(0)
+        };
+
+        specimen.brain.head = OutputHead::Softmax { temperature: 2.0 };
+        run(&graph(&specimen), &input)
+            .iter()
+            .zip(softmax(&outputs, 2.0).iter())
+            .for_each(|(actual, expected)| assert!(relative_eq!(actual, expected, epsilon = 1e-5)));
+
+        specimen.brain.head = OutputHead::Argmax;
+        let graph = graph(&specimen);
+        assert_eq!(run(&graph, &input), vec![argmax(&outputs) as f64]);
+        let output = &messages(&graph, 12)[0];
+        let tensor = &messages(&messages(output, 2)[0], 1)[0];
+        assert_eq!(ints(tensor, 1), vec![7]);
+
+        specimen.brain.head = OutputHead::Sample { temperature: 1.0 };
+        assert!(export(&specimen).is_err());
+        specimen.brain.head = OutputHead::Raw;
+        let weight_id = specimen.brain.layers[1][0];
+        specimen.brain.neurons[weight_id].inputs[0] = f64::NAN;
+        assert!(export(&specimen).is_err());
+        specimen.brain.layers.truncate(1);
+        assert!(export(&specimen).is_err());
+    }
+}
@@ -0,0 +1,368 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::regularization::Regularization;
+use crate::supervised::Loss;
+
+const DEFAULT_RMSPROP_DECAY: f64 = 0.9;
+const DEFAULT_ADAM_BETA_1: f64 = 0.9;
+const DEFAULT_ADAM_BETA_2: f64 = 0.999;
+const DEFAULT_EPSILON: f64 = 1e-8;
+
+/// Updates the weights of the network using the gradient of the loss.
+///
+/// `easyneural` comes with several optimizers, but you can use
+/// your own by implementing this trait.
+pub trait Optimizer {
+    /// Moves the weights against the gradient. Both slices are laid out
+    /// in the same order and keep their length between the calls.
+    fn step(&mut self, weights: &mut [f64], gradient: &[f64]);
+}
+
+/// Changes the learning rate as the training progresses.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub enum Schedule {
+    /// Learning rate never changes.
+    #[default]
+    Constant,
+    /// Learning rate is multiplied by `gamma` every `every` steps.
+    Step { every: usize, gamma: f64 },
+    /// Learning rate follows a half cosine from the base rate down to
+    /// `minimum` over `period` steps, and stays there afterwards.
+    Cosine { period: usize, minimum: f64 },
+    /// Learning rate grows linearly from zero during the first `steps`
+    /// steps, then continues according to the wrapped schedule.
+    Warmup { steps: usize, then: Box<Schedule> },
+}
+
+impl Schedule {
+    /// Returns the learning rate to be used at the given step (counting from zero).
+    pub fn get_learning_rate(&self, base: f64, step: usize) -> f64 {
+        match self {
+            Schedule::Constant => base,
+            Schedule::Step { every, gamma } => base * gamma.powi((step / (*every).max(1)) as i32),
+            Schedule::Cosine { period, minimum } => {
+                let progress = (step as f64 / (*period).max(1) as f64).min(1.0);
+                minimum + (base - minimum) * (1.0 + (std::f64::consts::PI * progress).cos()) / 2.0
+            }
+            Schedule::Warmup { steps, then } => {
+                if step < *steps {
+                    base * (step + 1) as f64 / *steps as f64
+                } else {
+                    then.get_learning_rate(base, step - steps)
+                }
+            }
+        }
+    }
+}
+
+fn ensure_size(state: &mut Vec<f64>, size: usize) {
+    if state.len() != size {
+        state.clear();
+        state.resize(size, 0.0);
+    }
+}
+
+/// Stochastic gradient descent with optional (Nesterov) momentum.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Sgd {
+    learning_rate: f64,
+    momentum: f64,
+    nesterov: bool,
+    schedule: Schedule,
+    step: usize,
+    velocity: Vec<f64>,
+}
+
+impl Sgd {
+    pub fn new(learning_rate: f64) -> Sgd {
+        Sgd {
+            learning_rate,
+            momentum: 0.0,
+            nesterov: false,
+            schedule: Schedule::Constant,
+            step: 0,
+            velocity: vec![],
+        }
+    }
+
+    /// Enables classical momentum.
+    pub fn with_momentum(mut self, momentum: f64) -> Self {
+        self.momentum = momentum;
+        self.nesterov = false;
+        self
+    }
+
+    /// Enables Nesterov momentum.
+    pub fn with_nesterov_momentum(mut self, momentum: f64) -> Self {
+        self.momentum = momentum;
+        self.nesterov = true;
+        self
+    }
+
+    pub fn with_schedule(mut self, schedule: Schedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+}
+
+impl Optimizer for Sgd {
+    fn step(&mut self, weights: &mut [f64], gradient: &[f64]) {
+        ensure_size(&mut self.velocity, weights.len());
+        let learning_rate = self
+            .schedule
+            .get_learning_rate(self.learning_rate, self.step);
+        self.step += 1;
+        for ((weight, g), velocity) in weights
+            .iter_mut()
+            .zip(gradient.iter())
+            .zip(self.velocity.iter_mut())
+        {
+            *velocity = self.momentum * *velocity + g;
+            let direction = if self.nesterov {
+                g + self.momentum * *velocity
+            } else {
+                *velocity
+            };
+            *weight -= learning_rate * direction;
+        }
+    }
+}
+
+/// RMSProp - gradient is scaled by the running average of its magnitude.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RmsProp {
+    learning_rate: f64,
+    decay: f64,
+    epsilon: f64,
+    schedule: Schedule,
+    step: usize,
+    mean_square: Vec<f64>,
+}
+
+impl RmsProp {
+    /// Creates new optimizer with the decay rate of `0.9`.
+    pub fn new(learning_rate: f64) -> RmsProp {
+        RmsProp {
+            learning_rate,
+            decay: DEFAULT_RMSPROP_DECAY,
+            epsilon: DEFAULT_EPSILON,
+            schedule: Schedule::Constant,
+            step: 0,
+            mean_square: vec![],
+        }
+    }
+
+    pub fn with_decay(mut self, decay: f64) -> Self {
+        self.decay = decay;
+        self
+    }
+
+    pub fn with_schedule(mut self, schedule: Schedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+}
+
+impl Optimizer for RmsProp {
+    fn step(&mut self, weights: &mut [f64], gradient: &[f64]) {
+        ensure_size(&mut self.mean_square, weights.len());
+        let learning_rate = self
+            .schedule
+            .get_learning_rate(self.learning_rate, self.step);
+        self.step += 1;
+        for ((weight, g), mean_square) in weights
+            .iter_mut()
+            .zip(gradient.iter())
+            .zip(self.mean_square.iter_mut())
+        {
+            *mean_square = self.decay * *mean_square + (1.0 - self.decay) * g * g;
+            *weight -= learning_rate * g / (mean_square.sqrt() + self.epsilon);
+        }
+    }
+}
+
+/// Adam, optionally with decoupled weight decay (AdamW).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Adam {
+    learning_rate: f64,
+    beta_1: f64,
+    beta_2: f64,
+    epsilon: f64,
+    weight_decay: f64,
+    schedule: Schedule,
+    step: usize,
+    first_moment: Vec<f64>,
+    second_moment: Vec<f64>,
+}
+
+impl Adam {
+    /// Creates new optimizer with `beta1 = 0.9` and `beta2 = 0.999`.
+    pub fn new(learning_rate: f64) -> Adam {
+        Adam {
+            learning_rate,
+            beta_1: DEFAULT_ADAM_BETA_1,
+            beta_2: DEFAULT_ADAM_BETA_2,
+            epsilon: DEFAULT_EPSILON,
+            weight_decay: 0.0,
+            schedule: Schedule::Constant,
+            step: 0,
+            first_moment: vec![],
+            second_moment: vec![],
+        }
+    }
+
+    /// Turns Adam into AdamW, which shrinks the weights by
+    /// `learning_rate * weight_decay` independently of the gradient.
+    pub fn with_decoupled_weight_decay(mut self, weight_decay: f64) -> Self {
+        self.weight_decay = weight_decay;
+        self
+    }
+
+    pub fn with_betas(mut self, beta_1: f64, beta_2: f64) -> Self {
+        self.beta_1 = beta_1;
+        self.beta_2 = beta_2;
+        self
+    }
+
+    pub fn with_schedule(mut self, schedule: Schedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+}
+
+impl Optimizer for Adam {
+    fn step(&mut self, weights: &mut [f64], gradient: &[f64]) {
+        ensure_size(&mut self.first_moment, weights.len());
+        ensure_size(&mut self.second_moment, weights.len());
+        let learning_rate = self
+            .schedule
+            .get_learning_rate(self.learning_rate, self.step);
+        self.step += 1;
+        let correction_1 = 1.0 - self.beta_1.powi(self.step as i32);
+        let correction_2 = 1.0 - self.beta_2.powi(self.step as i32);
+        for (((weight, g), m), v) in weights
+            .iter_mut()
+            .zip(gradient.iter())
+            .zip(self.first_moment.iter_mut())
+            .zip(self.second_moment.iter_mut())
+        {
+            *m = self.beta_1 * *m + (1.0 - self.beta_1) * g;
+            *v = self.beta_2 * *v + (1.0 - self.beta_2) * g * g;
+            *weight -= learning_rate
+                * ((*m / correction_1) / ((*v / correction_2).sqrt() + self.epsilon)
+                    + self.weight_decay * *weight);
+        }
+    }
+}
+
+/// Everything that is needed to resume interrupted supervised training.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Checkpoint<O> {
+    pub specimen: crate::Specimen,
+    pub optimizer: O,
+    /// Number of epochs completed so far.
+    pub epoch: usize,
+    pub loss: Loss,
+    pub batch_size: usize,
+    pub regularization: Regularization,
+}
+
+impl<O: Serialize + DeserializeOwned> Checkpoint<O> {
+    pub fn from_json(j: &str) -> Result<Checkpoint<O>, String> {
+        let mut checkpoint: Checkpoint<O> = serde_json::from_str(j).map_err(|e| e.to_string())?;
+        checkpoint.specimen.brain.validate()?;
+        if checkpoint.batch_size == 0 {
+            return Err("Batch size must be positive".to_string());
+        }
+        checkpoint.regularization.validate()?;
+        Ok(checkpoint)
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string(self).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::optimizer::{Adam, Optimizer, RmsProp, Schedule, Sgd};
+
+    /// Minimizes `(x - 3)^2 + (y + 1)^2`.
+    fn minimize(optimizer: &mut dyn Optimizer, steps: usize) -> [f64; 2] {
+        let mut weights = [0.0, 0.0];
+        for _ in 0..steps {
+            let gradient = [2.0 * (weights[0] - 3.0), 2.0 * (weights[1] + 1.0)];
+            optimizer.step(&mut weights, &gradient);
+        }
+        weights
+    }
+
+    #[test]
+    fn optimizers_find_minimum() {
+        let mut optimizers: Vec<Box<dyn Optimizer>> = vec![
+            Box::new(Sgd::new(0.1)),
+            Box::new(Sgd::new(0.05).with_momentum(0.9)),
+            Box::new(Sgd::new(0.05).with_nesterov_momentum(0.9)),
+            Box::new(RmsProp::new(0.05).with_schedule(Schedule::Step {
+                every: 200,
+                gamma: 0.5,
+            })),
+            Box::new(Adam::new(0.1)),
+            Box::new(Adam::new(0.1).with_decoupled_weight_decay(1e-4)),
+        ];
+        for optimizer in optimizers.iter_mut() {
+            let weights = minimize(optimizer.as_mut(), 1000);
+            assert!(relative_eq!(weights[0], 3.0, epsilon = 1e-2));
+            assert!(relative_eq!(weights[1], -1.0, epsilon = 1e-2));
+        }
+    }
+
+    #[test]
+    fn schedules() {
+        assert!(relative_eq!(
+            Schedule::Constant.get_learning_rate(0.5, 100),
+            0.5
+        ));
+
+        let step = Schedule::Step {
+            every: 10,
+            gamma: 0.1,
+        };
+        assert!(relative_eq!(step.get_learning_rate(1.0, 9), 1.0));
+        assert!(relative_eq!(step.get_learning_rate(1.0, 25), 0.01));
+
+        let cosine = Schedule::Cosine {
+            period: 100,
+            minimum: 0.1,
+        };
+        assert!(relative_eq!(cosine.get_learning_rate(1.0, 0), 1.0));
+        assert!(relative_eq!(cosine.get_learning_rate(1.0, 50), 0.55));
+        assert!(relative_eq!(cosine.get_learning_rate(1.0, 500), 0.1));
+
+        let warmup = Schedule::Warmup {
+            steps: 4,
+            then: Box::new(cosine),
+        };
+        assert!(relative_eq!(warmup.get_learning_rate(1.0, 0), 0.25));
+        assert!(relative_eq!(warmup.get_learning_rate(1.0, 3), 1.0));
+        assert!(relative_eq!(warmup.get_learning_rate(1.0, 54), 0.55));
+    }
+
+    #[test]
+    fn state_survives_serialization() {
+        let mut original = Adam::new(0.05).with_schedule(Schedule::Warmup {
+            steps: 5,
+            then: Box::new(Schedule::Constant),
+        });
+        minimize(&mut original, 20);
+        let mut restored: Adam =
+            serde_json::from_str(&serde_json::to_string(&original).unwrap()).unwrap();
+
+        let mut a = [1.0, 1.0];
+        let mut b = [1.0, 1.0];
+        original.step(&mut a, &[0.3, -0.7]);
+        restored.step(&mut b, &[0.3, -0.7]);
+        assert_eq!(a, b);
+    }
+}
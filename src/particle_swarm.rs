@@ -0,0 +1,283 @@
+use std::marker::PhantomData;
+
+use rand::Rng;
+
+use crate::network::{NetworkBuilder, NetworkLayout};
+use crate::randomizer::{DefaultRandomizer, RandomProvider};
+use crate::simulating_world::SimulatingWorld;
+use crate::simulation::{evaluate, Finish};
+
+const DEFAULT_INERTIA: f64 = 0.7298;
+const DEFAULT_ACCELERATION: f64 = 1.49618;
+
+/// Defines which particles share their best positions with each other.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Topology {
+    /// Every particle is attracted by the best position found by the whole swarm.
+    Global,
+    /// Particles are arranged in a ring and each one is attracted by the best
+    /// position found among itself and `radius` neighbours on both sides.
+    Ring { radius: usize },
+}
+
+struct Particle {
+    position: Vec<f64>,
+    velocity: Vec<f64>,
+    best_position: Vec<f64>,
+    best_fitness: f64,
+}
+
+/// Trainer that uses particle swarm optimisation.
+///
+/// Each particle is a set of network weights flying through the weight space
+/// with its own velocity. It is pulled towards the best position it has visited
+/// so far and towards the best position known to its neighbourhood.
+pub struct ParticleSwarm<T: SimulatingWorld> {
+    particles: Vec<Particle>,
+    template: NetworkLayout,
+    inertia: f64,
+    cognitive: f64,
+    social: f64,
+    topology: Topology,
+    velocity_limit: Option<f64>,
+    /// Used by the output heads sampling the actions.
+    randomizer: DefaultRandomizer,
+    counter: usize,
+    world: PhantomData<T>,
+}
+
+impl<T: SimulatingWorld> ParticleSwarm<T> {
+    /// Creates new particle swarm trainer.
+    ///
+    /// `inertia` defaults to `0.7298`, `cognitive` and `social` acceleration
+    /// coefficients default to `1.49618` (Clerc's constriction values).
+    /// Initial positions are random networks, initial velocities are zero.
+    pub fn new(
+        population_size: usize,
+        neurons_per_layer: &[usize],
+        randomizer: &mut dyn RandomProvider,
+        inertia: Option<f64>,
+        cognitive: Option<f64>,
+        social: Option<f64>,
+    ) -> Result<ParticleSwarm<T>, String> {
+        use crate::MINIMUM_POPULATION_SIZE;
+
+        if population_size < MINIMUM_POPULATION_SIZE {
+            return Err(format!(
+                "Population too small, minimum size={}",
+                MINIMUM_POPULATION_SIZE
+            ));
+        }
+
+        let layouts: Vec<NetworkLayout> = std::iter::repeat_with(|| {
+            NetworkBuilder::new()
+                .with_neurons_per_layer(neurons_per_layer)
+                .with_randomizer(randomizer)
+                .build()
+                .layout
+        })
+        .take(population_size)
+        .collect();
+
+        let particles = layouts
+            .iter()
+            .map(|layout| {
+                let position = layout.get_weights();
+                Particle {
+                    velocity: vec![0.0; position.len()],
+                    best_position: position.clone(),
+                    best_fitness: evaluate::<T>(layout, randomizer).fitness,
+                    position,
+                }
+            })
+            .collect();
+
+        Ok(ParticleSwarm {
+            particles,
+            template: layouts.into_iter().next().expect("Empty population"),
+            inertia: inertia.unwrap_or(DEFAULT_INERTIA),
+            cognitive: cognitive.unwrap_or(DEFAULT_ACCELERATION),
+            social: social.unwrap_or(DEFAULT_ACCELERATION),
+            topology: Topology::Global,
+            velocity_limit: None,
+            randomizer: DefaultRandomizer::new(),
+            counter: 0,
+            world: PhantomData,
+        })
+    }
+
+    /// Changes the neighbourhood topology (global by default).
+    pub fn with_topology(mut self, topology: Topology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// Clamps each velocity component to `[-limit, limit]`.
+    ///
+    /// The limit must be positive and finite.
+    pub fn with_velocity_limit(mut self, limit: f64) -> Result<Self, String> {
+        if !limit.is_finite() || limit <= 0.0 {
+            return Err(format!(
+                "Velocity limit must be positive and finite, got {}",
+                limit
+            ));
+        }
+        self.velocity_limit = Some(limit);
+        Ok(self)
+    }
+
+    /// Runs the learning round.
+    ///
+    /// Returns two best positions visited by the swarm, as specimen.
+    pub fn run(&mut self, finish: Finish) -> Result<[crate::Specimen; 2], String> {
+        match finish {
+            Finish::Occurences(count) => {
+                for _ in 0..count {
+                    self.iteration();
+                }
+                let mut order: Vec<usize> = (0..self.particles.len()).collect();
+                order.sort_by(|a, b| {
+                    self.particles[*b]
+                        .best_fitness
+                        .partial_cmp(&self.particles[*a].best_fitness)
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+                Ok([self.specimen(order[0]), self.specimen(order[1])])
+            }
+            _ => Err("Simulation end trigger not supported yet".to_string()),
+        }
+    }
+
+    /// Returns number of iterations used in recent learning session.
+    pub fn get_number_of_iterations(&self) -> usize {
+        self.counter
+    }
+
+    fn specimen(&self, index: usize) -> crate::Specimen {
+        let mut brain = self.template.clone();
+        brain.set_weights(&self.particles[index].best_position);
+        crate::Specimen {
+            brain,
+            fitness: self.particles[index].best_fitness,
+        }
+    }
+
+    /// Returns the index of the particle with the best personal best
+    /// among the neighbourhood of the specified particle.
+    fn neighbourhood_best(&self, index: usize) -> usize {
+        let size = self.particles.len();
+        let better = |a: usize, b: usize| {
+            if self.particles[b].best_fitness > self.particles[a].best_fitness {
+                b
+            } else {
+                a
+            }
+        };
+        match self.topology {
+            Topology::Global => (0..size).fold(index, better),
+            Topology::Ring { radius } => (1..=radius.min(size / 2))
+                .flat_map(|offset| vec![(index + offset) % size, (index + size - offset) % size])
+                .fold(index, better),
+        }
+    }
+
+    fn iteration(&mut self) {
+        self.counter += 1;
+        let mut rng = rand::thread_rng();
+        let attractors: Vec<Vec<f64>> = (0..self.particles.len())
+            .map(|index| {
+                self.particles[self.neighbourhood_best(index)]
+                    .best_position
+                    .clone()
+            })
+            .collect();
+
+        for (particle, attractor) in self.particles.iter_mut().zip(attractors.iter()) {
+            #[allow(clippy::needless_range_loop)]
+            for k in 0..particle.position.len() {
+                let mut velocity = self.inertia * particle.velocity[k]
+                    + self.cognitive
+                        * rng.gen::<f64>()
+                        * (particle.best_position[k] - particle.position[k])
+                    + self.social * rng.gen::<f64>() * (attractor[k] - particle.position[k]);
+                if let Some(limit) = self.velocity_limit {
+                    velocity = velocity.clamp(-limit, limit);
+                }
+                particle.velocity[k] = velocity;
+                particle.position[k] += velocity;
+            }
+
+            self.template.set_weights(&particle.position);
+            let fitness = evaluate::<T>(&self.template, &mut self.randomizer).fitness;
+            if fitness > particle.best_fitness {
+                particle.best_fitness = fitness;
+                particle.best_position = particle.position.clone();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::particle_swarm::{ParticleSwarm, Topology};
+    use crate::randomizer::DefaultRandomizer;
+    use crate::simulating_world::{assert_reaches_target, SimulatingWorld, TargetWorld};
+    use crate::simulation::Finish;
+    use crate::training_ground::Exercise;
+
+    #[test]
+    fn ring_neighbourhood() {
+        let mut randomizer = DefaultRandomizer::new();
+        let mut swarm =
+            ParticleSwarm::<TargetWorld>::new(6, &[2, 1], &mut randomizer, None, None, None)
+                .unwrap()
+                .with_topology(Topology::Ring { radius: 1 });
+        swarm
+            .particles
+            .iter_mut()
+            .enumerate()
+            .for_each(|(index, particle)| particle.best_fitness = index as f64);
+        assert_eq!(swarm.neighbourhood_best(0), 5);
+        assert_eq!(swarm.neighbourhood_best(2), 3);
+        assert_eq!(swarm.neighbourhood_best(4), 5);
+
+        swarm.topology = Topology::Global;
+        assert_eq!(swarm.neighbourhood_best(0), 5);
+    }
+
+    #[test]
+    fn velocity_limit_must_be_positive_and_finite() {
+        let mut randomizer = DefaultRandomizer::new();
+        for limit in [0.0, -1.0, f64::NAN, f64::INFINITY].iter() {
+            let swarm =
+                ParticleSwarm::<TargetWorld>::new(4, &[2, 1], &mut randomizer, None, None, None)
+                    .unwrap();
+            assert!(swarm.with_velocity_limit(*limit).is_err());
+        }
+    }
+
+    #[test]
+    fn swarm_finds_target_for_exercise() {
+        for topology in [Topology::Global, Topology::Ring { radius: 2 }].iter() {
+            let mut randomizer = DefaultRandomizer::new();
+            let mut swarm = ParticleSwarm::<TargetWorld>::new(
+                12,
+                &[2, 2, 1],
+                &mut randomizer,
+                None,
+                None,
+                None,
+            )
+            .unwrap()
+            .with_topology(*topology)
+            .with_velocity_limit(2.0)
+            .unwrap();
+            let best = swarm.run(Finish::Occurences(60)).unwrap();
+            assert_eq!(swarm.get_number_of_iterations(), 60);
+            assert_reaches_target(&best, 1e-3);
+
+            let output = Exercise::new(&best[0]).get_output(&TargetWorld::new().get_world_state());
+            assert!(relative_eq!(-(output[0] - 0.8).powi(2), best[0].fitness));
+        }
+    }
+}
@@ -0,0 +1,400 @@
+use serde::{Deserialize, Serialize};
+
+use crate::network::{LayerKind, NetworkLayout};
+use crate::randomizer::RandomProvider;
+use crate::simulating_world::SimulatingWorld;
+use crate::simulation::{evaluate, Finish, Simulation};
+
+/// Decides which connections and neurons matter the least.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Criterion {
+    /// Connections are rated by the absolute value of their weight,
+    /// neurons by the sum of absolute weights of their outgoing connections.
+    Magnitude,
+    /// Connections are rated by the fitness lost in the reference world when
+    /// their weight is set to zero, neurons by the fitness lost when they are
+    /// removed. Removals that improve the fitness go first. Plays an episode
+    /// for every connection, and for every candidate neuron at each removal.
+    Sensitivity,
+}
+
+/// Evolution run after the pruning, letting the remaining weights compensate
+/// for the removed ones. Pruned connections stay pruned.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FineTuning {
+    pub generations: usize,
+    pub population_size: usize,
+    pub mutation_probability: Option<f64>,
+}
+
+/// Removes the least important connections and hidden neurons of a trained network.
+///
+/// Neurons are removed from the dense hidden layers read by dense layers only,
+/// see [`NetworkLayout::remove_neuron`](../network/struct.NetworkLayout.html#method.remove_neuron).
+/// Connections are removed from the dense layers. Pruned neurons keep only
+/// the remaining weights, both when serialized and in the forward pass.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pruning {
+    pub criterion: Criterion,
+    /// Fraction of the connections to remove, from `0.0` to `1.0`.
+    pub connections: f64,
+    /// Fraction of the hidden neurons to remove, from `0.0` to `1.0`.
+    pub neurons: f64,
+    pub fine_tuning: Option<FineTuning>,
+}
+
+/// Effect of the pruning on the size and the quality of the network.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PruningReport {
+    pub weights_before: usize,
+    pub weights_after: usize,
+    /// Number of neurons, excluding the bias neurons.
+    pub neurons_before: usize,
+    pub neurons_after: usize,
+    /// Length of the layout serialized into JSON, in bytes.
+    pub serialized_size_before: usize,
+    pub serialized_size_after: usize,
+    /// Fitness reached in the reference world.
+    pub fitness_before: f64,
+    pub fitness_after: f64,
+}
+
+impl Pruning {
+    /// Prunes the brain of the specimen. Fitness is measured in a fresh
+    /// reference world `T` before and after the pruning (and the fine-tuning).
+    ///
+    /// `randomizer` is used by the fine-tuning and by the output heads
+    /// sampling the actions.
+    pub fn prune<T: SimulatingWorld>(
+        &self,
+        specimen: &crate::Specimen,
+        randomizer: &mut dyn RandomProvider,
+    ) -> Result<(crate::Specimen, PruningReport), String> {
+        if !(0.0..=1.0).contains(&self.connections) || !(0.0..=1.0).contains(&self.neurons) {
+            return Err("Pruned fractions must be in range [0.0, 1.0]".to_string());
+        }
+        let mut layout = specimen.brain.clone();
+        let fitness_before = evaluate::<T>(&layout, randomizer).fitness;
+
+        self.prune_neurons::<T>(&mut layout, randomizer);
+        self.prune_connections::<T>(&mut layout, randomizer);
+
+        let mut fitness_after = evaluate::<T>(&layout, randomizer).fitness;
+        if let Some(fine_tuning) = self.fine_tuning {
+            let pruned = crate::Specimen {
+                brain: layout.clone(),
+                fitness: fitness_after,
+            };
+            let mut simulation = Simulation::<T>::from_template(
+                fine_tuning.population_size,
+                &layout,
+                randomizer,
+                fine_tuning.mutation_probability,
+            )?;
+            let [best, _] = simulation.run_with_parents(
+                Finish::Occurences(fine_tuning.generations),
+                [pruned.clone(), pruned],
+            )?;
+            layout = best.brain;
+            fitness_after = evaluate::<T>(&layout, randomizer).fitness;
+        }
+
+        let report = PruningReport {
+            weights_before: specimen.brain.get_number_of_weights(),
+            weights_after: layout.get_number_of_weights(),
+            neurons_before: count_neurons(&specimen.brain),
+            neurons_after: count_neurons(&layout),
+            serialized_size_before: serialized_size(&specimen.brain)?,
+            serialized_size_after: serialized_size(&layout)?,
+            fitness_before,
+            fitness_after,
+        };
+        Ok((
+            crate::Specimen {
+                brain: layout,
+                fitness: fitness_after,
+            },
+            report,
+        ))
+    }
+
+    /// Removes the hidden neurons one by one, rating them again after each removal.
+    fn prune_neurons<T: SimulatingWorld>(
+        &self,
+        layout: &mut NetworkLayout,
+        randomizer: &mut dyn RandomProvider,
+    ) {
+        let hidden = 1..layout.layers.len().saturating_sub(1);
+        let mut candidates: Vec<usize> = hidden
+            .clone()
+            .flat_map(|layer_index| layout.layers[layer_index].iter().copied())
+            .filter(|neuron_id| !layout.neurons[*neuron_id].bias)
+            .collect();
+        let locate = |layout: &NetworkLayout, neuron_id: usize| {
+            let layer_index = hidden
+                .clone()
+                .find(|layer_index| layout.layers[*layer_index].contains(&neuron_id))
+                .expect("Neuron outside of the hidden layers");
+            let position = layout.layers[layer_index]
+                .iter()
+                .position(|id| *id == neuron_id)
+                .unwrap();
+            (layer_index, position)
+        };
+        let count = (self.neurons * candidates.len() as f64).floor() as usize;
+        for _ in 0..count {
+            let ratings = match self.criterion {
+                Criterion::Magnitude => get_outgoing_weights(layout),
+                Criterion::Sensitivity => {
+                    let reference = evaluate::<T>(layout, randomizer).fitness;
+                    let mut ratings = vec![f64::INFINITY; layout.neurons.len()];
+                    for neuron_id in &candidates {
+                        let (layer_index, position) = locate(layout, *neuron_id);
+                        let mut pruned = layout.clone();
+                        if pruned.remove_neuron(layer_index, position).is_ok() {
+                            ratings[*neuron_id] =
+                                reference - evaluate::<T>(&pruned, randomizer).fitness;
+                        }
+                    }
+                    ratings
+                }
+            };
+            candidates.sort_by(|a, b| {
+                ratings[*b]
+                    .partial_cmp(&ratings[*a])
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+            // Least important neuron that can be removed
+            while let Some(neuron_id) = candidates.pop() {
+                let (layer_index, position) = locate(layout, neuron_id);
+                let mut pruned = layout.clone();
+                if pruned.remove_neuron(layer_index, position).is_ok() {
+                    *layout = pruned;
+                    candidates
+                        .iter_mut()
+                        .filter(|id| **id > neuron_id)
+                        .for_each(|id| *id -= 1);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn prune_connections<T: SimulatingWorld>(
+        &self,
+        layout: &mut NetworkLayout,
+        randomizer: &mut dyn RandomProvider,
+    ) {
+        let reference = evaluate::<T>(layout, randomizer).fitness;
+        // (rating, neuron, weight index) of every connection of the dense layers
+        let mut connections: Vec<(f64, usize, usize)> = vec![];
+        for layer_index in 1..layout.layers.len() {
+            if layout.get_layer_kind(layer_index) != LayerKind::Dense {
+                continue;
+            }
+            for neuron_id in &layout.layers[layer_index] {
+                for (j, weight) in layout.neurons[*neuron_id].inputs.iter().enumerate() {
+                    let rating = match self.criterion {
+                        Criterion::Magnitude => weight.abs(),
+                        Criterion::Sensitivity => {
+                            let mut zeroed = layout.clone();
+                            zeroed.neurons[*neuron_id].inputs[j] = 0.0;
+                            reference - evaluate::<T>(&zeroed, randomizer).fitness
+                        }
+                    };
+                    connections.push((rating, *neuron_id, j));
+                }
+            }
+        }
+        let count = (self.connections * connections.len() as f64).floor() as usize;
+        connections.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        let mut pruned: Vec<(usize, usize)> = connections
+            .iter()
+            .take(count)
+            .map(|(_, neuron_id, j)| (*neuron_id, *j))
+            .collect();
+        // Later weights first, so the indices of the remaining ones stay valid
+        pruned.sort_by(|a, b| b.cmp(a));
+        for (neuron_id, j) in pruned {
+            layout.neurons[neuron_id].disconnect(j);
+        }
+    }
+}
+
+/// Sums the absolute weights of the connections starting at each neuron.
+fn get_outgoing_weights(layout: &NetworkLayout) -> Vec<f64> {
+    let mut outgoing = vec![0.0; layout.neurons.len()];
+    for layer_index in 1..layout.layers.len() {
+        let inputs = layout.get_layer_inputs(layer_index);
+        for neuron_id in &layout.layers[layer_index] {
+            let neuron = &layout.neurons[*neuron_id];
+            for (j, weight) in neuron.inputs.iter().enumerate() {
+                outgoing[inputs[neuron.get_input_position(j)]] += weight.abs();
+            }
+        }
+    }
+    outgoing
+}
+
+fn count_neurons(layout: &NetworkLayout) -> usize {
+    layout.neurons.iter().filter(|neuron| !neuron.bias).count()
+}
+
+fn serialized_size(layout: &NetworkLayout) -> Result<usize, String> {
+    serde_json::to_string(layout)
+        .map(|json| json.len())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{NetworkBuilder, NetworkLayout};
+    use crate::pruning::{Criterion, FineTuning, Pruning};
+    use crate::randomizer::DefaultRandomizer;
+    use crate::simulating_world::SimulatingWorld;
+    use crate::simulation::{evaluate, SimulationStatus};
+    use crate::specimen::SpecimenStatus;
+    use crate::training_ground::Exercise;
+
+    struct SensorWorld;
+    impl SimulatingWorld for SensorWorld {
+        fn new() -> SensorWorld {
+            SensorWorld {}
+        }
+        fn tick(&mut self, input: &[f64]) -> SimulationStatus {
+            SimulationStatus {
+                specimen_status: SpecimenStatus::DEAD(-(input[0] - 0.3).powi(2)),
+                current_tick: 0,
+            }
+        }
+        fn get_world_state(&self) -> Vec<f64> {
+            // Second sensor is never active
+            vec![1.0, 0.0, -0.5]
+        }
+    }
+
+    fn build(randomizer: &mut DefaultRandomizer) -> crate::Specimen {
+        crate::Specimen {
+            brain: NetworkBuilder::new()
+                .with_neurons_per_layer(&[3, 6, 1])
+                .with_randomizer(randomizer)
+                .build()
+                .layout,
+            fitness: 0.0,
+        }
+    }
+
+    #[test]
+    fn magnitude_pruning_is_sparse() {
+        let mut randomizer = DefaultRandomizer::new();
+        let specimen = build(&mut randomizer);
+        let pruning = Pruning {
+            criterion: Criterion::Magnitude,
+            connections: 0.5,
+            neurons: 0.0,
+            fine_tuning: None,
+        };
+        let (pruned, report) = pruning
+            .prune::<SensorWorld>(&specimen, &mut randomizer)
+            .unwrap();
+        assert_eq!(report.weights_before, 6 * 4 + 7);
+        assert_eq!(report.weights_after, report.weights_before - 15);
+        assert_eq!(report.neurons_after, report.neurons_before);
+        assert!(report.serialized_size_after < report.serialized_size_before);
+
+        // Pruned network behaves like the dense one with the smallest weights zeroed
+        let mut threshold: Vec<f64> = specimen
+            .brain
+            .get_weights()
+            .iter()
+            .map(|w| w.abs())
+            .collect();
+        threshold.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let mut zeroed: NetworkLayout = specimen.brain.clone();
+        let weights: Vec<f64> = zeroed
+            .get_weights()
+            .iter()
+            .map(|w| if w.abs() <= threshold[14] { 0.0 } else { *w })
+            .collect();
+        zeroed.set_weights(&weights);
+        let input = [0.2, -0.4, 0.9];
+        let expected = Exercise::new(&crate::Specimen {
+            brain: zeroed,
+            fitness: 0.0,
+        })
+        .get_output(&input);
+        assert!(relative_eq!(
+            Exercise::new(&pruned).get_output(&input)[0],
+            expected[0],
+            epsilon = 1e-12
+        ));
+
+        let json = serde_json::to_string(&pruned).unwrap();
+        let loaded = crate::Specimen::try_from_json(&serde_json::to_string(&pruned.brain).unwrap());
+        assert!(json.contains("connections"));
+        assert_eq!(
+            loaded.unwrap().brain.get_number_of_weights(),
+            report.weights_after
+        );
+    }
+
+    #[test]
+    fn sensitivity_pruning_removes_the_least_salient_connections() {
+        let mut randomizer = DefaultRandomizer::new();
+        let specimen = build(&mut randomizer);
+        let pruning = Pruning {
+            criterion: Criterion::Sensitivity,
+            connections: 0.2,
+            neurons: 0.0,
+            fine_tuning: None,
+        };
+        let (pruned, report) = pruning
+            .prune::<SensorWorld>(&specimen, &mut randomizer)
+            .unwrap();
+        assert_eq!(report.weights_after, report.weights_before - 6);
+
+        // Fitness lost when a single weight of the original network is zeroed
+        let saliency = |neuron_id: usize, j: usize| {
+            let mut zeroed = specimen.brain.clone();
+            zeroed.neurons[neuron_id].inputs[j] = 0.0;
+            report.fitness_before
+                - evaluate::<SensorWorld>(&zeroed, &mut DefaultRandomizer::new()).fitness
+        };
+        let (mut removed, mut kept) = (vec![], vec![]);
+        for neuron_id in pruned.brain.layers[1..].iter().flatten() {
+            let neuron = &pruned.brain.neurons[*neuron_id];
+            for j in 0..specimen.brain.neurons[*neuron_id].inputs.len() {
+                let rating = saliency(*neuron_id, j);
+                match neuron.connections.as_ref() {
+                    Some(connections) if !connections.contains(&j) => removed.push(rating),
+                    _ => kept.push(rating),
+                }
+            }
+        }
+        assert_eq!(removed.len(), 6);
+        let most_salient_removed = removed.iter().cloned().fold(f64::MIN, f64::max);
+        assert!(kept.iter().all(|rating| *rating >= most_salient_removed));
+    }
+
+    #[test]
+    fn sensitivity_pruning_removes_neurons_and_fine_tunes() {
+        let mut randomizer = DefaultRandomizer::new();
+        let specimen = build(&mut randomizer);
+        let pruning = Pruning {
+            criterion: Criterion::Sensitivity,
+            connections: 0.2,
+            neurons: 0.5,
+            fine_tuning: Some(FineTuning {
+                generations: 3,
+                population_size: 4,
+                mutation_probability: None,
+            }),
+        };
+        let (pruned, report) = pruning
+            .prune::<SensorWorld>(&specimen, &mut randomizer)
+            .unwrap();
+        assert_eq!(report.neurons_after, report.neurons_before - 3);
+        assert_eq!(pruned.brain.layers[1].len(), 4);
+        assert!(relative_eq!(pruned.fitness, report.fitness_after));
+    }
+}
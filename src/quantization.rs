@@ -0,0 +1,585 @@
+use serde::{Deserialize, Serialize};
+
+use crate::action::{Action, OutputHead};
+use crate::network::{Activation, DenseLayout, Network};
+use crate::randomizer::RandomProvider;
+use crate::simulating_world::SimulatingWorld;
+use crate::specimen::SpecimenStatus;
+use crate::training_ground::Exercise;
+
+/// Number of fractional bits of the values passed between the layers (Q16.16).
+pub const FRACTIONAL_BITS: u32 = 16;
+/// Lookup tables cover the inputs from `-LOOKUP_RANGE` to `LOOKUP_RANGE`,
+/// the activation saturates outside.
+const LOOKUP_RANGE: i64 = 8;
+/// Distance between the entries of the lookup tables is `2^-LOOKUP_STEP_BITS`.
+const LOOKUP_STEP_BITS: u32 = 4;
+
+/// Representation of the quantised weights.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Precision {
+    /// Signed 8-bit weights. The largest weight of each layer maps to `127`.
+    Int8,
+    /// Signed fixed-point weights (Q-format) of the specified width, at most
+    /// 16 bits. The number of fractional bits is chosen for each layer,
+    /// so that its largest weight still fits.
+    Fixed { bits: u8 },
+}
+
+impl Precision {
+    fn get_max_weight(self) -> i64 {
+        match self {
+            Precision::Int8 => i8::MAX as i64,
+            Precision::Fixed { bits } => (1 << (bits - 1)) - 1,
+        }
+    }
+
+    fn get_bytes_per_weight(self) -> usize {
+        match self {
+            Precision::Int8 => 1,
+            Precision::Fixed { bits } => (bits as usize + 7) / 8,
+        }
+    }
+}
+
+/// Weights of a single layer. Real weight is `weight * multiplier * 2^-shift`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct QuantizedLayer {
+    sources: Vec<usize>,
+    /// Positions of the inputs each neuron is connected to.
+    connections: Vec<Vec<usize>>,
+    weights: QuantizedWeights,
+    multiplier: i64,
+    shift: u32,
+}
+
+/// Weights of the neurons of a layer, in the narrowest type holding the precision.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+enum QuantizedWeights {
+    Int8(Vec<Vec<i8>>),
+    Fixed(Vec<Vec<i16>>),
+}
+
+impl QuantizedWeights {
+    fn from_rows(precision: Precision, rows: Vec<Vec<i64>>) -> QuantizedWeights {
+        match precision {
+            Precision::Int8 => QuantizedWeights::Int8(
+                rows.iter()
+                    .map(|row| row.iter().map(|weight| *weight as i8).collect())
+                    .collect(),
+            ),
+            Precision::Fixed { .. } => QuantizedWeights::Fixed(
+                rows.iter()
+                    .map(|row| row.iter().map(|weight| *weight as i16).collect())
+                    .collect(),
+            ),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            QuantizedWeights::Int8(rows) => rows.iter().map(|row| row.len()).sum(),
+            QuantizedWeights::Fixed(rows) => rows.iter().map(|row| row.len()).sum(),
+        }
+    }
+
+    /// Weighted sum of the inputs the neuron is connected to.
+    fn get_total(&self, neuron: usize, connections: &[usize], inputs: &[i32]) -> i64 {
+        match self {
+            QuantizedWeights::Int8(rows) => weighted_sum(&rows[neuron], connections, inputs),
+            QuantizedWeights::Fixed(rows) => weighted_sum(&rows[neuron], connections, inputs),
+        }
+    }
+}
+
+fn weighted_sum<W: Copy + Into<i64>>(weights: &[W], connections: &[usize], inputs: &[i32]) -> i64 {
+    connections
+        .iter()
+        .zip(weights.iter())
+        .fold(0i64, |total, (position, weight)| {
+            total.saturating_add((*weight).into() * inputs[*position] as i64)
+        })
+}
+
+/// Network with integer weights and integer-only forward pass, ready to be
+/// deployed onto devices without floating point unit.
+///
+/// Values passed between the layers are Q16.16 fixed-point numbers.
+/// Sigmoid and tanh are evaluated with linearly interpolated lookup tables.
+/// Only networks consisting of dense layers can be quantised.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QuantizedNetwork {
+    precision: Precision,
+    activation: Activation,
+    head: OutputHead,
+    /// Layers in the order they need to be fired, starting after the input layer.
+    order: Vec<usize>,
+    /// Layers indexed like in the original network. Input layer has no weights.
+    layers: Vec<QuantizedLayer>,
+    /// Sizes of the layers, excluding the bias neurons.
+    sizes: Vec<usize>,
+    lookup_table: Vec<i32>,
+}
+
+impl QuantizedNetwork {
+    /// Quantises the weights of the trained specimen.
+    pub fn from_specimen(
+        specimen: &crate::Specimen,
+        precision: Precision,
+    ) -> Result<QuantizedNetwork, String> {
+        let layout = &specimen.brain;
+        if let Precision::Fixed { bits } = precision {
+            if !(2..=16).contains(&bits) {
+                return Err("Fixed-point weights must have from 2 to 16 bits".to_string());
+            }
+        }
+        let DenseLayout { sizes, order } = layout.get_dense_layout("quantised")?;
+
+        let layers = (0..layout.layers.len())
+            .map(|layer_index| {
+                let neurons: Vec<_> = layout.layers[layer_index]
+                    .iter()
+                    .map(|neuron_id| &layout.neurons[*neuron_id])
+                    .filter(|neuron| !neuron.bias && layer_index > 0)
+                    .collect();
+                let largest = neurons
+                    .iter()
+                    .flat_map(|neuron| neuron.inputs.iter())
+                    .fold(0.0f64, |largest, weight| largest.max(weight.abs()));
+                let (multiplier, shift) = get_scale(precision, largest)?;
+                let scale = multiplier as f64 / (1u64 << shift) as f64;
+                let max_weight = precision.get_max_weight();
+                Ok(QuantizedLayer {
+                    sources: layout.get_layer_sources(layer_index),
+                    connections: neurons
+                        .iter()
+                        .map(|neuron| {
+                            (0..neuron.inputs.len())
+                                .map(|j| neuron.get_input_position(j))
+                                .collect()
+                        })
+                        .collect(),
+                    weights: QuantizedWeights::from_rows(
+                        precision,
+                        neurons
+                            .iter()
+                            .map(|neuron| {
+                                neuron
+                                    .inputs
+                                    .iter()
+                                    .map(|weight| {
+                                        ((weight / scale).round() as i64)
+                                            .clamp(-max_weight, max_weight)
+                                    })
+                                    .collect()
+                            })
+                            .collect(),
+                    ),
+                    multiplier,
+                    shift,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(QuantizedNetwork {
+            precision,
+            activation: layout.activation,
+            head: layout.head.clone(),
+            order: order.into_iter().skip(1).collect(),
+            layers,
+            sizes,
+            lookup_table: get_lookup_table(layout.activation),
+        })
+    }
+
+    pub fn get_precision(&self) -> Precision {
+        self.precision
+    }
+
+    /// Returns the real value of a single weight unit of each layer (except the input one).
+    pub fn get_layer_scales(&self) -> Vec<f64> {
+        self.layers
+            .iter()
+            .skip(1)
+            .map(|layer| layer.multiplier as f64 / (1u64 << layer.shift) as f64)
+            .collect()
+    }
+
+    /// Calculates the output for the inputs given as Q16.16 numbers,
+    /// using integer arithmetic only.
+    pub fn fire_fixed(&self, inputs: &[i32]) -> Vec<i32> {
+        assert_eq!(inputs.len(), self.sizes[0], "Incorrect number of inputs");
+        let last = self.layers.len() - 1;
+        let with_bias = |mut values: Vec<i32>, layer_index: usize| {
+            if layer_index != last {
+                values.push(1 << FRACTIONAL_BITS);
+            }
+            values
+        };
+        let mut values: Vec<Vec<i32>> = vec![vec![]; self.layers.len()];
+        values[0] = with_bias(inputs.to_vec(), 0);
+        for layer_index in &self.order {
+            let layer = &self.layers[*layer_index];
+            let inputs: Vec<i32> = layer
+                .sources
+                .iter()
+                .flat_map(|source| values[*source].iter().copied())
+                .collect();
+            let outputs = layer
+                .connections
+                .iter()
+                .enumerate()
+                .map(|(neuron, connections)| {
+                    let total = layer.weights.get_total(neuron, connections, &inputs);
+                    let value = total.saturating_mul(layer.multiplier) >> layer.shift;
+                    self.activate(value.clamp(i32::MIN as i64, i32::MAX as i64) as i32)
+                })
+                .collect();
+            values[*layer_index] = with_bias(outputs, *layer_index);
+        }
+        values.swap_remove(last)
+    }
+
+    /// Converts the inputs to Q16.16 numbers, fires the network
+    /// and converts the outputs back.
+    pub fn get_output(&self, inputs: &[f64]) -> Vec<f64> {
+        let scale = (1u64 << FRACTIONAL_BITS) as f64;
+        let inputs: Vec<i32> = inputs
+            .iter()
+            .map(|input| {
+                (input * scale)
+                    .round()
+                    .clamp(i32::MIN as f64, i32::MAX as f64) as i32
+            })
+            .collect();
+        self.fire_fixed(&inputs)
+            .iter()
+            .map(|output| *output as f64 / scale)
+            .collect()
+    }
+
+    /// Applies the output head of the original network to the outputs.
+    pub fn get_action(&self, inputs: &[f64], randomizer: &mut dyn RandomProvider) -> Action {
+        self.head.apply(&self.get_output(inputs), randomizer)
+    }
+
+    /// Number of bytes taken by the weights, layer scales and lookup table.
+    pub fn get_size(&self) -> usize {
+        self.layers
+            .iter()
+            .map(|layer| {
+                layer.weights.len() * self.precision.get_bytes_per_weight()
+                    + 2 * std::mem::size_of::<i32>()
+            })
+            .sum::<usize>()
+            + self.lookup_table.len() * std::mem::size_of::<i32>()
+    }
+
+    fn activate(&self, value: i32) -> i32 {
+        match self.activation {
+            Activation::Relu => value.max(0),
+            Activation::Identity => value,
+            Activation::Sigmoid | Activation::Tanh => {
+                let step_bits = FRACTIONAL_BITS - LOOKUP_STEP_BITS;
+                let range = LOOKUP_RANGE << FRACTIONAL_BITS;
+                let offset = (value as i64).clamp(-range, range - 1) + range;
+                let index = (offset >> step_bits) as usize;
+                let fraction = offset & ((1 << step_bits) - 1);
+                let (low, high) = (
+                    self.lookup_table[index] as i64,
+                    self.lookup_table[index + 1] as i64,
+                );
+                (low + (((high - low) * fraction) >> step_bits)) as i32
+            }
+        }
+    }
+}
+
+/// Finds the multiplier and shift representing the real value of a single weight unit.
+fn get_scale(precision: Precision, largest: f64) -> Result<(i64, u32), String> {
+    if largest == 0.0 {
+        return Ok((1, 0));
+    }
+    let max_weight = precision.get_max_weight() as f64;
+    match precision {
+        Precision::Int8 => {
+            // Multiplier keeps 15 significant bits
+            let scale = largest / max_weight;
+            let shift = 14 - scale.log2().floor() as i64;
+            if !(0..=62).contains(&shift) {
+                return Err(format!(
+                    "Weights of magnitude {} cannot be quantised",
+                    largest
+                ));
+            }
+            Ok((
+                (scale * (1u64 << shift) as f64).round() as i64,
+                shift as u32,
+            ))
+        }
+        Precision::Fixed { .. } => {
+            let shift = (max_weight / largest).log2().floor() as i64;
+            if !(0..=62).contains(&shift) {
+                return Err(format!(
+                    "Weights of magnitude {} cannot be quantised",
+                    largest
+                ));
+            }
+            Ok((1, shift as u32))
+        }
+    }
+}
+
+/// Samples the activation at the steps of the lookup table, as Q16.16 numbers.
+fn get_lookup_table(activation: Activation) -> Vec<i32> {
+    if !matches!(activation, Activation::Sigmoid | Activation::Tanh) {
+        return vec![];
+    }
+    let entries = (2 * LOOKUP_RANGE as usize) << LOOKUP_STEP_BITS;
+    let function = activation.function();
+    (0..=entries)
+        .map(|i| {
+            let x = i as f64 / (1 << LOOKUP_STEP_BITS) as f64 - LOOKUP_RANGE as f64;
+            (function(x) * (1u64 << FRACTIONAL_BITS) as f64).round() as i32
+        })
+        .collect()
+}
+
+/// Differences between the outputs of the original and the quantised network.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QuantizationReport {
+    /// Number of compared inputs.
+    pub samples: usize,
+    pub max_absolute_error: f64,
+    pub mean_absolute_error: f64,
+    /// Bytes taken by the 64-bit float weights.
+    pub float_size: usize,
+    /// Bytes taken by the quantised network, see [`QuantizedNetwork::get_size`](struct.QuantizedNetwork.html#method.get_size).
+    pub quantized_size: usize,
+    /// Fitness reached by the original network, when compared on a rollout.
+    pub float_fitness: Option<f64>,
+    /// Fitness reached by the quantised network, when compared on a rollout.
+    pub quantized_fitness: Option<f64>,
+}
+
+impl QuantizationReport {
+    /// Compares the outputs of both networks on the sample inputs.
+    pub fn from_samples(
+        specimen: &crate::Specimen,
+        quantized: &QuantizedNetwork,
+        inputs: &[Vec<f64>],
+    ) -> Result<QuantizationReport, String> {
+        if inputs.is_empty() {
+            return Err("No sample inputs provided".to_string());
+        }
+        let exercise = Exercise::new(specimen);
+        let errors: Vec<f64> = inputs
+            .iter()
+            .flat_map(|input| {
+                exercise
+                    .get_output(input)
+                    .into_iter()
+                    .zip(quantized.get_output(input))
+                    .map(|(expected, actual)| (expected - actual).abs())
+            })
+            .collect();
+        Ok(QuantizationReport {
+            samples: inputs.len(),
+            max_absolute_error: errors.iter().cloned().fold(0.0, f64::max),
+            mean_absolute_error: errors.iter().sum::<f64>() / errors.len().max(1) as f64,
+            float_size: specimen.brain.get_number_of_weights() * std::mem::size_of::<f64>(),
+            quantized_size: quantized.get_size(),
+            float_fitness: None,
+            quantized_fitness: None,
+        })
+    }
+
+    /// Plays an episode in the world `T` with the original network and compares
+    /// the outputs on the states it went through. Then plays another episode
+    /// with the quantised network, so that the fitness of both can be compared.
+    ///
+    /// `randomizer` is used by the output heads sampling the actions.
+    pub fn from_rollout<T: SimulatingWorld>(
+        specimen: &crate::Specimen,
+        quantized: &QuantizedNetwork,
+        randomizer: &mut dyn RandomProvider,
+    ) -> Result<QuantizationReport, String> {
+        let mut network = Network::from_layout(specimen.brain.clone());
+        network.reset_state();
+        let mut states = vec![];
+        let float_fitness = play(&mut T::new(), |state| {
+            states.push(state.to_vec());
+            network.fire(state);
+            specimen.brain.get_action(&network.get_output(), randomizer)
+        });
+        let quantized_fitness = play(&mut T::new(), |state| {
+            quantized.get_action(state, randomizer)
+        });
+
+        let mut report = QuantizationReport::from_samples(specimen, quantized, &states)?;
+        report.float_fitness = Some(float_fitness);
+        report.quantized_fitness = Some(quantized_fitness);
+        Ok(report)
+    }
+}
+
+/// Lets the controller act in the world until it dies, returning the fitness.
+fn play<T: SimulatingWorld>(world: &mut T, mut controller: impl FnMut(&[f64]) -> Action) -> f64 {
+    loop {
+        let action = controller(&world.get_world_state());
+        if let SpecimenStatus::DEAD(fitness) = world.tick_action(action).specimen_status {
+            return fitness;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::initializer::Initializer;
+    use crate::network::{Activation, Connectivity, NetworkBuilder};
+    use crate::quantization::{Precision, QuantizationReport, QuantizedNetwork};
+    use crate::randomizer::DefaultRandomizer;
+    use crate::simulating_world::SimulatingWorld;
+    use crate::simulation::SimulationStatus;
+    use crate::specimen::SpecimenStatus;
+
+    fn build(activation: Activation) -> crate::Specimen {
+        let mut randomizer = DefaultRandomizer::new();
+        crate::Specimen {
+            brain: NetworkBuilder::new()
+                .with_neurons_per_layer(&[3, 32, 16, 2])
+                .with_randomizer(&mut randomizer)
+                .with_activation(activation)
+                .with_initializer(Initializer::XavierNormal)
+                .with_connectivity(Connectivity::Residual)
+                .build()
+                .layout,
+            fitness: 0.0,
+        }
+    }
+
+    #[test]
+    fn quantized_outputs_follow_float_outputs() {
+        let inputs: Vec<Vec<f64>> = (0..20)
+            .map(|i| vec![i as f64 / 10.0 - 1.0, 0.5, -(i as f64) / 20.0])
+            .collect();
+        for (activation, precision, tolerance) in [
+            (Activation::Sigmoid, Precision::Int8, 0.05),
+            (Activation::Tanh, Precision::Int8, 0.1),
+            (Activation::Tanh, Precision::Fixed { bits: 16 }, 0.005),
+            (Activation::Relu, Precision::Fixed { bits: 12 }, 0.05),
+        ] {
+            let specimen = build(activation);
+            let quantized = QuantizedNetwork::from_specimen(&specimen, precision).unwrap();
+            let report = QuantizationReport::from_samples(&specimen, &quantized, &inputs).unwrap();
+            assert_eq!(report.samples, 20);
+            assert!(
+                report.max_absolute_error < tolerance,
+                "{:?} {:?}: {}",
+                activation,
+                precision,
+                report.max_absolute_error
+            );
+            assert!(report.quantized_size < report.float_size);
+        }
+    }
+
+    /// Single neuron reading two inputs and the bias with weights `[0.3, -0.25, 0.75]`.
+    fn build_neuron(activation: Activation) -> crate::Specimen {
+        let mut randomizer = DefaultRandomizer::new();
+        let mut layout = NetworkBuilder::new()
+            .with_neurons_per_layer(&[2, 1])
+            .with_randomizer(&mut randomizer)
+            .with_activation(activation)
+            .build()
+            .layout;
+        layout.set_weights(&[0.3, -0.25, 0.75]);
+        crate::Specimen {
+            brain: layout,
+            fitness: 0.0,
+        }
+    }
+
+    #[test]
+    fn integer_forward_pass() {
+        // Inputs 1.0 and -0.5 in Q16.16, the bias is 1.0
+        let inputs = [1 << 16, -(1 << 15)];
+
+        // 8-bit Q0.7 weights: 38, -32, 96, scaled by 2^-7
+        // (38 * 65536 + 32 * 32768 + 96 * 65536) >> 7 = 76800, i.e. 1.171875
+        let identity = build_neuron(Activation::Identity);
+        let quantized =
+            QuantizedNetwork::from_specimen(&identity, Precision::Fixed { bits: 8 }).unwrap();
+        assert_eq!(quantized.get_layer_scales(), vec![1.0 / 128.0]);
+        assert_eq!(quantized.fire_fixed(&inputs), vec![76800]);
+
+        // Lookup table entries sigmoid(1.125) = 49474 and sigmoid(1.1875) = 50220,
+        // interpolated at 3072 / 4096 of the step
+        let sigmoid = build_neuron(Activation::Sigmoid);
+        let quantized =
+            QuantizedNetwork::from_specimen(&sigmoid, Precision::Fixed { bits: 8 }).unwrap();
+        assert_eq!(quantized.fire_fixed(&inputs), vec![50033]);
+
+        // Int8 weights 51, -42, 127, the weight unit is 24770 * 2^-22
+        // (51 * 65536 + 42 * 32768 + 127 * 65536) * 24770 >> 22 = 77019
+        let quantized = QuantizedNetwork::from_specimen(&identity, Precision::Int8).unwrap();
+        assert_eq!(quantized.fire_fixed(&inputs), vec![77019]);
+        // One byte per weight, 32-bit multiplier and shift for both layers
+        assert_eq!(quantized.get_size(), 3 + 2 * 2 * 4);
+
+        let specimen = build(Activation::Sigmoid);
+        let quantized =
+            QuantizedNetwork::from_specimen(&specimen, Precision::Fixed { bits: 8 }).unwrap();
+        assert!(quantized
+            .get_layer_scales()
+            .iter()
+            .all(|scale| scale.log2().fract() == 0.0));
+
+        let json = serde_json::to_string(&quantized).unwrap();
+        let loaded: QuantizedNetwork = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, quantized);
+        assert!(QuantizedNetwork::from_specimen(&specimen, Precision::Fixed { bits: 20 }).is_err());
+    }
+
+    #[test]
+    fn rollout_report() {
+        struct CountingWorld {
+            ticks: usize,
+        }
+        impl SimulatingWorld for CountingWorld {
+            fn new() -> CountingWorld {
+                CountingWorld { ticks: 0 }
+            }
+            fn tick(&mut self, input: &[f64]) -> SimulationStatus {
+                self.ticks += 1;
+                SimulationStatus {
+                    specimen_status: if self.ticks < 5 {
+                        SpecimenStatus::ALIVE(0.0)
+                    } else {
+                        SpecimenStatus::DEAD(input[0])
+                    },
+                    current_tick: self.ticks,
+                }
+            }
+            fn get_world_state(&self) -> Vec<f64> {
+                vec![self.ticks as f64 / 5.0, 1.0, -1.0]
+            }
+        }
+
+        let specimen = build(Activation::Sigmoid);
+        let quantized = QuantizedNetwork::from_specimen(&specimen, Precision::Int8).unwrap();
+        let mut randomizer = DefaultRandomizer::new();
+        let report = QuantizationReport::from_rollout::<CountingWorld>(
+            &specimen,
+            &quantized,
+            &mut randomizer,
+        )
+        .unwrap();
+        assert_eq!(report.samples, 5);
+        assert!(relative_eq!(
+            report.float_fitness.unwrap(),
+            report.quantized_fitness.unwrap(),
+            epsilon = 0.05
+        ));
+    }
+}
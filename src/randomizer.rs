@@ -7,6 +7,27 @@ use rand_distr::{Distribution, Normal};
 pub trait RandomProvider {
     /// Returns a number which will be treated as a next random number
     fn get_number(&mut self) -> f64;
+
+    /// Returns a number from range `[0.0, 1.0]`, uniformly distributed as long as
+    /// [`get_number`](#tymethod.get_number) draws from the standard normal distribution.
+    ///
+    /// It is the angle of a point with two such coordinates, scaled from
+    /// `[-pi, pi]`, so two numbers are drawn.
+    fn get_uniform(&mut self) -> f64 {
+        let angle = self.get_number().atan2(self.get_number());
+        0.5 + angle / (2.0 * std::f64::consts::PI)
+    }
+}
+
+/// Shuffles the items in place, ordering them by keys drawn from the randomizer,
+/// so that the order can be reproduced with a seeded one.
+pub(crate) fn shuffle<T>(items: &mut Vec<T>, randomizer: &mut dyn RandomProvider) {
+    let mut keyed: Vec<(f64, T)> = items
+        .drain(..)
+        .map(|item| (randomizer.get_number(), item))
+        .collect();
+    keyed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    items.extend(keyed.into_iter().map(|(_, item)| item));
 }
 
 /// Default randomizer
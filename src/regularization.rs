@@ -0,0 +1,262 @@
+use serde::{Deserialize, Serialize};
+
+use crate::network::{LayerKind, NetworkLayout};
+
+/// Penalties and constraints that keep the weights small, so the network
+/// is less prone to overfitting.
+///
+/// Penalties cover the connection weights of the network, including the biases,
+/// gates and kernels. Time constants, gains and biases of the CTRNN neurons
+/// and the scales and shifts of the normalisation layers are left out.
+/// Trainers subtract the penalty from the fitness or add it to the loss,
+/// see e.g. [`Simulation::with_regularization`](../simulation/struct.Simulation.html#method.with_regularization).
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Regularization {
+    /// Weight of the sum of absolute values of the weights.
+    pub l1: f64,
+    /// Weight of the sum of squared weights (weight decay).
+    pub l2: f64,
+    /// Upper bound of the euclidean norm of each group of weights: the input
+    /// weights of a neuron, each of its gates and the shared weights of a layer.
+    /// Groups exceeding it are scaled down after each update. Must be positive.
+    pub max_norm: Option<f64>,
+}
+
+/// Subgradient of the absolute value, zero at zero.
+fn sign(weight: f64) -> f64 {
+    if weight == 0.0 {
+        0.0
+    } else {
+        weight.signum()
+    }
+}
+
+/// Tells which of the weights, laid out in the order of
+/// [`get_weights`](../network/struct.NetworkLayout.html#method.get_weights),
+/// are regularized.
+fn get_regularized(layout: &NetworkLayout) -> Vec<bool> {
+    let mut kinds = vec![LayerKind::Dense; layout.neurons.len()];
+    for (layer_index, layer) in layout.layers.iter().enumerate() {
+        let kind = layout.get_layer_kind(layer_index);
+        layer.iter().for_each(|neuron_id| kinds[*neuron_id] = kind);
+    }
+    let neurons = layout.neurons.iter().zip(kinds).flat_map(|(neuron, kind)| {
+        let gates = neuron.gates.iter().enumerate().flat_map(move |(g, gate)| {
+            std::iter::repeat(kind.gate_holds_weights(g)).take(gate.len())
+        });
+        std::iter::repeat(true)
+            .take(neuron.inputs.len())
+            .chain(gates)
+    });
+    let parameters = layout
+        .parameters
+        .iter()
+        .enumerate()
+        .flat_map(|(layer_index, parameters)| {
+            let kind = layout.get_layer_kind(layer_index);
+            std::iter::repeat(!kind.is_normalization()).take(parameters.len())
+        });
+    neurons.chain(parameters).collect()
+}
+
+impl Regularization {
+    /// Checks that `max_norm` is a positive number.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        match self.max_norm {
+            Some(max_norm) if !(max_norm.is_finite() && max_norm > 0.0) => {
+                Err("Maximum norm must be a positive number".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Calculates the penalty for the weights of the network.
+    pub fn penalty(&self, layout: &NetworkLayout) -> f64 {
+        layout
+            .get_weights()
+            .iter()
+            .zip(get_regularized(layout))
+            .filter(|(_, regularized)| *regularized)
+            .map(|(weight, _)| self.l1 * weight.abs() + self.l2 * weight * weight)
+            .sum()
+    }
+
+    /// Adds the derivative of the [`penalty`](#method.penalty) to the gradient
+    /// of the loss, laid out in the order of the weights.
+    pub(crate) fn add_gradient(&self, layout: &NetworkLayout, gradient: &mut [f64]) {
+        gradient
+            .iter_mut()
+            .zip(layout.get_weights())
+            .zip(get_regularized(layout))
+            .filter(|(_, regularized)| *regularized)
+            .for_each(|((g, weight), _)| *g += self.l1 * sign(weight) + 2.0 * self.l2 * weight);
+    }
+
+    /// Scales down each group of weights whose norm exceeds `max_norm`.
+    pub fn constrain(&self, layout: &mut NetworkLayout) {
+        let max_norm = match self.max_norm {
+            Some(max_norm) => max_norm,
+            None => return,
+        };
+        let clip = |weights: &mut [f64]| {
+            let norm = weights.iter().map(|w| w * w).sum::<f64>().sqrt();
+            if norm > max_norm {
+                weights
+                    .iter_mut()
+                    .for_each(|weight| *weight *= max_norm / norm);
+            }
+        };
+        for layer_index in 0..layout.layers.len() {
+            let kind = layout.get_layer_kind(layer_index);
+            for neuron_id in &layout.layers[layer_index] {
+                let neuron = &mut layout.neurons[*neuron_id];
+                clip(&mut neuron.inputs);
+                neuron
+                    .gates
+                    .iter_mut()
+                    .enumerate()
+                    .filter(|(g, _)| kind.gate_holds_weights(*g))
+                    .for_each(|(_, gate)| clip(gate));
+            }
+            if !kind.is_normalization() {
+                if let Some(parameters) = layout.parameters.get_mut(layer_index) {
+                    clip(parameters);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{LayerKind, NetworkBuilder, NetworkLayout};
+    use crate::randomizer::RandomProvider;
+    use crate::regularization::Regularization;
+
+    struct Constant(f64);
+    impl RandomProvider for Constant {
+        fn get_number(&mut self) -> f64 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn penalties_and_max_norm() {
+        let mut randomizer = Constant(-2.0);
+        let mut layout = NetworkBuilder::new()
+            .with_neurons_per_layer(&[3, 1])
+            .with_randomizer(&mut randomizer)
+            .build()
+            .layout;
+        let regularization = Regularization {
+            l1: 0.5,
+            l2: 0.25,
+            max_norm: Some(1.0),
+        };
+        // Four weights (three inputs and the bias), each equal to -2
+        assert!(relative_eq!(
+            regularization.penalty(&layout),
+            4.0 * (1.0 + 1.0)
+        ));
+
+        let mut gradient = vec![1.0; 4];
+        regularization.add_gradient(&layout, &mut gradient);
+        assert_eq!(gradient, vec![1.0 - 0.5 - 1.0; 4]);
+
+        regularization.constrain(&mut layout);
+        layout
+            .get_weights()
+            .iter()
+            .for_each(|weight| assert!(relative_eq!(*weight, -0.5)));
+    }
+
+    #[test]
+    fn zero_weights_have_no_l1_gradient() {
+        let regularization = Regularization {
+            l1: 0.5,
+            ..Regularization::default()
+        };
+        let mut randomizer = Constant(0.0);
+        let mut layout = NetworkBuilder::new()
+            .with_neurons_per_layer(&[2, 1])
+            .with_randomizer(&mut randomizer)
+            .build()
+            .layout;
+        layout.set_weights(&[0.0, 1.0, -1.0]);
+        let mut gradient = vec![0.25; 3];
+        regularization.add_gradient(&layout, &mut gradient);
+        assert_eq!(gradient, vec![0.25, 0.75, -0.25]);
+    }
+
+    #[test]
+    fn max_norm_covers_gates_and_kernels() {
+        let mut randomizer = Constant(-2.0);
+        let mut layout = NetworkBuilder::new()
+            .with_neurons_per_layer(&[3, 2, 1])
+            .with_randomizer(&mut randomizer)
+            .with_layer_kind(1, LayerKind::Gru)
+            .build()
+            .layout;
+        layout.parameters = vec![vec![], vec![3.0, 4.0], vec![]];
+        Regularization {
+            max_norm: Some(1.0),
+            ..Regularization::default()
+        }
+        .constrain(&mut layout);
+
+        let norm = |weights: &[f64]| weights.iter().map(|w| w * w).sum::<f64>().sqrt();
+        for neuron in layout.neurons.iter().filter(|neuron| !neuron.bias) {
+            assert!(norm(&neuron.inputs) <= 1.0 + 1e-9);
+            neuron
+                .gates
+                .iter()
+                .for_each(|gate| assert!(norm(gate) <= 1.0 + 1e-9));
+        }
+        assert!(relative_eq!(layout.parameters[1][0], 0.6));
+        assert!(relative_eq!(layout.parameters[1][1], 0.8));
+    }
+
+    #[test]
+    fn genes_and_normalization_are_not_regularized() {
+        let mut randomizer = Constant(-2.0);
+        let mut layout = NetworkBuilder::new()
+            .with_neurons_per_layer(&[2, 2, 2, 1])
+            .with_randomizer(&mut randomizer)
+            .with_layer_kind(1, LayerKind::Ctrnn { step: 0.1 })
+            .with_layer_kind(2, LayerKind::LayerNorm)
+            .build()
+            .layout;
+        let genes = |layout: &NetworkLayout| -> Vec<Vec<f64>> {
+            layout.layers[1]
+                .iter()
+                .filter(|neuron_id| !layout.neurons[**neuron_id].bias)
+                .map(|neuron_id| layout.neurons[*neuron_id].gates[1].clone())
+                .collect()
+        };
+        let (original_genes, original_parameters) = (genes(&layout), layout.parameters.clone());
+        let regularization = Regularization {
+            l1: 1.0,
+            l2: 0.0,
+            max_norm: Some(1.0),
+        };
+        // Each CTRNN neuron has three inputs and two recurrent weights,
+        // the output neuron three inputs
+        assert!(relative_eq!(
+            regularization.penalty(&layout),
+            (2.0 * (3.0 + 2.0) + 3.0) * 2.0
+        ));
+
+        regularization.constrain(&mut layout);
+        assert_eq!(genes(&layout), original_genes);
+        assert_eq!(layout.parameters, original_parameters);
+
+        for max_norm in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(Regularization {
+                max_norm: Some(max_norm),
+                ..Regularization::default()
+            }
+            .validate()
+            .is_err());
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::action::Action;
 use crate::simulation::SimulationStatus;
 
 /// Represents a structure that handles the logic
@@ -21,10 +22,69 @@ pub trait SimulatingWorld {
     /// simulation necessary and return correct status.
     fn tick(&mut self, input: &[f64]) -> SimulationStatus;
 
+    /// Gives the typed action chosen by the network to the world
+    ///
+    /// `easyneural` calls this function instead of [`tick`](#tmethod.tick),
+    /// passing the outputs transformed by the
+    /// [`OutputHead`](../action/enum.OutputHead.html) of the network.
+    /// Override it to receive e.g. `Action::Discrete` directly. The default
+    /// implementation forwards the values of the action to `tick`, so
+    /// a discrete action arrives as a single element with its index.
+    fn tick_action(&mut self, action: Action) -> SimulationStatus {
+        self.tick(&action.to_values())
+    }
+
     /// Gives feedback from the world to the network
     ///
     /// `easyneural` will call this function regularly in order
     /// to retrieve current world state. These will be used as an input
     /// to the neural network being trained.
     fn get_world_state(&self) -> Vec<f64>; // TODO: &[f64]
+
+    /// Describes how the specimen behaved during the episode
+    ///
+    /// `easyneural` will call this function once the specimen is dead.
+    /// Each element is a single behaviour dimension (e.g. average speed
+    /// or distance travelled) used by quality-diversity trainers such as
+    /// [`MapElites`](../map_elites/struct.MapElites.html) to tell
+    /// different strategies apart. Worlds that don't care may rely on
+    /// the default, empty descriptor.
+    fn get_behaviour_descriptor(&self) -> Vec<f64> {
+        vec![]
+    }
+}
+
+/// World rewarding the specimen for the first output close to `0.8`,
+/// shared by the tests of the trainers.
+#[cfg(test)]
+pub(crate) struct TargetWorld;
+
+#[cfg(test)]
+impl SimulatingWorld for TargetWorld {
+    fn new() -> TargetWorld {
+        TargetWorld {}
+    }
+
+    fn tick(&mut self, input: &[f64]) -> SimulationStatus {
+        SimulationStatus {
+            specimen_status: crate::specimen::SpecimenStatus::DEAD(-(input[0] - 0.8).powi(2)),
+            current_tick: 1,
+        }
+    }
+
+    fn get_world_state(&self) -> Vec<f64> {
+        vec![0.5, -0.5]
+    }
+}
+
+/// Checks that the trainer returned its best specimen first
+/// and that it got within `tolerance` of the target of the [`TargetWorld`].
+#[cfg(test)]
+pub(crate) fn assert_reaches_target(best: &[crate::Specimen; 2], tolerance: f64) {
+    assert!(best[0].fitness >= best[1].fitness);
+    assert!(
+        best[0].fitness > -tolerance,
+        "Fitness {} is too low",
+        best[0].fitness
+    );
 }
@@ -1,12 +1,14 @@
 use std::time::Duration;
 
 use crate::genetic::{crossover, mutate};
-use crate::network::NetworkBuilder;
-use crate::randomizer::RandomProvider;
+use crate::network::{Network, NetworkBuilder, NetworkLayout};
+use crate::randomizer::{shuffle, RandomProvider};
+use crate::regularization::Regularization;
 use crate::simulating_world::SimulatingWorld;
 use crate::specimen::{Specimen, SpecimenStatus};
+use crate::supervised::{Loss, Sample, SupervisedTraining};
 
-const DEFAULT_MUTATION_PROBABILITY: f64 = 0.1;
+pub(crate) const DEFAULT_MUTATION_PROBABILITY: f64 = 0.1;
 
 /// Finish condition for the learning session.
 pub enum Finish {
@@ -17,12 +19,160 @@ pub enum Finish {
     Timeout(Duration),
 }
 
+/// Decides what happens with the weights refined by the [`LocalSearch`](struct.LocalSearch.html).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Inheritance {
+    /// Refined weights replace the original ones in the population,
+    /// so the offspring inherit what their parents have learned.
+    Lamarckian,
+    /// Refined weights are only used to evaluate the specimen. Population keeps
+    /// the original weights, but selection favours those that learn well.
+    Baldwinian,
+}
+
+/// Gradient based refinement applied to every specimen before it is evaluated
+/// in the world, turning the genetic algorithm into a memetic one.
+///
+/// Use it when the task provides a differentiable surrogate of the fitness,
+/// expressed as labelled `samples` (e.g. recorded expert decisions).
+#[derive(Clone, Debug)]
+pub struct LocalSearch {
+    pub inheritance: Inheritance,
+    /// Number of gradient steps, each one over all samples.
+    pub steps: usize,
+    pub learning_rate: f64,
+    pub loss: Loss,
+    pub samples: Vec<Sample>,
+}
+
+impl LocalSearch {
+    fn refine(&self, specimen: &Specimen) -> Result<NetworkLayout, String> {
+        let mut training = SupervisedTraining::from_specimen(&crate::Specimen {
+            brain: specimen.brain.layout.clone(),
+            fitness: specimen.fitness,
+        })
+        .with_loss(self.loss)
+        .with_learning_rate(self.learning_rate)
+        .with_batch_size(self.samples.len());
+        training.train(self.steps, &self.samples, &[])?;
+        Ok(training.get_specimen().brain)
+    }
+}
+
+/// Fitness source that scores the specimen by their loss on labelled samples
+/// instead of letting them play in the world.
+///
+/// Fitness is the negated mean loss, so a perfect network scores `0.0`.
+/// Use it together with [`DatasetWorld`](../dataset/struct.DatasetWorld.html)
+/// to evolve networks for plain input to output mappings.
+#[derive(Clone, Debug)]
+pub struct DatasetFitness {
+    pub loss: Loss,
+    pub samples: Vec<Sample>,
+    /// Number of samples drawn at random in each generation. All specimen
+    /// of the generation are scored on the same batch. Uses all samples if `None`.
+    pub batch_size: Option<usize>,
+}
+
+impl DatasetFitness {
+    /// Checks that there are samples to draw from and that they fit the network.
+    fn validate(&self, layout: &NetworkLayout) -> Result<(), String> {
+        if self.samples.is_empty() {
+            return Err("No samples provided".to_string());
+        }
+        if self.batch_size == Some(0) {
+            return Err("Batch size must be positive".to_string());
+        }
+        let inputs = layout.get_number_of_inputs();
+        let outputs = layout.layers.last().map_or(0, |layer| layer.len());
+        match self
+            .samples
+            .iter()
+            .find(|sample| sample.input.len() != inputs || sample.target.len() != outputs)
+        {
+            Some(sample) => Err(format!(
+                "Sample does not match the network, expected {} inputs and {} targets: {:?}",
+                inputs, outputs, sample
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns indices of the samples used in the next generation.
+    ///
+    /// Samples are shuffled with the randomizer, so that the batches
+    /// can be reproduced with a seeded one.
+    fn draw_batch(&self, randomizer: &mut dyn RandomProvider) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..self.samples.len()).collect();
+        if let Some(size) = self.batch_size {
+            shuffle(&mut indices, randomizer);
+            indices.truncate(size);
+        }
+        indices
+    }
+
+    fn get_fitness(&self, network: &mut Network, batch: &[usize]) -> f64 {
+        -batch
+            .iter()
+            .map(|index| {
+                let sample = &self.samples[*index];
+                network.reset_state();
+                network.fire(&sample.input);
+                self.loss.value(&network.get_output(), &sample.target)
+            })
+            .sum::<f64>()
+            / batch.len().max(1) as f64
+    }
+}
+
 /// Represents simulation status.
 pub struct SimulationStatus {
     pub specimen_status: SpecimenStatus,
     pub current_tick: usize,
 }
 
+/// Outcome of a single episode played by a specimen.
+pub(crate) struct Episode {
+    pub(crate) fitness: f64,
+    pub(crate) behaviour: Vec<f64>,
+}
+
+/// Lets the specimen interact with the world until it dies.
+///
+/// State of the recurrent layers is reset before the episode starts.
+/// The randomizer is used by the output heads sampling the actions.
+pub(crate) fn run_episode<T: SimulatingWorld>(
+    world: &mut T,
+    specimen: &mut Specimen,
+    randomizer: &mut dyn RandomProvider,
+) -> Episode {
+    specimen.brain.reset_state();
+    let mut current_state = world.get_world_state();
+    loop {
+        let output = specimen.tick(&current_state);
+        let status = world.tick_action(specimen.brain.layout.get_action(&output, randomizer));
+        if let SpecimenStatus::DEAD(fitness) = status.specimen_status {
+            return Episode {
+                fitness,
+                behaviour: world.get_behaviour_descriptor(),
+            };
+        }
+        current_state = world.get_world_state();
+    }
+}
+
+/// Plays a single episode in a freshly created world, using the specified brain.
+pub(crate) fn evaluate<T: SimulatingWorld>(
+    brain: &NetworkLayout,
+    randomizer: &mut dyn RandomProvider,
+) -> Episode {
+    let mut specimen = Specimen {
+        brain: Network::from_layout(brain.clone()),
+        fitness: 0.0,
+    };
+    run_episode(&mut T::new(), &mut specimen, randomizer)
+}
+
 /// Main struct that handles the learning logic.
 pub struct Simulation<'a, T: SimulatingWorld> {
     pub(crate) population: Vec<Specimen>,
@@ -30,6 +180,9 @@ pub struct Simulation<'a, T: SimulatingWorld> {
     parents: Vec<(usize, f64)>,
     randomizer: Option<&'a mut dyn RandomProvider>,
     mutation_probability: f64,
+    local_search: Option<LocalSearch>,
+    dataset_fitness: Option<DatasetFitness>,
+    regularization: Regularization,
 
     // TODO: Temporary - will be reworked with SimulationStatus
     counter: usize,
@@ -39,24 +192,14 @@ impl<'a, T: SimulatingWorld> Simulation<'a, T> {
     /// Creates new simulation struct.
     ///
     /// `mutation_probability` is represented as float number from range `[0.0, 1.0)`.
+    #[allow(clippy::needless_borrow)]
     pub fn new(
         population_size: usize,
         neurons_per_layer: &[usize],
         randomizer: &'a mut dyn RandomProvider,
         mutation_probability: Option<f64>,
     ) -> Result<Simulation<'a, T>, String> {
-        use crate::MINIMUM_POPULATION_SIZE;
-
-        if population_size < MINIMUM_POPULATION_SIZE {
-            return Err(format!(
-                "Population too small, minimum size={}",
-                MINIMUM_POPULATION_SIZE
-            ));
-        }
-
-        if population_size % 2 != 0 {
-            return Err("Population size must be an even number".to_string());
-        };
+        Self::check_population_size(population_size)?;
 
         Ok(Simulation {
             world: None,
@@ -75,10 +218,94 @@ impl<'a, T: SimulatingWorld> Simulation<'a, T> {
             parents: vec![],
             randomizer: Some(randomizer),
             mutation_probability: mutation_probability.unwrap_or(DEFAULT_MUTATION_PROBABILITY),
+            local_search: None,
+            dataset_fitness: None,
+            regularization: Regularization::default(),
             counter: 0,
         })
     }
 
+    /// Creates new simulation whose population shares the structure of the
+    /// specified layout, e.g. a network with recurrent layers.
+    ///
+    /// All weights of the template, including the gates, are replaced with
+    /// new random numbers for each specimen.
+    pub fn from_template(
+        population_size: usize,
+        template: &NetworkLayout,
+        randomizer: &'a mut dyn RandomProvider,
+        mutation_probability: Option<f64>,
+    ) -> Result<Simulation<'a, T>, String> {
+        Self::check_population_size(population_size)?;
+
+        Ok(Simulation {
+            world: None,
+            population: (0..population_size)
+                .map(|_| {
+                    let mut layout = template.clone();
+                    layout.randomize(randomizer);
+                    Specimen {
+                        brain: Network::from_layout(layout),
+                        fitness: 0.0,
+                    }
+                })
+                .collect(),
+            parents: vec![],
+            randomizer: Some(randomizer),
+            mutation_probability: mutation_probability.unwrap_or(DEFAULT_MUTATION_PROBABILITY),
+            local_search: None,
+            dataset_fitness: None,
+            regularization: Regularization::default(),
+            counter: 0,
+        })
+    }
+
+    fn check_population_size(population_size: usize) -> Result<(), String> {
+        use crate::MINIMUM_POPULATION_SIZE;
+
+        if population_size < MINIMUM_POPULATION_SIZE {
+            return Err(format!(
+                "Population too small, minimum size={}",
+                MINIMUM_POPULATION_SIZE
+            ));
+        }
+
+        if population_size % 2 != 0 {
+            return Err("Population size must be an even number".to_string());
+        };
+
+        Ok(())
+    }
+
+    /// Refines each specimen with a few gradient steps before
+    /// it is evaluated in the world.
+    pub fn with_local_search(mut self, local_search: LocalSearch) -> Self {
+        self.local_search = Some(local_search);
+        self
+    }
+
+    /// Scores the specimen on labelled samples instead of playing
+    /// episodes in the world.
+    ///
+    /// Fails if there are no samples, the batch size is zero or the samples
+    /// do not match the input and output layers of the population.
+    pub fn with_dataset_fitness(mut self, dataset_fitness: DatasetFitness) -> Result<Self, String> {
+        if let Some(specimen) = self.population.first() {
+            dataset_fitness.validate(&specimen.brain.layout)?;
+        }
+        self.dataset_fitness = Some(dataset_fitness);
+        Ok(self)
+    }
+
+    /// Subtracts weight penalties from the fitness of each specimen and
+    /// constrains the weights of the offspring after the mutation.
+    pub fn with_regularization(mut self, regularization: Regularization) -> Result<Self, String> {
+        regularization.validate()?;
+        self.regularization = regularization;
+        Ok(self)
+    }
+
+    #[allow(clippy::needless_borrow)]
     pub(crate) fn evolve_population(&mut self, parents: &[crate::Specimen; 2]) {
         self.parents.clear();
         for i in 0..self.population.len() / 2 {
@@ -90,11 +317,13 @@ impl<'a, T: SimulatingWorld> Simulation<'a, T> {
         }
     }
 
+    #[allow(clippy::needless_borrow)]
     pub(crate) fn evolve(&mut self, parents: &[crate::Specimen; 2]) -> [crate::Specimen; 2] {
         mutate(
             crossover(&parents),
             self.randomizer.as_deref_mut().unwrap(),
             self.mutation_probability,
+            &self.regularization,
         )
     }
 
@@ -160,23 +389,60 @@ impl<'a, T: SimulatingWorld> Simulation<'a, T> {
     }
 
     fn simulate(&mut self) -> Result<[crate::Specimen; 2], String> {
-        let mut status;
+        if self.dataset_fitness.is_none() {
+            let inputs = self.population[0].brain.layout.get_number_of_inputs();
+            let state = self.world.insert(T::new()).get_world_state().len();
+            if state != inputs {
+                return Err(format!(
+                    "World state has {} values, but the network expects {} inputs. Worlds without a state, like `DatasetWorld`, need `with_dataset_fitness`",
+                    state, inputs
+                ));
+            }
+        }
+        let batch = match (&self.dataset_fitness, self.randomizer.as_deref_mut()) {
+            (Some(dataset_fitness), Some(randomizer)) => {
+                Some(dataset_fitness.draw_batch(randomizer))
+            }
+            _ => None,
+        };
         for specimen_index in 0..self.population.len() {
             let specimen = &mut self.population[specimen_index];
-            self.world = Some(T::new());
-            if let Some(world) = &mut self.world {
-                let mut current_state = world.get_world_state();
-                loop {
-                    let output = specimen.tick(&current_state);
-                    status = world.tick(&output);
-                    if let SpecimenStatus::DEAD(fitness) = status.specimen_status {
-                        specimen.fitness = fitness;
-                        self.add_parent_candidate(specimen_index);
-                        break;
+            let mut learned = None;
+            if let Some(local_search) = &self.local_search {
+                let refined = local_search.refine(specimen)?;
+                match local_search.inheritance {
+                    Inheritance::Lamarckian => specimen.brain.layout = refined,
+                    Inheritance::Baldwinian => {
+                        learned = Some(Specimen {
+                            brain: Network::from_layout(refined),
+                            fitness: 0.0,
+                        })
                     }
-                    current_state = world.get_world_state();
                 }
             }
+
+            let fitness = {
+                let scored = match learned.as_mut() {
+                    Some(learned) => learned,
+                    None => &mut *specimen,
+                };
+                let fitness = match (&self.dataset_fitness, &batch) {
+                    (Some(dataset_fitness), Some(batch)) => {
+                        dataset_fitness.get_fitness(&mut scored.brain, batch)
+                    }
+                    _ => {
+                        run_episode(
+                            self.world.insert(T::new()),
+                            scored,
+                            self.randomizer.as_deref_mut().unwrap(),
+                        )
+                        .fitness
+                    }
+                };
+                fitness - self.regularization.penalty(&scored.brain.layout)
+            };
+            specimen.fitness = fitness;
+            self.add_parent_candidate(specimen_index);
         }
 
         self.parents
@@ -197,11 +463,16 @@ impl<'a, T: SimulatingWorld> Simulation<'a, T> {
 
 #[cfg(test)]
 mod tests {
+    use crate::dataset::DatasetWorld;
     use crate::network::NetworkLayout;
     use crate::randomizer::{DefaultRandomizer, RandomProvider};
+    use crate::simulating_world::TargetWorld;
     use crate::simulation::{
-        Finish, SimulatingWorld, Simulation, SimulationStatus, SpecimenStatus,
+        evaluate, DatasetFitness, Finish, Inheritance, LocalSearch, SimulatingWorld, Simulation,
+        SimulationStatus, SpecimenStatus,
     };
+    use crate::supervised::{Loss, Sample};
+    use crate::training_ground::Exercise;
     use crate::MINIMUM_POPULATION_SIZE;
     use if_chain::if_chain;
 
@@ -227,6 +498,19 @@ mod tests {
         }
     }
 
+    fn target_local_search(inheritance: Inheritance) -> LocalSearch {
+        LocalSearch {
+            inheritance,
+            steps: 50,
+            learning_rate: 0.5,
+            loss: Loss::MeanSquaredError,
+            samples: vec![Sample {
+                input: TargetWorld::new().get_world_state(),
+                target: vec![0.8],
+            }],
+        }
+    }
+
     fn prepare_simulation<'a>(
         population_size: usize,
         randomizer: &'a mut dyn RandomProvider,
@@ -244,6 +528,7 @@ mod tests {
         None
     }
 
+    #[allow(clippy::map_flatten)]
     fn get_all_neuron_inputs_sum(network: &NetworkLayout) -> f64 {
         network
             .neurons
@@ -374,4 +659,247 @@ mod tests {
         assert!(simulation.is_selected_as_parent(TEST_BEST_POP));
         assert!(simulation.is_selected_as_parent(TEST_MIDDLE_POP));
     }
+
+    #[test]
+    fn lamarckian_local_search_writes_back_weights() {
+        let mut randomizer = DefaultRandomizer::new();
+        let mut simulation = Simulation::<TargetWorld>::new(4, &[2, 3, 1], &mut randomizer, None)
+            .unwrap()
+            .with_local_search(target_local_search(Inheritance::Lamarckian));
+        let original: Vec<NetworkLayout> = simulation
+            .population
+            .iter()
+            .map(|specimen| specimen.brain.layout.clone())
+            .collect();
+        simulation.simulate().unwrap();
+
+        simulation
+            .population
+            .iter()
+            .zip(original.iter())
+            .for_each(|(specimen, original)| {
+                assert_ne!(specimen.brain.layout.get_weights(), original.get_weights());
+                assert!(relative_eq!(
+                    specimen.fitness,
+                    evaluate::<TargetWorld>(&specimen.brain.layout, &mut DefaultRandomizer::new())
+                        .fitness
+                ));
+                assert!(
+                    specimen.fitness
+                        >= evaluate::<TargetWorld>(original, &mut DefaultRandomizer::new()).fitness
+                );
+            });
+    }
+
+    #[test]
+    fn baldwinian_local_search_keeps_weights() {
+        let mut randomizer = DefaultRandomizer::new();
+        let mut simulation = Simulation::<TargetWorld>::new(4, &[2, 3, 1], &mut randomizer, None)
+            .unwrap()
+            .with_local_search(target_local_search(Inheritance::Baldwinian));
+        let original: Vec<NetworkLayout> = simulation
+            .population
+            .iter()
+            .map(|specimen| specimen.brain.layout.clone())
+            .collect();
+        simulation.simulate().unwrap();
+
+        simulation
+            .population
+            .iter()
+            .zip(original.iter())
+            .for_each(|(specimen, original)| {
+                assert_eq!(specimen.brain.layout.get_weights(), original.get_weights());
+                assert!(
+                    specimen.fitness
+                        >= evaluate::<TargetWorld>(original, &mut DefaultRandomizer::new()).fitness
+                );
+            });
+    }
+
+    #[test]
+    fn dataset_fitness_replaces_world() {
+        let samples: Vec<Sample> = [[0.0, 0.0, 0.1], [0.0, 1.0, 0.9], [1.0, 0.0, 0.9]]
+            .iter()
+            .map(|row| Sample {
+                input: row[..2].to_vec(),
+                target: vec![row[2]],
+            })
+            .collect();
+        let mut randomizer = DefaultRandomizer::new();
+        let mut simulation = Simulation::<DatasetWorld>::new(10, &[2, 2, 1], &mut randomizer, None)
+            .unwrap()
+            .with_dataset_fitness(DatasetFitness {
+                loss: Loss::MeanSquaredError,
+                samples: samples.clone(),
+                batch_size: None,
+            })
+            .unwrap();
+        let best = simulation.run(Finish::Occurences(5)).unwrap();
+
+        let expected = -Exercise::new(&best[0])
+            .evaluate_regression(&samples)
+            .unwrap()
+            .mean_squared_error;
+        assert!(relative_eq!(best[0].fitness, expected));
+        assert!(best[0].fitness >= best[1].fitness);
+
+        let mut randomizer = DefaultRandomizer::new();
+        let mut simulation =
+            Simulation::<DatasetWorld>::new(4, &[2, 1], &mut randomizer, None).unwrap();
+        assert!(simulation.run(Finish::Occurences(1)).is_err());
+    }
+
+    #[test]
+    fn dataset_fitness_mini_batch() {
+        struct Countdown(f64);
+        impl RandomProvider for Countdown {
+            fn get_number(&mut self) -> f64 {
+                self.0 -= 1.0;
+                self.0
+            }
+        }
+
+        let mut dataset_fitness = DatasetFitness {
+            loss: Loss::MeanSquaredError,
+            samples: (0..10)
+                .map(|i| Sample {
+                    input: vec![i as f64],
+                    target: vec![0.0],
+                })
+                .collect(),
+            batch_size: Some(3),
+        };
+        let batch = dataset_fitness.draw_batch(&mut DefaultRandomizer::new());
+        assert_eq!(batch.len(), 3);
+        assert!(batch[0] != batch[1] && batch[1] != batch[2] && batch[0] != batch[2]);
+        assert_eq!(
+            dataset_fitness.draw_batch(&mut Countdown(0.0)),
+            vec![9, 8, 7]
+        );
+
+        let accepts = |dataset_fitness: &DatasetFitness| {
+            let mut randomizer = DefaultRandomizer::new();
+            Simulation::<DatasetWorld>::new(4, &[1, 1], &mut randomizer, None)
+                .unwrap()
+                .with_dataset_fitness(dataset_fitness.clone())
+                .is_ok()
+        };
+        assert!(accepts(&dataset_fitness));
+        dataset_fitness.batch_size = Some(0);
+        assert!(!accepts(&dataset_fitness));
+        dataset_fitness.batch_size = None;
+        dataset_fitness.samples[4].input.push(1.0);
+        assert!(!accepts(&dataset_fitness));
+        dataset_fitness.samples.clear();
+        assert!(!accepts(&dataset_fitness));
+    }
+
+    #[test]
+    fn recurrent_template_is_reset_between_episodes() {
+        use crate::network::{LayerKind, NetworkBuilder};
+
+        let mut randomizer = DefaultRandomizer::new();
+        let template = NetworkBuilder::new()
+            .with_neurons_per_layer(&[2, 3, 1])
+            .with_randomizer(&mut randomizer)
+            .with_layer_kind(1, LayerKind::Lstm)
+            .build()
+            .get_layout()
+            .clone();
+        let mut simulation =
+            Simulation::<TargetWorld>::from_template(4, &template, &mut randomizer, None).unwrap();
+        assert!(simulation.population[0].brain.layout.get_weights() != template.get_weights());
+
+        let best = simulation.run(Finish::Occurences(3)).unwrap();
+        assert!(relative_eq!(
+            best[0].fitness,
+            evaluate::<TargetWorld>(&best[0].brain, &mut DefaultRandomizer::new()).fitness
+        ));
+    }
+
+    #[test]
+    fn specimens_are_scored_in_evaluation_mode() {
+        use crate::network::{LayerKind, NetworkBuilder};
+
+        let mut randomizer = DefaultRandomizer::new();
+        let template = NetworkBuilder::new()
+            .with_neurons_per_layer(&[2, 3, 0, 0, 1])
+            .with_randomizer(&mut randomizer)
+            .with_layer_kind(2, LayerKind::Dropout { rate: 0.5 })
+            .with_layer_kind(3, LayerKind::BatchNorm { momentum: 0.9 })
+            .build()
+            .get_layout()
+            .clone();
+        let mut simulation =
+            Simulation::<TargetWorld>::from_template(4, &template, &mut randomizer, None).unwrap();
+        simulation.simulate().unwrap();
+        for specimen in &simulation.population {
+            assert_eq!(specimen.brain.layout.statistics, template.statistics);
+            assert!(relative_eq!(
+                specimen.fitness,
+                evaluate::<TargetWorld>(&specimen.brain.layout, &mut DefaultRandomizer::new())
+                    .fitness
+            ));
+        }
+    }
+
+    #[test]
+    fn worlds_receive_typed_actions() {
+        use crate::action::{Action, OutputHead};
+        use crate::network::NetworkBuilder;
+
+        struct ChoiceWorld;
+        impl SimulatingWorld for ChoiceWorld {
+            fn new() -> ChoiceWorld {
+                ChoiceWorld {}
+            }
+            fn tick(&mut self, _: &[f64]) -> SimulationStatus {
+                unreachable!("Typed action expected")
+            }
+            fn tick_action(&mut self, action: Action) -> SimulationStatus {
+                let choice = action.as_discrete().expect("Discrete action expected");
+                SimulationStatus {
+                    specimen_status: SpecimenStatus::DEAD(choice as f64),
+                    current_tick: 0,
+                }
+            }
+            fn get_world_state(&self) -> Vec<f64> {
+                vec![1.0, -1.0]
+            }
+        }
+
+        let mut randomizer = DefaultRandomizer::new();
+        let template = NetworkBuilder::new()
+            .with_neurons_per_layer(&[2, 3])
+            .with_randomizer(&mut randomizer)
+            .with_output_head(OutputHead::Argmax)
+            .build()
+            .get_layout()
+            .clone();
+        let mut simulation =
+            Simulation::<ChoiceWorld>::from_template(8, &template, &mut randomizer, None).unwrap();
+        let best = simulation.run(Finish::Occurences(3)).unwrap();
+        assert!([0.0, 1.0, 2.0].contains(&best[0].fitness));
+    }
+
+    #[test]
+    fn grown_parents_resume_training() {
+        let mut randomizer = DefaultRandomizer::new();
+        let mut simulation =
+            Simulation::<TargetWorld>::new(4, &[2, 3, 1], &mut randomizer, None).unwrap();
+        let mut parents = simulation.run(Finish::Occurences(1)).unwrap();
+        let mut surgeon = DefaultRandomizer::new();
+        for parent in parents.iter_mut() {
+            parent.brain.add_neuron(1, &mut surgeon).unwrap();
+        }
+
+        simulation
+            .run_with_parents(Finish::Occurences(2), parents)
+            .unwrap();
+        assert!(simulation
+            .population
+            .iter()
+            .all(|specimen| specimen.brain.layout.layers[1].len() == 5));
+    }
 }
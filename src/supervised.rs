@@ -0,0 +1,518 @@
+use serde::{Deserialize, Serialize};
+
+use crate::network::{Activation, Network, NetworkBuilder};
+use crate::optimizer::{Checkpoint, Optimizer, Sgd};
+use crate::randomizer::{shuffle, DefaultRandomizer, RandomProvider};
+use crate::regularization::Regularization;
+
+const DEFAULT_LEARNING_RATE: f64 = 0.1;
+const DEFAULT_BATCH_SIZE: usize = 16;
+const LOG_EPSILON: f64 = 1e-12;
+
+/// Single labelled example.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub input: Vec<f64>,
+    pub target: Vec<f64>,
+}
+
+/// Loss function minimized during the supervised training.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Loss {
+    /// Mean of the squared differences between outputs and targets.
+    MeanSquaredError,
+    /// Binary cross-entropy, averaged over outputs. Each output is treated as
+    /// an independent probability, so targets must be from range `[0.0, 1.0]`.
+    CrossEntropy,
+}
+
+impl Loss {
+    /// Calculates the loss of a single sample.
+    pub fn value(self, output: &[f64], target: &[f64]) -> f64 {
+        let count = output.len() as f64;
+        match self {
+            Loss::MeanSquaredError => {
+                output
+                    .iter()
+                    .zip(target.iter())
+                    .map(|(y, t)| (y - t).powi(2))
+                    .sum::<f64>()
+                    / count
+            }
+            Loss::CrossEntropy => {
+                -output
+                    .iter()
+                    .zip(target.iter())
+                    .map(|(y, t)| {
+                        let y = y.clamp(LOG_EPSILON, 1.0 - LOG_EPSILON);
+                        t * y.ln() + (1.0 - t) * (1.0 - y).ln()
+                    })
+                    .sum::<f64>()
+                    / count
+            }
+        }
+    }
+
+    /// Calculates the derivatives of the loss with respect to the weighted
+    /// sums of the output neurons.
+    pub(crate) fn output_deltas(
+        self,
+        activation: Activation,
+        output: &[f64],
+        target: &[f64],
+    ) -> Vec<f64> {
+        let count = output.len() as f64;
+        output
+            .iter()
+            .zip(target.iter())
+            .map(|(y, t)| match (self, activation) {
+                (Loss::MeanSquaredError, _) => 2.0 * (y - t) / count * activation.derivative(*y),
+                (Loss::CrossEntropy, Activation::Sigmoid) => (y - t) / count,
+                (Loss::CrossEntropy, _) => {
+                    let y_clamped = y.clamp(LOG_EPSILON, 1.0 - LOG_EPSILON);
+                    (y_clamped - t) / (y_clamped * (1.0 - y_clamped)) / count
+                        * activation.derivative(*y)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Losses measured after a single epoch.
+#[derive(Clone, Debug)]
+pub struct EpochReport {
+    pub epoch: usize,
+    pub training_loss: f64,
+    /// Not available when no validation samples were provided.
+    pub validation_loss: Option<f64>,
+}
+
+/// Trains the network with backpropagation on labelled samples.
+///
+/// Weights are updated by the [`Optimizer`](../optimizer/trait.Optimizer.html)
+/// after each mini-batch, plain gradient descent is used by default.
+/// Samples are shuffled at the beginning of every epoch.
+pub struct SupervisedTraining<O: Optimizer = Sgd> {
+    network: Network,
+    loss: Loss,
+    optimizer: O,
+    batch_size: usize,
+    epochs: usize,
+    last_report: Option<EpochReport>,
+    regularization: Regularization,
+    randomizer: Box<dyn RandomProvider>,
+}
+
+impl SupervisedTraining<Sgd> {
+    /// Creates new supervised training session for a fresh, random network.
+    pub fn new(
+        neurons_per_layer: &[usize],
+        randomizer: &mut dyn RandomProvider,
+        activation: Option<Activation>,
+    ) -> Result<SupervisedTraining, String> {
+        if neurons_per_layer.len() < 2 {
+            return Err("Network needs at least the input and the output layer".to_string());
+        }
+        Ok(Self::with_network(
+            NetworkBuilder::new()
+                .with_neurons_per_layer(neurons_per_layer)
+                .with_randomizer(randomizer)
+                .with_activation(activation.unwrap_or_default())
+                .build(),
+        ))
+    }
+
+    /// Creates new supervised training session that continues
+    /// to train the brain of the given specimen.
+    pub fn from_specimen(specimen: &crate::Specimen) -> SupervisedTraining {
+        Self::with_network(Network::from_layout(specimen.brain.clone()))
+    }
+
+    fn with_network(network: Network) -> SupervisedTraining {
+        SupervisedTraining {
+            network,
+            loss: Loss::MeanSquaredError,
+            optimizer: Sgd::new(DEFAULT_LEARNING_RATE),
+            batch_size: DEFAULT_BATCH_SIZE,
+            epochs: 0,
+            last_report: None,
+            regularization: Regularization::default(),
+            randomizer: Box::new(DefaultRandomizer::new()),
+        }
+    }
+
+    /// Sets the learning rate of the default optimizer (`0.1` by default).
+    pub fn with_learning_rate(mut self, learning_rate: f64) -> Self {
+        self.optimizer = Sgd::new(learning_rate);
+        self
+    }
+}
+
+impl<O: Optimizer + Clone> SupervisedTraining<O> {
+    /// Resumes the training from the checkpoint previously obtained
+    /// with [`get_checkpoint`](#method.get_checkpoint).
+    pub fn from_checkpoint(checkpoint: Checkpoint<O>) -> Result<SupervisedTraining<O>, String> {
+        SupervisedTraining::from_specimen(&checkpoint.specimen)
+            .with_optimizer(checkpoint.optimizer)
+            .with_completed_epochs(checkpoint.epoch)
+            .with_loss(checkpoint.loss)
+            .with_batch_size(checkpoint.batch_size)
+            .with_regularization(checkpoint.regularization)
+    }
+
+    /// Captures the network together with the optimizer state and the
+    /// training settings, so that the training can be resumed later.
+    pub fn get_checkpoint(&self) -> Checkpoint<O> {
+        Checkpoint {
+            specimen: self.get_specimen(),
+            optimizer: self.optimizer.clone(),
+            epoch: self.epochs,
+            loss: self.loss,
+            batch_size: self.batch_size,
+            regularization: self.regularization,
+        }
+    }
+}
+
+impl<O: Optimizer> SupervisedTraining<O> {
+    /// Replaces the optimizer used to update the weights.
+    pub fn with_optimizer<P: Optimizer>(self, optimizer: P) -> SupervisedTraining<P> {
+        SupervisedTraining {
+            network: self.network,
+            loss: self.loss,
+            optimizer,
+            batch_size: self.batch_size,
+            epochs: self.epochs,
+            last_report: self.last_report,
+            regularization: self.regularization,
+            randomizer: self.randomizer,
+        }
+    }
+
+    fn with_completed_epochs(mut self, epochs: usize) -> Self {
+        self.epochs = epochs;
+        self
+    }
+
+    /// Selects the loss function (mean squared error by default).
+    pub fn with_loss(mut self, loss: Loss) -> Self {
+        self.loss = loss;
+        self
+    }
+
+    /// Sets the number of samples per mini-batch (`16` by default).
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Adds weight penalties to the loss and constrains the weights
+    /// after each step of the optimizer.
+    ///
+    /// Reported losses do not include the penalties.
+    pub fn with_regularization(mut self, regularization: Regularization) -> Result<Self, String> {
+        regularization.validate()?;
+        self.regularization = regularization;
+        Ok(self)
+    }
+
+    /// Replaces the randomizer that shuffles the samples and drives the dropout,
+    /// so that the training can be reproduced with a seeded one.
+    pub fn with_randomizer(mut self, randomizer: Box<dyn RandomProvider>) -> Self {
+        self.randomizer = randomizer;
+        self
+    }
+
+    /// Trains the network for the specified number of epochs.
+    ///
+    /// Returns the training and validation losses measured after each epoch.
+    pub fn train(
+        &mut self,
+        epochs: usize,
+        training: &[Sample],
+        validation: &[Sample],
+    ) -> Result<Vec<EpochReport>, String> {
+        if training.is_empty() {
+            return Err("No training samples provided".to_string());
+        }
+        if self.network.has_custom_activator() {
+            return Err(
+                "Backpropagation needs a predefined activation, custom activators are not supported"
+                    .to_string(),
+            );
+        }
+        if !self.network.layout.supports_backpropagation() {
+            return Err(
+                "Backpropagation does not support recurrent, convolution and pooling layers"
+                    .to_string(),
+            );
+        }
+        self.check_samples(training)?;
+        self.check_samples(validation)?;
+
+        let mut order: Vec<usize> = (0..training.len()).collect();
+        let mut reports = Vec::with_capacity(epochs);
+        for _ in 0..epochs {
+            shuffle(&mut order, self.randomizer.as_mut());
+            for batch in order.chunks(self.batch_size) {
+                let mut gradient = self.get_gradient(batch.iter().map(|index| &training[*index]));
+                let mut weights = self.network.layout.get_weights();
+                self.regularization
+                    .add_gradient(&self.network.layout, &mut gradient);
+                self.optimizer.step(&mut weights, &gradient);
+                self.network.layout.set_weights(&weights);
+                self.regularization.constrain(&mut self.network.layout);
+            }
+
+            self.epochs += 1;
+            let report = EpochReport {
+                epoch: self.epochs,
+                training_loss: self.get_loss(training),
+                validation_loss: if validation.is_empty() {
+                    None
+                } else {
+                    Some(self.get_loss(validation))
+                },
+            };
+            self.last_report = Some(report.clone());
+            reports.push(report);
+        }
+        Ok(reports)
+    }
+
+    /// Calculates the average loss over the given samples.
+    pub fn get_loss(&mut self, samples: &[Sample]) -> f64 {
+        samples
+            .iter()
+            .map(|sample| {
+                self.network.fire(&sample.input);
+                self.loss.value(&self.network.get_output(), &sample.target)
+            })
+            .sum::<f64>()
+            / samples.len() as f64
+    }
+
+    /// Returns the trained network as specimen, ready to be tested in
+    /// the [`Exercise`](../training_ground/struct.Exercise.html).
+    ///
+    /// Fitness of the specimen is the negated loss from the most recent epoch
+    /// (validation loss if available, training loss otherwise).
+    pub fn get_specimen(&self) -> crate::Specimen {
+        crate::Specimen {
+            brain: self.network.layout.clone(),
+            fitness: self.last_report.as_ref().map_or(0.0, |report| {
+                -report.validation_loss.unwrap_or(report.training_loss)
+            }),
+        }
+    }
+
+    fn check_samples(&self, samples: &[Sample]) -> Result<(), String> {
+        let inputs = self.network.layout.layers[0].len() - 1;
+        let outputs = self.network.layout.layers.last().map_or(0, |l| l.len());
+        match samples
+            .iter()
+            .find(|sample| sample.input.len() != inputs || sample.target.len() != outputs)
+        {
+            Some(sample) => Err(format!(
+                "Sample does not match the network, expected {} inputs and {} targets: {:?}",
+                inputs, outputs, sample
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Calculates the gradient averaged over the batch.
+    pub(crate) fn get_gradient<'s>(&mut self, batch: impl Iterator<Item = &'s Sample>) -> Vec<f64> {
+        let mut gradient = vec![0.0; self.network.layout.get_number_of_weights()];
+        let mut count = 0;
+        for sample in batch {
+            self.network
+                .fire_training(&sample.input, self.randomizer.as_mut());
+            let deltas = self.loss.output_deltas(
+                self.network.layout.activation,
+                &self.network.get_output(),
+                &sample.target,
+            );
+            gradient
+                .iter_mut()
+                .zip(self.network.backpropagate(&deltas).iter())
+                .for_each(|(total, g)| *total += g);
+            count += 1;
+        }
+        gradient.iter_mut().for_each(|g| *g /= count.max(1) as f64);
+        gradient
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{Activation, LayerKind, NetworkBuilder};
+    use crate::optimizer::{Adam, Checkpoint, Schedule};
+    use crate::randomizer::{DefaultRandomizer, RandomProvider};
+    use crate::regularization::Regularization;
+    use crate::supervised::{Loss, Sample, SupervisedTraining};
+    use crate::training_ground::Exercise;
+
+    fn xor() -> Vec<Sample> {
+        [
+            (0.0, 0.0, 0.0),
+            (0.0, 1.0, 1.0),
+            (1.0, 0.0, 1.0),
+            (1.0, 1.0, 0.0),
+        ]
+        .iter()
+        .map(|(a, b, t)| Sample {
+            input: vec![*a, *b],
+            target: vec![*t],
+        })
+        .collect()
+    }
+
+    #[test]
+    fn gradient_matches_finite_differences() {
+        const STEP: f64 = 1e-6;
+        for (loss, activation) in [
+            (Loss::MeanSquaredError, Activation::Sigmoid),
+            (Loss::MeanSquaredError, Activation::Tanh),
+            (Loss::CrossEntropy, Activation::Sigmoid),
+        ]
+        .iter()
+        {
+            let mut randomizer = DefaultRandomizer::new();
+            let mut training =
+                SupervisedTraining::new(&[2, 3, 2, 1], &mut randomizer, Some(*activation))
+                    .unwrap()
+                    .with_loss(*loss);
+            let samples = xor();
+            let gradient = training.get_gradient(samples.iter());
+
+            let weights = training.network.layout.get_weights();
+            for (index, analytic) in gradient.iter().enumerate() {
+                let mut shifted = weights.clone();
+                shifted[index] += STEP;
+                training.network.layout.set_weights(&shifted);
+                let plus = training.get_loss(&samples);
+                shifted[index] -= 2.0 * STEP;
+                training.network.layout.set_weights(&shifted);
+                let minus = training.get_loss(&samples);
+                training.network.layout.set_weights(&weights);
+
+                let numeric = (plus - minus) / (2.0 * STEP);
+                assert!(relative_eq!(*analytic, numeric, epsilon = 1e-6));
+            }
+        }
+    }
+
+    #[test]
+    fn learns_xor() {
+        let mut randomizer = DefaultRandomizer::new();
+        let mut training = SupervisedTraining::new(&[2, 4, 1], &mut randomizer, None)
+            .unwrap()
+            .with_loss(Loss::CrossEntropy)
+            .with_learning_rate(1.0)
+            .with_batch_size(4);
+        let samples = xor();
+        let reports = training.train(3000, &samples, &samples).unwrap();
+        assert_eq!(reports.len(), 3000);
+        assert_eq!(reports.last().unwrap().epoch, 3000);
+        assert!(reports.last().unwrap().validation_loss.unwrap() < reports[0].training_loss);
+
+        let specimen = training.get_specimen();
+        let exercise = Exercise::new(&specimen);
+        samples.iter().for_each(|sample| {
+            let output = exercise.get_output(&sample.input);
+            assert!((output[0] - sample.target[0]).abs() < 0.5);
+        });
+    }
+
+    #[test]
+    fn seeded_training_is_reproducible() {
+        /// Linear congruential generator, reproducible from its seed.
+        struct Lcg(u64);
+        impl RandomProvider for Lcg {
+            fn get_number(&mut self) -> f64 {
+                self.0 = self
+                    .0
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (self.0 >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+            }
+        }
+
+        let mut randomizer = DefaultRandomizer::new();
+        let specimen = crate::Specimen {
+            brain: NetworkBuilder::new()
+                .with_neurons_per_layer(&[2, 4, 0, 1])
+                .with_randomizer(&mut randomizer)
+                .with_layer_kind(2, LayerKind::Dropout { rate: 0.5 })
+                .build()
+                .layout,
+            fitness: 0.0,
+        };
+        let train = || {
+            let mut training = SupervisedTraining::from_specimen(&specimen)
+                .with_batch_size(2)
+                .with_randomizer(Box::new(Lcg(7)));
+            training.train(20, &xor(), &[]).unwrap();
+            training.get_specimen().brain.get_weights()
+        };
+        let weights = train();
+        assert!(weights != specimen.brain.get_weights());
+        assert_eq!(weights, train());
+    }
+
+    #[test]
+    fn mismatched_samples_are_rejected() {
+        let mut randomizer = DefaultRandomizer::new();
+        let mut training = SupervisedTraining::new(&[3, 1], &mut randomizer, None).unwrap();
+        assert!(training.train(1, &xor(), &[]).is_err());
+    }
+
+    #[test]
+    fn custom_activator_is_rejected() {
+        let mut randomizer = DefaultRandomizer::new();
+        let network = NetworkBuilder::new()
+            .with_neurons_per_layer(&[2, 1])
+            .with_randomizer(&mut randomizer)
+            .with_activator(|x| x.max(0.0))
+            .build();
+        let mut training = SupervisedTraining::with_network(network);
+        assert!(training.train(1, &xor(), &[]).is_err());
+    }
+
+    #[test]
+    fn resumes_from_checkpoint() {
+        let mut randomizer = DefaultRandomizer::new();
+        let mut training = SupervisedTraining::new(&[2, 4, 1], &mut randomizer, None)
+            .unwrap()
+            .with_optimizer(Adam::new(0.05).with_schedule(Schedule::Cosine {
+                period: 400,
+                minimum: 0.001,
+            }))
+            .with_loss(Loss::CrossEntropy)
+            .with_batch_size(3)
+            .with_regularization(Regularization {
+                l1: 1e-4,
+                l2: 1e-3,
+                max_norm: Some(4.0),
+            })
+            .unwrap();
+        let samples = xor();
+        training.train(200, &samples, &[]).unwrap();
+
+        let json = training.get_checkpoint().to_json().unwrap();
+        let checkpoint = Checkpoint::<Adam>::from_json(&json).unwrap();
+        assert_eq!(checkpoint.epoch, 200);
+
+        let mut resumed = SupervisedTraining::from_checkpoint(checkpoint).unwrap();
+        assert_eq!(resumed.loss, Loss::CrossEntropy);
+        assert_eq!(resumed.batch_size, 3);
+        assert_eq!(resumed.regularization, training.regularization);
+        assert!(relative_eq!(
+            resumed.get_loss(&samples),
+            training.get_loss(&samples)
+        ));
+        let reports = resumed.train(10, &samples, &[]).unwrap();
+        assert_eq!(reports[0].epoch, 201);
+    }
+}
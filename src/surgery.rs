@@ -0,0 +1,294 @@
+use crate::network::{LayerKind, NetworkLayout, Shape};
+use crate::neuron::Neuron;
+use crate::randomizer::RandomProvider;
+
+/// Scale applied to the numbers of the randomizer for the weights of new neurons.
+const NEW_WEIGHT_SCALE: f64 = 1e-3;
+
+/// Operations growing or shrinking the layers of an already trained network,
+/// e.g. the brain of a [`Specimen`](../struct.Specimen.html).
+///
+/// New neurons receive near-zero input weights and all connections leading
+/// out of them start at zero, so the network behaves exactly as before.
+/// Modified specimen may be passed to
+/// [`Simulation::run_with_parents`](../simulation/struct.Simulation.html#method.run_with_parents)
+/// as they are, as long as both parents are modified the same way.
+///
+/// Only the dense layers, read by dense layers only, can be modified.
+impl NetworkLayout {
+    /// Appends a new neuron to the specified hidden layer.
+    pub fn add_neuron(
+        &mut self,
+        layer_index: usize,
+        randomizer: &mut dyn RandomProvider,
+    ) -> Result<(), String> {
+        self.check_hidden_layer(layer_index)?;
+        let number_of_inputs = self.get_layer_inputs(layer_index).len();
+        let mut neuron = Neuron::new(false, 0, &mut None);
+        neuron.inputs = (0..number_of_inputs)
+            .map(|_| randomizer.get_number() * NEW_WEIGHT_SCALE)
+            .collect();
+        self.grow_layer(layer_index, neuron)
+    }
+
+    /// Removes the neuron at the specified position of a hidden layer,
+    /// together with all its connections.
+    pub fn remove_neuron(&mut self, layer_index: usize, position: usize) -> Result<(), String> {
+        self.check_hidden_layer(layer_index)?;
+        self.shrink_layer(layer_index, position)
+    }
+
+    /// Appends a new input, e.g. a new sensor. The world needs to report
+    /// it as the last element of its state.
+    pub fn add_input(&mut self) -> Result<(), String> {
+        if self.layers.len() < 2 {
+            return Err("Network needs at least the input and the output layer".to_string());
+        }
+        self.grow_layer(0, Neuron::new(false, 0, &mut None))
+    }
+
+    /// Removes the input at the specified position.
+    pub fn remove_input(&mut self, position: usize) -> Result<(), String> {
+        if self.layers.len() < 2 {
+            return Err("Network needs at least the input and the output layer".to_string());
+        }
+        self.shrink_layer(0, position)
+    }
+
+    /// Adds a new dense layer of `width` neurons at the specified index as a
+    /// parallel branch, shifting the following layers.
+    ///
+    /// The new layer reads from the layer before it. The layer that was at
+    /// `layer_index` keeps its sources and additionally reads from the new
+    /// layer with zero weights, so the network behaves exactly as before and
+    /// the training can gradually route the signal through the branch.
+    ///
+    /// For example, adding a branch at index 2 to layers `0 -> 1 -> 2` gives
+    /// `0 -> 1 -> 3` plus `1 -> 2 -> 3`.
+    pub fn add_branch(
+        &mut self,
+        layer_index: usize,
+        width: usize,
+        randomizer: &mut dyn RandomProvider,
+    ) -> Result<(), String> {
+        if layer_index == 0 || layer_index >= self.layers.len() {
+            return Err(format!("Cannot add branch at index {}", layer_index));
+        }
+        if width == 0 {
+            return Err("Layer needs at least one neuron".to_string());
+        }
+        if self.get_layer_kind(layer_index) != LayerKind::Dense {
+            return Err("Only dense layers can read from the new layer".to_string());
+        }
+
+        let shift = |index: usize| {
+            if index >= layer_index {
+                index + 1
+            } else {
+                index
+            }
+        };
+        let mut sources: Vec<Vec<usize>> = (0..self.layers.len())
+            .map(|index| {
+                self.get_layer_sources(index)
+                    .into_iter()
+                    .map(shift)
+                    .collect()
+            })
+            .collect();
+        sources[layer_index].push(layer_index);
+        sources.insert(layer_index, vec![layer_index - 1]);
+
+        // New inputs of the following layer come last and start at zero
+        let number_of_inputs = self.layers[layer_index - 1].len();
+        let position = self.get_layer_inputs(layer_index).len();
+        for neuron_id in &self.layers[layer_index] {
+            let neuron = &mut self.neurons[*neuron_id];
+            if !neuron.bias {
+                (position..=position + width).for_each(|p| neuron.insert_input(p, 0.0));
+            }
+        }
+
+        let first_id = self.layers[layer_index][0];
+        let mut layer = Vec::with_capacity(width + 1);
+        for offset in 0..width {
+            let mut neuron = Neuron::new(false, 0, &mut None);
+            neuron.inputs = (0..number_of_inputs)
+                .map(|_| randomizer.get_number() * NEW_WEIGHT_SCALE)
+                .collect();
+            self.insert_neuron_at(first_id + offset, neuron);
+            layer.push(first_id + offset);
+        }
+        self.insert_neuron_at(first_id + width, Neuron::new(true, 0, &mut None));
+        layer.push(first_id + width);
+        self.layers.insert(layer_index, layer);
+
+        self.sources = sources;
+        if self.layer_kinds.len() >= layer_index {
+            self.layer_kinds.insert(layer_index, LayerKind::Dense);
+        }
+        if !self.shapes.is_empty() {
+            self.shapes.insert(layer_index, Shape::new_1d(1, width));
+        }
+        if !self.parameters.is_empty() {
+            self.parameters.insert(layer_index, vec![]);
+        }
+        if !self.statistics.is_empty() {
+            self.statistics.insert(layer_index, vec![]);
+        }
+        self.validate()
+    }
+
+    fn check_hidden_layer(&self, layer_index: usize) -> Result<(), String> {
+        if layer_index == 0 || layer_index + 1 >= self.layers.len() {
+            return Err(format!("Layer {} is not a hidden layer", layer_index));
+        }
+        Ok(())
+    }
+
+    /// Checks that the layer and all layers reading from it are dense, with plain shapes.
+    fn check_resizable(&self, layer_index: usize) -> Result<Vec<usize>, String> {
+        let consumers: Vec<usize> = (0..self.layers.len())
+            .filter(|index| self.get_layer_sources(*index).contains(&layer_index))
+            .collect();
+        if std::iter::once(&layer_index)
+            .chain(consumers.iter())
+            .any(|index| self.get_layer_kind(*index) != LayerKind::Dense)
+        {
+            return Err("Only dense layers read by dense layers can be resized".to_string());
+        }
+        let shape = self.get_layer_shape(layer_index);
+        if shape.channels != 1 || shape.height != 1 {
+            return Err("Layers arranged into channels or grids cannot be resized".to_string());
+        }
+        Ok(consumers)
+    }
+
+    /// Returns the position of the first input coming from the source layer
+    /// among the inputs of the consumer.
+    fn get_input_offset(&self, consumer: usize, source: usize) -> usize {
+        self.get_layer_sources(consumer)
+            .iter()
+            .take_while(|index| **index != source)
+            .map(|index| self.layers[*index].len())
+            .sum()
+    }
+
+    fn grow_layer(&mut self, layer_index: usize, neuron: Neuron) -> Result<(), String> {
+        let consumers = self.check_resizable(layer_index)?;
+        let position = self.get_layer_shape(layer_index).width;
+        for consumer in consumers {
+            let offset = self.get_input_offset(consumer, layer_index);
+            for neuron_id in &self.layers[consumer] {
+                let reader = &mut self.neurons[*neuron_id];
+                if !reader.bias {
+                    reader.insert_input(offset + position, 0.0);
+                }
+            }
+        }
+
+        // Neurons are kept in the order of the layers
+        let neuron_id = match self.layers[layer_index].get(position) {
+            Some(bias_id) => *bias_id,
+            None => self.layers[layer_index][position - 1] + 1,
+        };
+        self.insert_neuron_at(neuron_id, neuron);
+        self.layers[layer_index].insert(position, neuron_id);
+        if let Some(shape) = self.shapes.get_mut(layer_index) {
+            shape.width += 1;
+        }
+        self.validate()
+    }
+
+    fn shrink_layer(&mut self, layer_index: usize, position: usize) -> Result<(), String> {
+        let consumers = self.check_resizable(layer_index)?;
+        let width = self.get_layer_shape(layer_index).width;
+        if position >= width {
+            return Err(format!(
+                "Layer {} has no neuron at position {}",
+                layer_index, position
+            ));
+        }
+        if width == 1 {
+            return Err("Layer needs at least one neuron".to_string());
+        }
+        for consumer in consumers {
+            let offset = self.get_input_offset(consumer, layer_index);
+            for neuron_id in &self.layers[consumer] {
+                let reader = &mut self.neurons[*neuron_id];
+                if !reader.bias {
+                    reader.remove_input(offset + position);
+                }
+            }
+        }
+
+        let neuron_id = self.layers[layer_index].remove(position);
+        self.neurons.remove(neuron_id);
+        self.layers
+            .iter_mut()
+            .flatten()
+            .filter(|id| **id > neuron_id)
+            .for_each(|id| *id -= 1);
+        if let Some(shape) = self.shapes.get_mut(layer_index) {
+            shape.width -= 1;
+        }
+        self.validate()
+    }
+
+    fn insert_neuron_at(&mut self, neuron_id: usize, neuron: Neuron) {
+        self.layers
+            .iter_mut()
+            .flatten()
+            .filter(|id| **id >= neuron_id)
+            .for_each(|id| *id += 1);
+        self.neurons.insert(neuron_id, neuron);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{Activation, Network, NetworkBuilder};
+    use crate::randomizer::DefaultRandomizer;
+
+    #[test]
+    fn surgery_preserves_behaviour() {
+        let mut randomizer = DefaultRandomizer::new();
+        let layout = NetworkBuilder::new()
+            .with_neurons_per_layer(&[2, 3, 2])
+            .with_randomizer(&mut randomizer)
+            .with_activation(Activation::Tanh)
+            .build()
+            .layout;
+        let output = |layout: &crate::network::NetworkLayout, input: &[f64]| {
+            let mut net = Network::from_layout(layout.clone());
+            net.fire(input);
+            net.get_output()
+        };
+        let expected = output(&layout, &[0.4, -0.8]);
+
+        let mut grown = layout.clone();
+        grown.add_neuron(1, &mut randomizer).unwrap();
+        grown.add_input().unwrap();
+        grown.add_branch(2, 4, &mut randomizer).unwrap();
+        assert_eq!(grown.layers[0].len(), 4);
+        assert_eq!(grown.layers[1].len(), 5);
+        assert_eq!(grown.layers[2].len(), 5);
+        assert_eq!(grown.get_layer_sources(3), vec![1, 2]);
+        assert_eq!(output(&grown, &[0.4, -0.8, 0.9]), expected);
+
+        grown.remove_neuron(1, 3).unwrap();
+        grown.remove_input(2).unwrap();
+        assert_eq!(output(&grown, &[0.4, -0.8]), expected);
+        grown.remove_neuron(2, 0).unwrap();
+        assert_eq!(grown.layers[2].len(), 4);
+
+        let mut shrunk = layout.clone();
+        shrunk.remove_input(0).unwrap();
+        assert_eq!(
+            shrunk.get_number_of_weights(),
+            layout.get_number_of_weights() - 3
+        );
+        assert!(shrunk.remove_input(0).is_err());
+        assert!(shrunk.add_neuron(2, &mut randomizer).is_err());
+    }
+}
@@ -1,5 +1,11 @@
-use crate::network::NetworkBuilder;
-use crate::randomizer::DefaultRandomizer;
+use crate::action::Action;
+use crate::evaluation::{ClassificationReport, RegressionReport};
+use crate::network::Network;
+use crate::randomizer::RandomProvider;
+use crate::supervised::Sample;
+
+/// Outputs of the network paired with the targets of the samples.
+type Predictions = (Vec<Vec<f64>>, Vec<Vec<f64>>);
 
 /// Holds the specimen that is going to be tested.
 pub struct Exercise {
@@ -17,16 +23,145 @@ impl Exercise {
     /// Tests the neural network of a specimen
     /// against the specified input, yielding the output value.
     pub fn get_output(&self, inputs: &[f64]) -> Vec<f64> {
-        let neurons_per_layer = [1];
+        let mut net = Network::from_layout(self.specimen.brain.clone());
+        net.reset_state();
+        net.fire(inputs);
+        net.get_output()
+    }
+
+    /// Tests the neural network of a specimen against the specified input,
+    /// yielding the action chosen by its output head.
+    pub fn get_action(&self, inputs: &[f64], randomizer: &mut dyn RandomProvider) -> Action {
+        self.specimen
+            .brain
+            .get_action(&self.get_output(inputs), randomizer)
+    }
+
+    /// Feeds the inputs to the network one tick after another, like during
+    /// an episode, so recurrent layers keep their state between the ticks.
+    /// Yields the output of each tick.
+    pub fn get_outputs(&self, inputs: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        let mut net = Network::from_layout(self.specimen.brain.clone());
+        net.reset_state();
+        inputs
+            .iter()
+            .map(|input| {
+                net.fire(input);
+                net.get_output()
+            })
+            .collect()
+    }
+
+    /// Runs the network over all samples and reports regression metrics.
+    pub fn evaluate_regression(&self, samples: &[Sample]) -> Result<RegressionReport, String> {
+        let (predictions, targets) = self.predict(samples)?;
+        RegressionReport::from_predictions(&predictions, &targets)
+    }
+
+    /// Runs the network over all samples and reports classification metrics.
+    pub fn evaluate_classification(
+        &self,
+        samples: &[Sample],
+    ) -> Result<ClassificationReport, String> {
+        let (predictions, targets) = self.predict(samples)?;
+        ClassificationReport::from_predictions(&predictions, &targets)
+    }
+
+    /// Fires the network for each sample, checking first
+    /// that the inputs match the input layer.
+    fn predict(&self, samples: &[Sample]) -> Result<Predictions, String> {
+        let inputs = self.specimen.brain.get_number_of_inputs();
+        if let Some(sample) = samples.iter().find(|sample| sample.input.len() != inputs) {
+            return Err(format!(
+                "Sample does not match the network, expected {} inputs: {:?}",
+                inputs, sample
+            ));
+        }
+        let mut net = Network::from_layout(self.specimen.brain.clone());
+        Ok(samples
+            .iter()
+            .map(|sample| {
+                net.reset_state();
+                net.fire(&sample.input);
+                (net.get_output(), sample.target.clone())
+            })
+            .unzip())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::{Activation, NetworkBuilder};
+    use crate::randomizer::DefaultRandomizer;
+    use crate::supervised::Sample;
+    use crate::training_ground::Exercise;
+
+    #[test]
+    fn evaluation_matches_outputs() {
         let mut randomizer = DefaultRandomizer::new();
-        let mut net = NetworkBuilder::new()
-            .with_neurons_per_layer(&neurons_per_layer)
-            .with_randomizer(&mut randomizer)
-            .build();
+        let specimen = crate::Specimen {
+            brain: NetworkBuilder::new()
+                .with_neurons_per_layer(&[2, 1])
+                .with_randomizer(&mut randomizer)
+                .with_activation(Activation::Sigmoid)
+                .build()
+                .layout,
+            fitness: 0.0,
+        };
+        let exercise = Exercise::new(&specimen);
+        let samples: Vec<Sample> = [[0.0, 1.0], [1.0, 0.0], [-1.0, 2.0]]
+            .iter()
+            .map(|input| Sample {
+                input: input.to_vec(),
+                target: vec![0.5],
+            })
+            .collect();
 
-        net.layout = self.specimen.brain.clone();
+        let expected = samples
+            .iter()
+            .map(|sample| (exercise.get_output(&sample.input)[0] - 0.5).powi(2))
+            .sum::<f64>()
+            / 3.0;
+        let regression = exercise.evaluate_regression(&samples).unwrap();
+        assert!(relative_eq!(regression.mean_squared_error, expected));
 
-        net.fire(&inputs);
-        net.get_output()
+        let classification = exercise.evaluate_classification(&samples).unwrap();
+        assert_eq!(classification.confusion_matrix[0], vec![0, 0]);
+        assert_eq!(classification.classes[1].support, 3);
+    }
+
+    #[test]
+    fn mismatched_samples_are_rejected() {
+        let mut randomizer = DefaultRandomizer::new();
+        let specimen = crate::Specimen {
+            brain: NetworkBuilder::new()
+                .with_neurons_per_layer(&[2, 1])
+                .with_randomizer(&mut randomizer)
+                .build()
+                .layout,
+            fitness: 0.0,
+        };
+        let exercise = Exercise::new(&specimen);
+        let sample = |input: &[f64], target: &[f64]| {
+            vec![Sample {
+                input: input.to_vec(),
+                target: target.to_vec(),
+            }]
+        };
+        assert!(exercise
+            .evaluate_regression(&sample(&[1.0], &[0.5]))
+            .is_err());
+        assert!(exercise
+            .evaluate_regression(&sample(&[1.0, 0.0, 2.0], &[0.5]))
+            .is_err());
+        assert!(exercise
+            .evaluate_regression(&sample(&[1.0, 0.0], &[0.5, 0.5]))
+            .is_err());
+        assert!(exercise
+            .evaluate_classification(&sample(&[1.0], &[1.0]))
+            .is_err());
+        assert!(exercise
+            .evaluate_classification(&sample(&[1.0, 0.0], &[]))
+            .is_err());
     }
 }
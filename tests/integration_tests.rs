@@ -26,6 +26,7 @@ impl SimulatingWorld for MyWorld {
         }
     }
 
+    #[allow(clippy::assign_op_pattern, clippy::needless_late_init)]
     fn tick(&mut self, input: &[f64]) -> SimulationStatus {
         self.tick += 1;
 
@@ -52,6 +53,7 @@ impl SimulatingWorld for MyWorld {
 }
 
 #[test]
+#[allow(unused_variables)]
 fn test_run_training_session() {
     const POPULATION_SIZE: usize = 10;
     const SIMULATION_ROUNDS: usize = 1;